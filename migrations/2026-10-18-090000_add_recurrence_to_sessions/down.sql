@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE sessions_occurrences;
+
+ALTER TABLE sessions DROP COLUMN recurrence;
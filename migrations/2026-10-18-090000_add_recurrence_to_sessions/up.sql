@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE sessions ADD COLUMN recurrence TEXT;
+
+CREATE TABLE sessions_occurrences (
+    session_id INT NOT NULL REFERENCES sessions (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    occurrence_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    cancelled BOOLEAN NOT NULL DEFAULT FALSE,
+    session_date TIMESTAMP WITH TIME ZONE,
+    title TEXT,
+    description TEXT,
+    colour TEXT,
+    CONSTRAINT sessions_occurrences_pkey PRIMARY KEY (session_id, occurrence_date)
+)
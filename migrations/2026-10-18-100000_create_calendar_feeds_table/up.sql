@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE calendar_feeds (
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    token TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT calendar_feeds_pkey PRIMARY KEY (user_id)
+)
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE polls (
+    id INT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    dm INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    colour TEXT NOT NULL,
+    group_id INT NOT NULL REFERENCES groups ON UPDATE CASCADE ON DELETE CASCADE,
+    session_id INT REFERENCES sessions ON UPDATE CASCADE ON DELETE SET NULL
+)
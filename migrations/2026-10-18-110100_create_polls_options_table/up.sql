@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE polls_options (
+    id INT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    poll_id INT NOT NULL REFERENCES polls (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    option_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    CONSTRAINT polls_options_poll_id_option_date_key UNIQUE (poll_id, option_date)
+)
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE polls_votes (
+    option_id INT NOT NULL REFERENCES polls_options (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    vote TEXT NOT NULL CHECK (vote IN ('yes', 'maybe', 'no')),
+    CONSTRAINT polls_votes_pkey PRIMARY KEY (option_id, user_id)
+)
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sessions_users
+    DROP COLUMN rsvp,
+    DROP COLUMN rsvp_note;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE sessions_users
+    ADD COLUMN rsvp TEXT NOT NULL DEFAULT 'no_response' CHECK (rsvp IN ('going', 'maybe', 'not_going', 'no_response')),
+    ADD COLUMN rsvp_note TEXT;
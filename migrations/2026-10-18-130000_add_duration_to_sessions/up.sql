@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE sessions ADD COLUMN duration_minutes INT NOT NULL DEFAULT 180 CHECK (duration_minutes > 0);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE sessions_guests ADD COLUMN guest_email TEXT;
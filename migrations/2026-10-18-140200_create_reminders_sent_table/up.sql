@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE reminders_sent (
+    session_id INT NOT NULL REFERENCES sessions (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    session_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    offset_minutes INT NOT NULL,
+    recipient TEXT NOT NULL,
+    sent_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT reminders_sent_pkey PRIMARY KEY (session_id, session_date, offset_minutes, recipient)
+)
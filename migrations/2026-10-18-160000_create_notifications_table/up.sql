@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE notifications (
+    id INT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    actor_id INT REFERENCES users (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    session_id INT REFERENCES sessions (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    group_id INT REFERENCES groups (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    read_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
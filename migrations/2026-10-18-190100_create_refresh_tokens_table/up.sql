@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE refresh_tokens (
+    id INT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    login_session_id INT NOT NULL REFERENCES login_sessions (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
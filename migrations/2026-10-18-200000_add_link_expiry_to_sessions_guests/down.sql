@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sessions_guests
+    DROP COLUMN link_version,
+    DROP COLUMN link_expires_at,
+    DROP COLUMN link_revoked_at,
+    DROP COLUMN link_last_used_at;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE sessions_guests
+    ADD COLUMN link_version INT NOT NULL DEFAULT 1,
+    ADD COLUMN link_expires_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() + INTERVAL '14 days',
+    ADD COLUMN link_revoked_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN link_last_used_at TIMESTAMP WITH TIME ZONE;
+
+-- new guest links always say how long they last
+ALTER TABLE sessions_guests ALTER COLUMN link_expires_at DROP DEFAULT;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sessions_guests
+    DROP COLUMN rsvp,
+    DROP COLUMN rsvp_note,
+    DROP COLUMN upgraded_to,
+    DROP COLUMN upgraded_at;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE sessions_guests
+    ADD COLUMN rsvp TEXT NOT NULL DEFAULT 'no_response' CHECK (rsvp IN ('going', 'maybe', 'not_going', 'no_response')),
+    ADD COLUMN rsvp_note TEXT,
+    ADD COLUMN upgraded_to INT REFERENCES users (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    ADD COLUMN upgraded_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+-- names can now repeat across sessions, so give every repeat after the first a unique suffix
+UPDATE sessions_guests
+SET guest_name = guest_name || ' (' || session_id || '-' || guest_id || ')'
+WHERE (session_id, guest_id) NOT IN (
+    SELECT DISTINCT ON (guest_name) session_id, guest_id
+    FROM sessions_guests
+    ORDER BY guest_name, session_id, guest_id
+);
+
+ALTER TABLE sessions_guests
+    DROP CONSTRAINT sessions_guests_session_id_guest_name_key,
+    ADD CONSTRAINT sessions_guests_guest_name_key UNIQUE (guest_name);
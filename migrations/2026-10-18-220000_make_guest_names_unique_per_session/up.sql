@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- guest names were unique across every session, so existing rows never clash per session
+ALTER TABLE sessions_guests
+    DROP CONSTRAINT sessions_guests_guest_name_key,
+    ADD CONSTRAINT sessions_guests_session_id_guest_name_key UNIQUE (session_id, guest_name);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX groups_users_one_owner;
+
+ALTER TABLE groups_users
+    DROP COLUMN role;
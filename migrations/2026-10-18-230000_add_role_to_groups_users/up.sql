@@ -0,0 +1,11 @@
+-- Your SQL goes here
+ALTER TABLE groups_users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'scheduler', 'member'));
+
+-- the group's admin becomes its owner
+UPDATE groups_users
+SET role = 'owner'
+FROM groups
+WHERE groups_users.group_id = groups.id AND groups_users.user_id = groups.admin;
+
+CREATE UNIQUE INDEX groups_users_one_owner ON groups_users (group_id) WHERE role = 'owner';
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX sessions_users_one_dm;
+
+ALTER TABLE sessions_users
+    DROP COLUMN role;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE sessions_users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'player' CHECK (role IN ('dm', 'co_dm', 'player', 'spectator'));
+
+UPDATE sessions_users
+SET role = 'dm'
+FROM sessions
+WHERE sessions_users.session_id = sessions.id AND sessions_users.user_id = sessions.dm;
+
+CREATE UNIQUE INDEX sessions_users_one_dm ON sessions_users (session_id) WHERE role = 'dm';
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE group_invites (
+    id INT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    group_id INT NOT NULL REFERENCES groups (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    code TEXT NOT NULL UNIQUE,
+    created_by INT REFERENCES users (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    max_uses INT CHECK (max_uses > 0),
+    uses INT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX group_invites_group_id ON group_invites (group_id);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- every group could already be found and requested to join, so they start out listed
+ALTER TABLE groups
+    ADD COLUMN privacy TEXT NOT NULL DEFAULT 'listed' CHECK (privacy IN ('public', 'listed', 'private'));
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+-- the tokens cannot be recovered from their hashes, so the feeds have to be created again
+DELETE FROM calendar_feeds;
+ALTER TABLE calendar_feeds RENAME COLUMN token_hash TO token;
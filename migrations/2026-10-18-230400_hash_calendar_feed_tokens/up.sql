@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- existing feeds keep working, as their tokens are hashed the same way as new ones
+UPDATE calendar_feeds SET token = encode(sha256(token::bytea), 'hex');
+ALTER TABLE calendar_feeds RENAME COLUMN token TO token_hash;
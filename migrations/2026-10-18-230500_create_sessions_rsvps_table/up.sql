@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- a member's RSVP for a single occurrence of a recurring session, their RSVP in sessions_users
+-- is used for every other occurrence
+CREATE TABLE sessions_rsvps (
+    session_id INT NOT NULL,
+    user_id INT NOT NULL,
+    occurrence_date TIMESTAMP WITH TIME ZONE NOT NULL,
+    rsvp TEXT NOT NULL CHECK (rsvp IN ('going', 'maybe', 'not_going', 'no_response')),
+    rsvp_note TEXT,
+    CONSTRAINT sessions_rsvps_pkey PRIMARY KEY (session_id, occurrence_date, user_id),
+    FOREIGN KEY (session_id, user_id) REFERENCES sessions_users (session_id, user_id) ON UPDATE CASCADE ON DELETE CASCADE
+)
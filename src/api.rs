@@ -5,8 +5,14 @@ use rocket_contrib::json::JsonValue;
 use std::collections::HashMap;
 use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::group;
-use crate::user;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::group::privacy::GroupPrivacy;
+use crate::group::role::GroupRole;
+use crate::schema::{groups, users};
+use crate::session::recurrence::Recurrence;
+use crate::session::role::SessionRole;
 
 #[derive(Debug)]
 pub struct ApiResponse {
@@ -16,18 +22,26 @@ pub struct ApiResponse {
 
 impl<'r> Responder<'r> for ApiResponse {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
-        Response::build_from(self.json.respond_to(req).unwrap())
+        Response::build_from(self.json.respond_to(req)?)
             .status(self.status)
             .header(ContentType::JSON)
             .ok()
     }
 }
 
-use rocket::Outcome;
+use rocket::{Outcome, State};
 
 use frank_jwt as jwt;
 
 use crate::config;
+use crate::config::AppConfig;
+use crate::database::DnDAgendaDB;
+use crate::error::ApiError;
+use crate::user::login_session::LoginSession;
+
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Auth {
@@ -35,15 +49,17 @@ pub struct Auth {
     pub exp: i64,
     /// user id
     pub id: i32,
+    /// id of the login session the token was issued for, see `LoginSession`
+    pub sid: i32,
 }
 
 impl Auth {
-    pub fn token(&self) -> String {
+    pub fn token(&self, app_config: &AppConfig) -> String {
         let headers = json!({});
         let payload = json!(self);
         jwt::encode(
             headers.0,
-            &config::JWT_SECRET.to_string(),
+            &app_config.jwt_secret,
             &payload,
             jwt::Algorithm::HS256,
         )
@@ -52,28 +68,58 @@ impl Auth {
 }
 
 impl<'a, 'r> FromRequest<'a, 'r> for Auth {
-    type Error = JsonValue;
+    type Error = ApiError;
 
     /// Extract Auth token from the "Authorization" header.
     ///
-    /// Handlers with Auth guard will fail with 503 error.
+    /// Handlers with Auth guard will fail with a 401 error, see `ApiError::Unauthorized`.
     /// Handlers with Option<Auth> will be called with None.
+    /// Handlers with Result<Auth, ApiError> will be given the error to respond with.
     fn from_request(request: &'a Request<'r>) -> request::Outcome<Auth, Self::Error> {
-        if let Some(auth) = extract_auth_from_request(request) {
-            // ie assignment successful
-            Outcome::Success(auth)
+        let app_config = match request.guard::<State<AppConfig>>() {
+            Outcome::Success(app_config) => app_config,
+            _ => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    ApiError::Internal("server is not configured".to_string()),
+                ))
+            }
+        };
+
+        if let Some(auth) = extract_auth_from_request(request, &app_config) {
+            // ie assignment successful, but the user may have logged out since
+            let connection = match request.guard::<DnDAgendaDB>() {
+                Outcome::Success(connection) => connection,
+                _ => {
+                    return Outcome::Failure((
+                        Status::ServiceUnavailable,
+                        ApiError::Internal("database unavailable".to_string()),
+                    ))
+                }
+            };
+
+            match LoginSession::is_active(auth.sid, &connection) {
+                Ok(true) => Outcome::Success(auth),
+                _ => Outcome::Failure((
+                    Status::Unauthorized,
+                    ApiError::Unauthorized("unauthorised".to_string()),
+                )),
+            }
         } else {
-            Outcome::Failure((Status::Unauthorized, json!({"error": "unauthorised"})))
+            Outcome::Failure((
+                Status::Unauthorized,
+                ApiError::Unauthorized("unauthorised".to_string()),
+            ))
         }
     }
 }
 
-fn extract_auth_from_request(request: &Request) -> Option<Auth> {
+fn extract_auth_from_request(request: &Request, app_config: &AppConfig) -> Option<Auth> {
     request
         .headers()
         .get_one("authorization")
         .and_then(extract_token_from_header)
-        .and_then(decode_token)
+        .and_then(|token| decode_token(token, app_config))
 }
 
 fn extract_token_from_header(header: &str) -> Option<&str> {
@@ -86,10 +132,10 @@ fn extract_token_from_header(header: &str) -> Option<&str> {
 
 /// Decode token into `Auth` struct. If any error is encountered, log it
 /// an return None.
-fn decode_token(token: &str) -> Option<Auth> {
+fn decode_token(token: &str, app_config: &AppConfig) -> Option<Auth> {
     jwt::decode(
         token,
-        &config::JWT_SECRET.to_string(),
+        &app_config.jwt_secret,
         jwt::Algorithm::HS256,
         &jwt::ValidationOptions::default(),
     )
@@ -106,32 +152,51 @@ fn decode_token(token: &str) -> Option<Auth> {
     })
 }
 
+/// The signed contents of a guest link.
+///
+/// Links made before guest links expired have neither `exp` nor `version`. They are still
+/// accepted as the guest's first link, and stop working when the `link_expires_at` their guest
+/// was given by the migration passes, or as soon as the DM rotates or revokes the link.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GuestAuth {
+    /// expiration timestamp, missing from links made before guest links expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
     pub session_id: i32,
     pub guest_id: i32,
     pub guest_name: String,
+    /// the guest's `link_version` when the link was made, rotating the link makes old ones stop
+    /// working
+    #[serde(default = "first_guest_link_version")]
+    pub version: i32,
+}
+
+/// the `link_version` every guest started with, see `GuestAuth`
+fn first_guest_link_version() -> i32 {
+    1
 }
 
 impl GuestAuth {
-    pub fn token(&self) -> String {
+    pub fn token(&self, app_config: &AppConfig) -> String {
         let headers = json!({});
         let payload = json!(self);
         jwt::encode(
             headers.0,
-            &config::JWT_SECRET.to_string(),
+            &app_config.jwt_secret,
             &payload,
             jwt::Algorithm::HS256,
         )
         .expect("jwt")
     }
 
-    /// Decode guest token into `GuestAuth` struct. If any error is encountered, log it
-    /// an return None.
-    pub fn decode_guest_token(token: &str) -> Option<GuestAuth> {
+    /// Decode guest token into `GuestAuth` struct. If any error is encountered (including the
+    /// link having expired), log it an return None.
+    pub fn decode_guest_token(token: &str, app_config: &AppConfig) -> Option<GuestAuth> {
+        // the expiry is checked here rather than by `jwt`, which would reject links made before
+        // guest links expired
         jwt::decode(
             token,
-            &config::JWT_SECRET.to_string(),
+            &app_config.jwt_secret,
             jwt::Algorithm::HS256,
             &jwt::ValidationOptions::dangerous(),
         )
@@ -146,9 +211,76 @@ impl GuestAuth {
             eprintln!("Auth decode error: {:?}", err);
             None
         })
+        .filter(|guest_auth| match guest_auth.exp {
+            Some(exp) if exp <= Utc::now().timestamp() => {
+                eprintln!("Auth decode error: guest link expired");
+                false
+            }
+            _ => true,
+        })
     }
 }
 
+/// The signed contents of an email verification link. The link only verifies the address it
+/// was sent to, so it stops working if the user changes their email again.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmailVerification {
+    /// expiration timestamp
+    pub exp: i64,
+    /// not called `id`, so that these tokens can never be decoded as an `Auth`
+    pub user_id: i32,
+    pub email: String,
+}
+
+impl EmailVerification {
+    pub fn token(&self, app_config: &AppConfig) -> String {
+        let headers = json!({});
+        let payload = json!(self);
+        jwt::encode(
+            headers.0,
+            &app_config.jwt_secret,
+            &payload,
+            jwt::Algorithm::HS256,
+        )
+        .expect("jwt")
+    }
+
+    /// Decode verification token into `EmailVerification` struct. If any error is encountered
+    /// (including the link having expired), log it an return None.
+    pub fn decode_token(token: &str, app_config: &AppConfig) -> Option<EmailVerification> {
+        jwt::decode(
+            token,
+            &app_config.jwt_secret,
+            jwt::Algorithm::HS256,
+            &jwt::ValidationOptions::default(),
+        )
+        .map(|(_, payload)| {
+            serde_json::from_value::<EmailVerification>(payload)
+                .map_err(|err| {
+                    eprintln!("Auth serde decode error: {:?}", err);
+                })
+                .ok()
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("Auth decode error: {:?}", err);
+            None
+        })
+    }
+}
+
+/// a random token to hand out in a link, e.g. in a password reset email
+pub fn generate_token(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .collect()
+}
+
+/// tokens are only stored as this hash, so a leaked database does not leak usable links
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
 pub struct FieldValidator {
     errors: ValidationErrors,
 }
@@ -168,7 +300,7 @@ impl FieldValidator {
         }
     }
 
-    pub fn check(self) -> Result<(), ApiResponse> {
+    pub fn check(self) -> Result<(), ApiError> {
         if self.errors.is_empty() {
             Ok(())
         } else {
@@ -177,14 +309,56 @@ impl FieldValidator {
                 .field_errors()
                 .into_iter()
                 .map(|(field, errors)| {
-                    let codes = errors.clone().into_iter().map(|err| err.code).collect();
-                    (field, codes)
+                    let codes = errors.iter().map(|err| err.code.to_string()).collect();
+                    (field.to_string(), codes)
                 })
                 .collect::<HashMap<_, Vec<_>>>();
-            Err(ApiResponse {
-                json: json!({ "errors": errors }),
-                status: Status::UnprocessableEntity,
-            })
+            Err(ApiError::Validation(errors))
+        }
+    }
+
+    /// add an error to each of the given fields whose user does not exist, checking them all in
+    /// one query on the request's connection. Fields that were not given are skipped.
+    pub fn users_exist(
+        &mut self,
+        fields: &[(&'static str, Option<i32>)],
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let found = users::table
+            .filter(users::id.eq_any(given_ids(fields)))
+            .select(users::id)
+            .load::<i32>(connection)?;
+        self.add_missing(fields, &found, "can only be a valid (existing) user");
+        Ok(())
+    }
+
+    /// add an error to each of the given fields whose group does not exist, checking them all in
+    /// one query on the request's connection. Fields that were not given are skipped.
+    pub fn groups_exist(
+        &mut self,
+        fields: &[(&'static str, Option<i32>)],
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let found = groups::table
+            .filter(groups::id.eq_any(given_ids(fields)))
+            .select(groups::id)
+            .load::<i32>(connection)?;
+        self.add_missing(fields, &found, "Group can only be an existing group");
+        Ok(())
+    }
+
+    fn add_missing(
+        &mut self,
+        fields: &[(&'static str, Option<i32>)],
+        found: &[i32],
+        message: &'static str,
+    ) {
+        for (field_name, id) in fields {
+            if let Some(id) = id {
+                if !found.contains(id) {
+                    self.errors.add(*field_name, ValidationError::new(message));
+                }
+            }
         }
     }
 
@@ -221,16 +395,168 @@ pub fn validate_colour(colour: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
-pub fn validate_user_exists(user_id: i32) -> Result<(), ValidationError> {
-    match user::User::find(user_id, &crate::database::establish_connection()) {
-        Ok(_user) => Ok(()),
-        Err(_response) => Err(ValidationError::new("can only be a valid (existing) user")),
+pub fn validate_recurrence(recurrence: &str) -> Result<(), ValidationError> {
+    // an empty recurrence is used to stop a session from recurring
+    if recurrence.is_empty() {
+        return Ok(());
     }
+
+    recurrence
+        .parse::<Recurrence>()
+        .map(|_| ())
+        .map_err(|error| {
+            println!("Invalid recurrence: {}", error);
+            ValidationError::new(
+                "must be a weekly RRULE with optional BYDAY, INTERVAL, COUNT or UNTIL and EXDATE",
+            )
+        })
 }
 
-pub fn validate_group_exists(group_id: i32) -> Result<(), ValidationError> {
-    match group::Group::find(group_id, &crate::database::establish_connection()) {
-        Ok(_group) => Ok(()),
-        Err(_response) => Err(ValidationError::new("Group can only be an existing group")),
+pub fn validate_occurrence_fields(fields: &[String]) -> Result<(), ValidationError> {
+    if !fields.iter().all(|field| {
+        field == "title" || field == "description" || field == "session_date" || field == "colour"
+    }) {
+        return Err(ValidationError::new(
+            "can only reset title, description, session_date, or colour",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_rsvp(rsvp: &str) -> Result<(), ValidationError> {
+    if !(rsvp == "going" || rsvp == "maybe" || rsvp == "not_going" || rsvp == "no_response") {
+        return Err(ValidationError::new(
+            "rsvp can only be going, maybe, not_going, or no_response",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_group_role(role: &str) -> Result<(), ValidationError> {
+    role.parse::<GroupRole>().map(|_| ()).map_err(|error| {
+        println!("Invalid group role: {}", error);
+        ValidationError::new("role can only be owner, admin, scheduler, or member")
+    })
+}
+
+pub fn validate_group_privacy(privacy: &str) -> Result<(), ValidationError> {
+    privacy
+        .parse::<GroupPrivacy>()
+        .map(|_| ())
+        .map_err(|error| {
+            println!("Invalid group privacy: {}", error);
+            ValidationError::new("privacy can only be public, listed, or private")
+        })
+}
+
+pub fn validate_session_role(role: &str) -> Result<(), ValidationError> {
+    role.parse::<SessionRole>().map(|_| ()).map_err(|error| {
+        println!("Invalid session role: {}", error);
+        ValidationError::new("role can only be dm, co_dm, player, or spectator")
+    })
+}
+
+fn given_ids(fields: &[(&'static str, Option<i32>)]) -> Vec<i32> {
+    fields.iter().filter_map(|(_, id)| *id).collect()
+}
+
+pub fn validate_poll_options(options: &[String]) -> Result<(), ValidationError> {
+    if options.is_empty() {
+        return Err(ValidationError::new("must have at least one date"));
+    }
+    if options
+        .iter()
+        .any(|option| option.parse::<DateTime<Utc>>().is_err())
+    {
+        return Err(ValidationError::new(
+            "dates must be valid output of JS toISOString()",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_vote(vote: &str) -> Result<(), ValidationError> {
+    if !(vote == "yes" || vote == "maybe" || vote == "no") {
+        return Err(ValidationError::new("vote can only be yes, maybe, or no"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            jwt_secret: "secret".to_string(),
+            database_url: "postgres://localhost/dnd_agenda".to_string(),
+            mail_transport: "memory".to_string(),
+            mailgun_url: None,
+            mailgun_api_key: None,
+            smtp_host: None,
+            smtp_credentials: None,
+            mail_outbox_dir: "outbox".to_string(),
+            reminder_offsets_minutes: config::DEFAULT_REMINDER_OFFSETS_MINUTES.to_vec(),
+            reminder_interval_seconds: config::DEFAULT_REMINDER_INTERVAL_SECONDS,
+            remind_guests: true,
+            app_url: config::DEFAULT_APP_URL.to_string(),
+        }
+    }
+
+    fn guest_auth(exp: Option<i64>) -> GuestAuth {
+        GuestAuth {
+            exp,
+            session_id: 1,
+            guest_id: 2,
+            guest_name: "guest".to_string(),
+            version: 3,
+        }
+    }
+
+    #[test]
+    fn guest_links_work_until_they_expire() {
+        let app_config = app_config();
+
+        let exp = (Utc::now() + Duration::days(1)).timestamp();
+        let token = guest_auth(Some(exp)).token(&app_config);
+        let decoded = GuestAuth::decode_guest_token(&token, &app_config).expect("link works");
+        assert_eq!(decoded.exp, Some(exp));
+        assert_eq!(decoded.version, 3);
+
+        let exp = (Utc::now() - Duration::minutes(1)).timestamp();
+        let token = guest_auth(Some(exp)).token(&app_config);
+        assert!(GuestAuth::decode_guest_token(&token, &app_config).is_none());
+    }
+
+    #[test]
+    fn links_from_before_guest_links_expired_are_first_links() {
+        let app_config = app_config();
+        let token = jwt::encode(
+            json!({}).0,
+            &app_config.jwt_secret,
+            &json!({ "session_id": 1, "guest_id": 2, "guest_name": "guest" }).0,
+            jwt::Algorithm::HS256,
+        )
+        .expect("jwt");
+
+        let decoded = GuestAuth::decode_guest_token(&token, &app_config).expect("link works");
+        assert_eq!(decoded.exp, None);
+        assert_eq!(decoded.version, 1);
+    }
+
+    #[test]
+    fn guest_links_must_be_signed_by_the_server() {
+        let token = guest_auth(None).token(&app_config());
+        let other_config = AppConfig {
+            jwt_secret: "another secret".to_string(),
+            ..app_config()
+        };
+
+        assert!(GuestAuth::decode_guest_token(&token, &other_config).is_none());
     }
 }
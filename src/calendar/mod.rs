@@ -0,0 +1,375 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::calendar_feeds;
+use diesel::prelude::*;
+
+use crate::session::{Session, SessionOccurrence};
+use crate::user::User;
+
+use chrono::{DateTime, Utc};
+
+pub mod routes;
+
+use crate::api::{generate_token, hash_token};
+use crate::error::ApiError;
+
+/// format of DATE-TIME values in iCalendar (always UTC)
+const ICAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// length of the random token in a calendar feed url
+const FEED_TOKEN_LENGTH: usize = 32;
+
+/// A long-lived, revocable token that lets calendar apps subscribe to a user's sessions,
+/// since they cannot send an Authorization header. Only the hash of the token is stored, so its
+/// url is only known when the feed is created.
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
+#[belongs_to(User)]
+#[primary_key(user_id)]
+#[table_name = "calendar_feeds"]
+pub struct CalendarFeed {
+    pub user_id: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[table_name = "calendar_feeds"]
+#[derive(Serialize, Deserialize, Insertable, AsChangeset)]
+pub struct InsertableCalendarFeed {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CalendarFeed {
+    /// path of the feed with the given token, relative to the api's host
+    pub fn url(token: &str) -> String {
+        format!("/api/v1/calendar/feed/{}.ics", token)
+    }
+
+    pub fn find(user_id: i32, connection: &PgConnection) -> Result<CalendarFeed, ApiError> {
+        calendar_feeds::table
+            .find(user_id)
+            .first::<CalendarFeed>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Calendar feed not found".to_string())
+            })
+    }
+
+    pub fn find_by_token(token: &str, connection: &PgConnection) -> Result<CalendarFeed, ApiError> {
+        calendar_feeds::table
+            .filter(calendar_feeds::token_hash.eq(hash_token(token)))
+            .first::<CalendarFeed>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("not a valid calendar feed".to_string())
+            })
+    }
+
+    /// create the user's feed, or rotate its token (revoking the old url) if it already exists.
+    /// The feed is given along with its token.
+    pub fn create(
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(CalendarFeed, String), ApiError> {
+        let token = generate_token(FEED_TOKEN_LENGTH);
+        let new_calendar_feed = &InsertableCalendarFeed {
+            user_id,
+            token_hash: hash_token(&token),
+            created_at: Utc::now(),
+        };
+
+        diesel::insert_into(calendar_feeds::table)
+            .values(new_calendar_feed)
+            .on_conflict(calendar_feeds::user_id)
+            .do_update()
+            .set(new_calendar_feed)
+            .get_result::<CalendarFeed>(connection)
+            .map(|calendar_feed| (calendar_feed, token))
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not create a calendar feed".to_string())
+            })
+    }
+
+    pub fn revoke(user_id: i32, connection: &PgConnection) -> Result<(), ApiError> {
+        let deleted = diesel::delete(calendar_feeds::table.find(user_id))
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Calendar feed could not be revoked".to_string())
+            })?;
+
+        if deleted == 0 {
+            return Err(ApiError::NotFound("Calendar feed not found".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// render sessions as an iCalendar (RFC 5545) VCALENDAR with one VEVENT per session, each given
+/// along with its edited or cancelled occurrences. A recurring session stays a single VEVENT with
+/// its RRULE: cancelled occurrences are added as EXDATEs, and each edited occurrence is its own
+/// VEVENT overriding the series through its RECURRENCE-ID. Each event links to its session on
+/// the app at `app_url`, see `AppConfig::app_url`.
+pub fn to_ics(app_url: &str, sessions: &[(Session, Vec<SessionOccurrence>)]) -> String {
+    let dtstamp = Utc::now().format(ICAL_DATE_TIME_FORMAT).to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//DnDearAll//dnd_agenda//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:DnDearAll".to_string(),
+    ];
+
+    for (session, overrides) in sessions {
+        let uid = format!("UID:session-{}@dndearall.com", session.id);
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(uid.clone());
+        lines.extend(event_lines(
+            app_url,
+            session,
+            session.session_date,
+            &session.title,
+            &session.description,
+            &dtstamp,
+        ));
+
+        let recurrence = match session.recurrence {
+            Some(ref recurrence) => recurrence,
+            None => {
+                lines.push("END:VEVENT".to_string());
+                continue;
+            }
+        };
+        lines.extend(recurrence.lines().map(String::from));
+        let cancelled = overrides
+            .iter()
+            .filter(|occurrence| occurrence.cancelled)
+            .map(|occurrence| to_ical_date_time(occurrence.occurrence_date))
+            .collect::<Vec<_>>();
+        if !cancelled.is_empty() {
+            lines.push(format!("EXDATE:{}", cancelled.join(",")));
+        }
+        lines.push("END:VEVENT".to_string());
+
+        for edited in overrides.iter().filter(|occurrence| {
+            !occurrence.cancelled
+                && (occurrence.session_date.is_some()
+                    || occurrence.title.is_some()
+                    || occurrence.description.is_some())
+        }) {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(uid.clone());
+            lines.push(format!(
+                "RECURRENCE-ID:{}",
+                to_ical_date_time(edited.occurrence_date)
+            ));
+            lines.extend(event_lines(
+                app_url,
+                session,
+                edited.session_date.unwrap_or(edited.occurrence_date),
+                edited.title.as_ref().unwrap_or(&session.title),
+                edited.description.as_ref().unwrap_or(&session.description),
+                &dtstamp,
+            ));
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+/// the properties of a VEVENT for the instance of `session` starting at `start`
+fn event_lines(
+    app_url: &str,
+    session: &Session,
+    start: DateTime<Utc>,
+    title: &str,
+    description: &str,
+    dtstamp: &str,
+) -> Vec<String> {
+    vec![
+        format!("DTSTAMP:{}", dtstamp),
+        format!("DTSTART:{}", to_ical_date_time(start)),
+        format!("DTEND:{}", to_ical_date_time(session.end_date(start))),
+        format!("SUMMARY:{}", escape_text(title)),
+        format!("DESCRIPTION:{}", escape_text(description)),
+        format!("URL:{}/#/sessions/{}", app_url, session.slug),
+    ]
+}
+
+fn to_ical_date_time(date: DateTime<Utc>) -> String {
+    date.format(ICAL_DATE_TIME_FORMAT).to_string()
+}
+
+/// escape TEXT values as per RFC 5545 section 3.3.11
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// fold lines longer than 75 octets as per RFC 5545 section 3.1
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut line_length = 0;
+    for character in line.chars() {
+        if line_length + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(character);
+        line_length += character.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const APP_URL: &str = "https://dnd.example.com";
+
+    fn session(recurrence: Option<&str>) -> Session {
+        Session {
+            id: 7,
+            slug: "one-shot".to_string(),
+            title: "One, shot".to_string(),
+            description: "Bring dice; snacks".to_string(),
+            dm: 1,
+            session_date: Utc.ymd(2020, 3, 5).and_hms(19, 0, 0),
+            colour: "red".to_string(),
+            image: None,
+            group_id: 1,
+            recurrence: recurrence.map(String::from),
+            duration_minutes: 180,
+        }
+    }
+
+    fn occurrence(day: u32, cancelled: bool, title: Option<&str>) -> SessionOccurrence {
+        SessionOccurrence {
+            session_id: 7,
+            occurrence_date: Utc.ymd(2020, 3, day).and_hms(19, 0, 0),
+            cancelled,
+            session_date: None,
+            title: title.map(String::from),
+            description: None,
+            colour: None,
+        }
+    }
+
+    fn lines(ics: &str) -> Vec<&str> {
+        ics.split("\r\n").collect()
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("a,b;c\\d"), "a\\,b\\;c\\\\d");
+        assert_eq!(escape_text("one\r\ntwo\nthree"), "one\\ntwo\\nthree");
+    }
+
+    #[test]
+    fn test_fold_line() {
+        let short = "a".repeat(75);
+        assert_eq!(fold_line(&short), short);
+
+        let long = "a".repeat(160);
+        let folded = fold_line(&long);
+        let folded_lines = folded.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(folded_lines.len(), 3);
+        assert!(folded_lines.iter().all(|line| line.len() <= 75));
+        assert!(folded_lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(folded.replace("\r\n ", ""), long);
+    }
+
+    #[test]
+    fn test_fold_line_does_not_split_characters() {
+        // 2 octets each, so 37 fit on the first line
+        let long = "é".repeat(40);
+        let folded = fold_line(&long);
+        let folded_lines = folded.split("\r\n").collect::<Vec<_>>();
+
+        assert_eq!(folded_lines[0].len(), 74);
+        assert!(folded_lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), long);
+    }
+
+    #[test]
+    fn test_single_session() {
+        let ics = to_ics(APP_URL, &[(session(None), vec![])]);
+        let lines = lines(&ics);
+
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(
+            lines.iter().filter(|line| **line == "BEGIN:VEVENT").count(),
+            1
+        );
+        assert!(lines.contains(&"UID:session-7@dndearall.com"));
+        assert!(lines.contains(&"DTSTART:20200305T190000Z"));
+        assert!(lines.contains(&"DTEND:20200305T220000Z"));
+        assert!(lines.contains(&"SUMMARY:One\\, shot"));
+        assert!(lines.contains(&"DESCRIPTION:Bring dice\\; snacks"));
+        assert!(lines.contains(&"URL:https://dnd.example.com/#/sessions/one-shot"));
+        assert!(!lines.iter().any(|line| line.starts_with("RRULE")));
+    }
+
+    #[test]
+    fn test_recurring_session_with_overrides() {
+        let mut moved = occurrence(19, false, Some("Finale"));
+        moved.session_date = Some(Utc.ymd(2020, 3, 20).and_hms(18, 0, 0));
+        let ics = to_ics(
+            APP_URL,
+            &[(
+                session(Some("RRULE:FREQ=WEEKLY;COUNT=4")),
+                vec![
+                    occurrence(12, true, None),
+                    moved,
+                    // reset back to the series, so nothing to override
+                    occurrence(26, false, None),
+                ],
+            )],
+        );
+        let lines = lines(&ics);
+
+        assert!(lines.contains(&"RRULE:FREQ=WEEKLY;COUNT=4"));
+        assert!(lines.contains(&"EXDATE:20200312T190000Z"));
+        assert_eq!(
+            lines.iter().filter(|line| **line == "BEGIN:VEVENT").count(),
+            2
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| **line == "UID:session-7@dndearall.com")
+                .count(),
+            2
+        );
+
+        let override_start = lines
+            .iter()
+            .position(|line| *line == "RECURRENCE-ID:20200319T190000Z")
+            .expect("edited occurrence has its own event");
+        let override_lines = &lines[override_start..];
+        assert!(override_lines.contains(&"DTSTART:20200320T180000Z"));
+        assert!(override_lines.contains(&"DTEND:20200320T210000Z"));
+        assert!(override_lines.contains(&"SUMMARY:Finale"));
+        assert!(!lines.iter().any(|line| line.contains("20200326")));
+    }
+}
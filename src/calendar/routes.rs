@@ -0,0 +1,85 @@
+use crate::calendar;
+use crate::database::DnDAgendaDB;
+use crate::session;
+
+use crate::api::ApiResponse;
+use crate::api::Auth;
+use crate::config::AppConfig;
+use crate::error::ApiError;
+use rocket::http::{ContentType, Status};
+use rocket::response::content::Content;
+use rocket::State;
+
+/// the feed's url is not given, as only the hash of its token is stored. Rotate the feed with
+/// `create_feed` to get a new url.
+#[get("/feed")]
+pub fn get_feed(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => calendar::CalendarFeed::find(auth.id, &connection)
+            .map(|calendar_feed| ApiResponse {
+                json: json!({ "calendarFeed": { "createdAt": calendar_feed.created_at } }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// create the feed, or rotate it if it already exists so that the old url stops working
+#[post("/feed")]
+pub fn create_feed(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => calendar::CalendarFeed::create(auth.id, &connection)
+            .map(|(calendar_feed, token)| ApiResponse {
+                json: json!({ "calendarFeed": { "url": calendar::CalendarFeed::url(&token), "createdAt": calendar_feed.created_at } }),
+                status: Status::Created,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[delete("/feed")]
+pub fn revoke_feed(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => calendar::CalendarFeed::revoke(auth.id, &connection)
+            .map(|_| ApiResponse {
+                json: json!({ "message": "calendar feed revoked successfully" }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// the token in the url is the only authentication, as calendar apps cannot send headers
+#[get("/feed/<token>")]
+pub fn get_feed_ics(
+    token: String,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<Content<String>, ApiError> {
+    // calendar apps expect subscription urls to end in .ics
+    let token = token.trim_end_matches(".ics");
+
+    let calendar_feed =
+        calendar::CalendarFeed::find_by_token(token, &connection).map_err(|response| response)?;
+
+    session::Session::read_all(calendar_feed.user_id, &connection)
+        .map(|sessions| {
+            Content(
+                ContentType::new("text", "calendar"),
+                calendar::to_ics(&app_config.app_url, &sessions),
+            )
+        })
+        .map_err(|response| response)
+}
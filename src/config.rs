@@ -1,10 +1,478 @@
+use rocket::Config;
+use rocket_contrib::databases::database_config;
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
 /// js toISOString() in test suit can't handle chrono's default precision
 pub const DATE_FORMAT: &str = "%FT%H:%M:%S%.3f%:z";
 
-pub const JWT_SECRET: &str = dotenv!("JWT_SECRET");
-
 pub const TOKEN_PREFIX: &str = "Token ";
 
 pub const DEFAULT_LIMIT: i64 = 20;
 
-pub const DATABASE_URL: &str = dotenv!("DATABASE_URL");
+/// how far ahead recurring sessions are expanded into occurrences
+pub const RECURRENCE_HORIZON_DAYS: i64 = 180;
+
+/// how long a session lasts when its creator does not say
+pub const DEFAULT_SESSION_DURATION_MINUTES: i32 = 180;
+
+/// how long before a session reminder emails are sent when `REMINDER_OFFSETS_MINUTES` is not
+/// set, i.e. a day and an hour before
+pub const DEFAULT_REMINDER_OFFSETS_MINUTES: &[i32] = &[24 * 60, 60];
+
+/// how often the reminder scheduler looks for reminders that are due, unless
+/// `REMINDER_INTERVAL_SECONDS` is set
+pub const DEFAULT_REMINDER_INTERVAL_SECONDS: u64 = 60;
+
+/// how often the mail worker looks for queued mails to deliver
+pub const MAIL_WORKER_INTERVAL_SECONDS: u64 = 10;
+
+/// how many queued mails the mail worker delivers at a time
+pub const MAIL_BATCH_SIZE: i64 = 50;
+
+/// how long a mail worker has to deliver the mails it claimed, before they can be claimed again
+pub const MAIL_CLAIM_SECONDS: i64 = 5 * 60;
+
+/// how many times delivering a mail is attempted before it is dead-lettered
+pub const MAIL_MAX_ATTEMPTS: i32 = 8;
+
+/// how long to wait before retrying a mail the first time, doubled after every failed attempt
+pub const MAIL_RETRY_BASE_SECONDS: i64 = 30;
+
+/// the longest to wait between two attempts at delivering a mail
+pub const MAIL_RETRY_MAX_SECONDS: i64 = 6 * 60 * 60;
+
+/// how long an emailed password reset link can be used for
+pub const PASSWORD_RESET_EXPIRY_MINUTES: i64 = 60;
+
+/// how long the link in an email verification mail can be used for
+pub const EMAIL_VERIFICATION_EXPIRY_HOURS: i64 = 48;
+
+/// how long an access token works for, before it has to be refreshed
+pub const ACCESS_TOKEN_EXPIRY_MINUTES: i64 = 60;
+
+/// how long a refresh token can be used for, i.e. how long a user can stay away and still be
+/// logged in
+pub const REFRESH_TOKEN_EXPIRY_DAYS: i64 = 30;
+
+/// where the web app is when `APP_URL` is not set, links the API hands out point there
+pub const DEFAULT_APP_URL: &str = "https://dndearall.com";
+
+/// how long a guest link works for when the DM does not say
+pub const DEFAULT_GUEST_LINK_LIFETIME_DAYS: i64 = 14;
+
+/// the longest a DM can make a guest link work for
+pub const MAX_GUEST_LINK_LIFETIME_DAYS: i64 = 90;
+
+/// the ways mail can be sent, see `mail::transport`
+pub const MAIL_TRANSPORTS: &[&str] = &["mailgun", "smtp", "file", "memory"];
+
+/// Settings that differ between deployments, read when the server starts rather than baked
+/// into the binary. Each one is looked up first in the environment (which an optional file can
+/// add to, see `load_env_file`), then in the extras of the active `Rocket.toml` environment.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// `JWT_SECRET` or `jwt_secret`, signs login, guest and email verification tokens
+    pub jwt_secret: String,
+    /// `DATABASE_URL` or `database_url`, falling back on the `dnd_agenda` database Rocket uses
+    pub database_url: String,
+    /// `MAIL_TRANSPORT` or `mail_transport`, one of `MAIL_TRANSPORTS`. Defaults to `mailgun`
+    /// when `MAILGUN_URL` is set and is required otherwise, so that mail is never silently
+    /// kept in memory instead of being sent.
+    pub mail_transport: String,
+    /// `MAILGUN_URL` or `mailgun_url`, only needed to send mails through Mailgun
+    pub mailgun_url: Option<String>,
+    /// `MAILGUN_API_KEY` or `mailgun_api_key`
+    pub mailgun_api_key: Option<String>,
+    /// `SMTP_HOST` or `smtp_host`, only needed to send mails over SMTP
+    pub smtp_host: Option<String>,
+    /// `SMTP_USERNAME` and `SMTP_PASSWORD` (or `smtp_username` and `smtp_password`), for SMTP
+    /// servers that need them
+    pub smtp_credentials: Option<(String, String)>,
+    /// `MAIL_OUTBOX_DIR` or `mail_outbox_dir`, where the `file` transport writes mails. Defaults
+    /// to `outbox`.
+    pub mail_outbox_dir: String,
+    /// `REMINDER_OFFSETS_MINUTES` or `reminder_offsets_minutes`, a comma separated list
+    pub reminder_offsets_minutes: Vec<i32>,
+    /// `REMINDER_INTERVAL_SECONDS` or `reminder_interval_seconds`
+    pub reminder_interval_seconds: u64,
+    /// `REMIND_GUESTS` or `remind_guests`, whether guests who left an email address are sent
+    /// reminders too (unless they turned them off). Defaults to true.
+    pub remind_guests: bool,
+    /// `APP_URL` or `app_url`, without a trailing slash, which every link in mails, invites and
+    /// calendars points to
+    pub app_url: String,
+}
+
+/// Why the configuration could not be loaded
+#[derive(Debug)]
+pub struct ConfigError {
+    /// the environment variables that are needed but were not found anywhere
+    pub missing: Vec<&'static str>,
+    /// problems with the settings that were found, e.g. an unreadable config file
+    pub invalid: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if !self.missing.is_empty() {
+            write!(formatter, "missing config: {}", self.missing.join(", "))?;
+        }
+        if !self.invalid.is_empty() {
+            if !self.missing.is_empty() {
+                write!(formatter, "; ")?;
+            }
+            write!(formatter, "invalid config: {}", self.invalid.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl AppConfig {
+    /// read the config for the given Rocket config, see `load_env_file` for the optional file
+    pub fn load(rocket_config: &Config) -> Result<AppConfig, ConfigError> {
+        AppConfig::from_settings(|key| {
+            env::var(key)
+                .ok()
+                .or_else(|| {
+                    rocket_config
+                        .get_str(&key.to_lowercase())
+                        .ok()
+                        .map(String::from)
+                })
+                .or_else(|| match key {
+                    "DATABASE_URL" => database_config("dnd_agenda", rocket_config)
+                        .ok()
+                        .map(|database| database.url.to_string()),
+                    _ => None,
+                })
+        })
+    }
+
+    /// check and read the settings `lookup` finds, e.g. by their environment variable names
+    fn from_settings<F>(lookup: F) -> Result<AppConfig, ConfigError>
+    where
+        F: Fn(&'static str) -> Option<String>,
+    {
+        let jwt_secret = lookup("JWT_SECRET");
+        let database_url = lookup("DATABASE_URL");
+
+        let mailgun_url = lookup("MAILGUN_URL");
+        let mailgun_api_key = lookup("MAILGUN_API_KEY");
+        let smtp_host = lookup("SMTP_HOST");
+        let smtp_username = lookup("SMTP_USERNAME");
+        let smtp_password = lookup("SMTP_PASSWORD");
+        let mail_outbox_dir = lookup("MAIL_OUTBOX_DIR").unwrap_or_else(|| "outbox".to_string());
+        let mail_transport = lookup("MAIL_TRANSPORT")
+            .or_else(|| mailgun_url.as_ref().map(|_| "mailgun".to_string()));
+
+        let mut invalid = Vec::new();
+        if let Some(ref transport) = mail_transport {
+            if !MAIL_TRANSPORTS.contains(&transport.as_str()) {
+                invalid.push(format!("MAIL_TRANSPORT {} is not known", transport));
+            }
+        }
+        let smtp_credentials = match (smtp_username, smtp_password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => {
+                invalid.push("SMTP_USERNAME and SMTP_PASSWORD must be set together".to_string());
+                None
+            }
+        };
+        let reminder_offsets_minutes = match lookup("REMINDER_OFFSETS_MINUTES") {
+            Some(offsets) => parse_offsets(&offsets).unwrap_or_else(|| {
+                invalid.push(format!(
+                    "REMINDER_OFFSETS_MINUTES {} is not a list of minutes",
+                    offsets
+                ));
+                Vec::new()
+            }),
+            None => DEFAULT_REMINDER_OFFSETS_MINUTES.to_vec(),
+        };
+        let reminder_interval_seconds = parse_setting(
+            "REMINDER_INTERVAL_SECONDS",
+            lookup("REMINDER_INTERVAL_SECONDS"),
+            DEFAULT_REMINDER_INTERVAL_SECONDS,
+            &mut invalid,
+        );
+        let remind_guests =
+            parse_setting("REMIND_GUESTS", lookup("REMIND_GUESTS"), true, &mut invalid);
+        let app_url = lookup("APP_URL")
+            .map(|app_url| app_url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| DEFAULT_APP_URL.to_string());
+        if !app_url.starts_with("https://") && !app_url.starts_with("http://") {
+            invalid.push(format!("APP_URL {} is not an http(s) url", app_url));
+        }
+
+        let mut missing = Vec::new();
+        if jwt_secret.is_none() {
+            missing.push("JWT_SECRET");
+        }
+        if database_url.is_none() {
+            missing.push("DATABASE_URL");
+        }
+        if mail_transport.is_none() {
+            missing.push("MAIL_TRANSPORT");
+        }
+        // Mailgun is used by default whenever its url is set, so the key has to be set with it
+        if mail_transport.as_deref() == Some("mailgun") {
+            if mailgun_url.is_none() {
+                missing.push("MAILGUN_URL");
+            }
+            if mailgun_api_key.is_none() {
+                missing.push("MAILGUN_API_KEY");
+            }
+        }
+        if mail_transport.as_deref() == Some("smtp") && smtp_host.is_none() {
+            missing.push("SMTP_HOST");
+        }
+
+        match (jwt_secret, database_url, mail_transport) {
+            (Some(jwt_secret), Some(database_url), Some(mail_transport))
+                if missing.is_empty() && invalid.is_empty() =>
+            {
+                Ok(AppConfig {
+                    jwt_secret,
+                    database_url,
+                    mail_transport,
+                    mailgun_url,
+                    mailgun_api_key,
+                    smtp_host,
+                    smtp_credentials,
+                    mail_outbox_dir,
+                    reminder_offsets_minutes,
+                    reminder_interval_seconds,
+                    remind_guests,
+                    app_url,
+                })
+            }
+            _ => Err(ConfigError { missing, invalid }),
+        }
+    }
+}
+
+/// parse a setting that was found, noting it as invalid (and using `default`) if it cannot be
+fn parse_setting<T: FromStr>(
+    key: &str,
+    value: Option<String>,
+    default: T,
+    invalid: &mut Vec<String>,
+) -> T {
+    match value {
+        Some(value) => value.trim().parse::<T>().unwrap_or_else(|_| {
+            invalid.push(format!("{} {} could not be read", key, value));
+            default
+        }),
+        None => default,
+    }
+}
+
+/// a comma separated list of positive numbers of minutes, e.g. `1440,60`
+fn parse_offsets(offsets: &str) -> Option<Vec<i32>> {
+    offsets
+        .split(',')
+        .map(|offset| {
+            offset
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|offset| *offset > 0)
+        })
+        .collect()
+}
+
+/// load the file named by `CONFIG_FILE` (or `.env`, if there is one) into the environment,
+/// without overriding variables that are already set. Done before Rocket reads its own config,
+/// so the file can set `ROCKET_` variables too.
+pub fn load_env_file() -> Result<(), ConfigError> {
+    match env::var("CONFIG_FILE") {
+        Ok(path) => dotenv::from_path(&path).map_err(|error| ConfigError {
+            missing: Vec::new(),
+            invalid: vec![format!(
+                "CONFIG_FILE {} could not be read ({})",
+                path, error
+            )],
+        }),
+        Err(_) => {
+            dotenv::dotenv().ok();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_a_comma_separated_list() {
+        assert_eq!(parse_offsets("1440,60"), Some(vec![1440, 60]));
+        assert_eq!(parse_offsets(" 30 , 5 "), Some(vec![30, 5]));
+    }
+
+    #[test]
+    fn offsets_must_be_positive_minutes() {
+        assert_eq!(parse_offsets("60,-5"), None);
+        assert_eq!(parse_offsets("an hour"), None);
+        assert_eq!(parse_offsets(""), None);
+    }
+
+    #[test]
+    fn unreadable_settings_are_invalid() {
+        let mut invalid = Vec::new();
+        assert!(!parse_setting(
+            "REMIND_GUESTS",
+            Some("no".to_string()),
+            false,
+            &mut invalid
+        ));
+        assert_eq!(invalid, vec!["REMIND_GUESTS no could not be read"]);
+
+        let mut invalid = Vec::new();
+        assert_eq!(
+            parse_setting("REMINDER_INTERVAL_SECONDS", None, 60, &mut invalid),
+            60
+        );
+        assert!(parse_setting(
+            "REMIND_GUESTS",
+            Some("true".to_string()),
+            false,
+            &mut invalid
+        ));
+        assert!(invalid.is_empty());
+    }
+
+    /// load the config from just the given settings, as if nothing else was set. A setting given
+    /// twice takes its last value, so tests can override `REQUIRED`.
+    fn load_settings(settings: &[(&'static str, &str)]) -> Result<AppConfig, ConfigError> {
+        AppConfig::from_settings(|key| {
+            settings
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    const REQUIRED: &[(&str, &str)] = &[
+        ("JWT_SECRET", "secret"),
+        ("DATABASE_URL", "postgres://localhost/dnd_agenda"),
+        ("MAIL_TRANSPORT", "memory"),
+    ];
+
+    #[test]
+    fn everything_else_has_a_default() {
+        let app_config = load_settings(REQUIRED).expect("the config loads");
+
+        assert_eq!(app_config.mail_outbox_dir, "outbox");
+        assert_eq!(app_config.smtp_credentials, None);
+        assert_eq!(
+            app_config.reminder_offsets_minutes,
+            DEFAULT_REMINDER_OFFSETS_MINUTES
+        );
+        assert!(app_config.remind_guests);
+        assert_eq!(app_config.app_url, DEFAULT_APP_URL);
+    }
+
+    #[test]
+    fn missing_settings_are_listed() {
+        let error = load_settings(&[]).expect_err("the config does not load");
+
+        assert_eq!(
+            error.missing,
+            vec!["JWT_SECRET", "DATABASE_URL", "MAIL_TRANSPORT"]
+        );
+        assert!(error.invalid.is_empty());
+    }
+
+    #[test]
+    fn transports_need_their_settings() {
+        let mailgun = [
+            REQUIRED,
+            &[("MAIL_TRANSPORT", "mailgun"), ("MAILGUN_API_KEY", "key")],
+        ]
+        .concat();
+        let error = load_settings(&mailgun).expect_err("Mailgun needs a url");
+        assert_eq!(error.missing, vec!["MAILGUN_URL"]);
+
+        // Mailgun is used whenever its url is set, without MAIL_TRANSPORT
+        let mailgun = [
+            ("JWT_SECRET", "secret"),
+            ("DATABASE_URL", "postgres://localhost/dnd_agenda"),
+            ("MAILGUN_URL", "https://mailgun.example.com"),
+        ];
+        let error = load_settings(&mailgun).expect_err("Mailgun needs a key");
+        assert_eq!(error.missing, vec!["MAILGUN_API_KEY"]);
+
+        let smtp = [REQUIRED, &[("MAIL_TRANSPORT", "smtp")]].concat();
+        let error = load_settings(&smtp).expect_err("SMTP needs a host");
+        assert_eq!(error.missing, vec!["SMTP_HOST"]);
+
+        let smtp = [
+            REQUIRED,
+            &[
+                ("MAIL_TRANSPORT", "smtp"),
+                ("SMTP_HOST", "smtp.example.com"),
+                ("SMTP_USERNAME", "dnd_agenda"),
+            ],
+        ]
+        .concat();
+        let error = load_settings(&smtp).expect_err("SMTP needs a password with the username");
+        assert_eq!(
+            error.invalid,
+            vec!["SMTP_USERNAME and SMTP_PASSWORD must be set together"]
+        );
+
+        let smtp = [
+            REQUIRED,
+            &[
+                ("MAIL_TRANSPORT", "smtp"),
+                ("SMTP_HOST", "smtp.example.com"),
+                ("SMTP_USERNAME", "dnd_agenda"),
+                ("SMTP_PASSWORD", "password"),
+            ],
+        ]
+        .concat();
+        let app_config = load_settings(&smtp).expect("the config loads");
+        assert_eq!(app_config.smtp_host, Some("smtp.example.com".to_string()));
+        assert_eq!(
+            app_config.smtp_credentials,
+            Some(("dnd_agenda".to_string(), "password".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_invalid() {
+        let settings = [
+            REQUIRED,
+            &[
+                ("MAIL_TRANSPORT", "pigeon"),
+                ("APP_URL", "dndearall.com"),
+                ("REMINDER_OFFSETS_MINUTES", "an hour"),
+            ],
+        ]
+        .concat();
+        let error = load_settings(&settings).expect_err("the config does not load");
+
+        assert!(error.missing.is_empty());
+        assert_eq!(
+            error.invalid,
+            vec![
+                "MAIL_TRANSPORT pigeon is not known",
+                "REMINDER_OFFSETS_MINUTES an hour is not a list of minutes",
+                "APP_URL dndearall.com is not an http(s) url",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_app_url_has_no_trailing_slash() {
+        let settings = [REQUIRED, &[("APP_URL", "http://localhost:8080/")]].concat();
+        let app_config = load_settings(&settings).expect("the config loads");
+
+        assert_eq!(app_config.app_url, "http://localhost:8080");
+    }
+}
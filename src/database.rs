@@ -5,13 +5,21 @@ use rocket_contrib::databases::diesel::PgConnection;
 #[database("dnd_agenda")]
 pub struct DnDAgendaDB(PgConnection);
 
-use crate::config;
 use crate::config::DEFAULT_LIMIT;
 use diesel::prelude::*;
-
-pub fn establish_connection() -> PgConnection {
-    PgConnection::establish(config::DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", config::DATABASE_URL))
+use diesel::r2d2::{ConnectionManager, Pool};
+
+/// Connections for a background worker, which runs outside of any request and so cannot use
+/// `DnDAgendaDB`. They are kept open between runs, and checked before each one so that a lost
+/// connection is replaced.
+pub type WorkerPool = Pool<ConnectionManager<PgConnection>>;
+
+/// a pool of one connection, opened the first time it is needed
+pub fn worker_pool(database_url: &str) -> WorkerPool {
+    Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .build_unchecked(ConnectionManager::new(database_url))
 }
 
 pub mod functions {
@@ -0,0 +1,173 @@
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket_contrib::json::{JsonError, JsonValue};
+use std::collections::HashMap;
+
+use crate::api::ApiResponse;
+
+/// Every error the API can respond with.
+///
+/// All of them are sent as the same JSON envelope:
+///
+/// ```json
+/// { "code": "not_found", "error": "Session not found" }
+/// ```
+///
+/// `code` is stable and meant for clients to match on, `error` is a human readable message that
+/// may change. `validation_failed` and `schedule_conflict` errors also have an `errors` object,
+/// mapping each invalid field to its messages, and `schedule_conflict` errors list the clashing
+/// sessions in `conflicts`.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 400 `bad_request`, e.g. a body that is not valid JSON
+    BadRequest(String),
+    /// 401 `unauthorized`, the user is not logged in, or gave wrong credentials
+    Unauthorized(String),
+    /// 403 `forbidden`, the user is logged in but not allowed to do this
+    Forbidden(String),
+    /// 404 `not_found`
+    NotFound(String),
+    /// 409 `conflict`, e.g. joining a group the user is already in
+    Conflict(String),
+    /// 409 `schedule_conflict`, a session overlaps others, which are given here
+    ScheduleConflict(JsonValue),
+    /// 410 `gone`, e.g. an invite code that has expired
+    Gone(String),
+    /// 422 `validation_failed`, with the messages for each invalid field
+    Validation(HashMap<String, Vec<String>>),
+    /// 500 `internal`, sent with a short summary only, the details are only logged
+    Internal(String),
+}
+
+impl ApiError {
+    /// a validation error for a single field
+    pub fn invalid_field(field: &str, message: impl Into<String>) -> ApiError {
+        let mut errors = HashMap::new();
+        errors.insert(field.to_string(), vec![message.into()]);
+        ApiError::Validation(errors)
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) | ApiError::ScheduleConflict(_) => Status::Conflict,
+            ApiError::Gone(_) => Status::Gone,
+            ApiError::Validation(_) => Status::UnprocessableEntity,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    /// the machine readable `code` of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::ScheduleConflict(_) => "schedule_conflict",
+            ApiError::Gone(_) => "gone",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Gone(message)
+            | ApiError::Internal(message) => json!({ "code": self.code(), "error": message }),
+            ApiError::ScheduleConflict(conflicts) => json!({
+                "code": self.code(),
+                "error": "Session overlaps with another session",
+                "errors": { "session_date": [ "Session overlaps with another session" ] },
+                "conflicts": conflicts,
+            }),
+            ApiError::Validation(errors) => json!({
+                "code": self.code(),
+                "error": "some fields are invalid",
+                "errors": errors,
+            }),
+        }
+    }
+}
+
+impl From<DieselError> for ApiError {
+    /// for queries without a more specific error, the error itself is only logged
+    fn from(error: DieselError) -> Self {
+        println!("Error: {:#?}", error);
+        match error {
+            DieselError::NotFound => ApiError::NotFound("not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::Conflict("already exists".to_string())
+            }
+            _ => ApiError::Internal("internal server error".to_string()),
+        }
+    }
+}
+
+impl<'a> From<JsonError<'a>> for ApiError {
+    fn from(json_error: JsonError<'a>) -> Self {
+        match json_error {
+            JsonError::Parse(_req, err) => ApiError::BadRequest(err.to_string()),
+            JsonError::Io(err) => {
+                println!("Error: {:#?}", err);
+                ApiError::Internal(
+                    "I/O error occured while reading the incoming request data".to_string(),
+                )
+            }
+        }
+    }
+}
+
+impl From<ApiError> for ApiResponse {
+    fn from(error: ApiError) -> Self {
+        ApiResponse {
+            json: error.to_json(),
+            status: error.status(),
+        }
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        ApiResponse::from(self).respond_to(req)
+    }
+}
+
+// Catchers, so that errors Rocket raises itself (e.g. no route matching) use the same envelope
+// instead of Rocket's HTML pages
+
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::BadRequest("the request could not be understood".to_string())
+}
+
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthorized("unauthorised".to_string())
+}
+
+#[catch(404)]
+pub fn not_found() -> ApiError {
+    ApiError::NotFound("resource not found".to_string())
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::Validation(HashMap::new())
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::Internal("internal server error".to_string())
+}
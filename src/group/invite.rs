@@ -0,0 +1,200 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::{group_invites, groups_users};
+use diesel::prelude::*;
+
+use super::role::GroupRole;
+use super::{Group, GroupUser, InsertableGroupUser};
+use crate::role::Role;
+
+use chrono::{DateTime, Utc};
+
+use crate::api::generate_token;
+use crate::error::ApiError;
+
+use crate::notification::{Notification, NotificationKind};
+
+/// length of the random code in an invite link
+const INVITE_CODE_LENGTH: usize = 8;
+
+/// A code (or the link containing it) that lets anyone who has it join a group straight away,
+/// without the group's admins having to accept them
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize)]
+#[belongs_to(Group)]
+#[table_name = "group_invites"]
+#[serde(rename_all = "camelCase")]
+pub struct GroupInvite {
+    pub id: i32,
+    pub group_id: i32,
+    pub code: String,
+    pub created_by: Option<i32>,
+    /// the code stops working once it has been used this many times, if set
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[table_name = "group_invites"]
+#[derive(Insertable)]
+pub struct InsertableGroupInvite {
+    pub group_id: i32,
+    pub code: String,
+    pub created_by: Option<i32>,
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl GroupInvite {
+    pub fn create(
+        group: &Group,
+        created_by: i32,
+        max_uses: Option<i32>,
+        expires_at: Option<DateTime<Utc>>,
+        connection: &PgConnection,
+    ) -> Result<GroupInvite, ApiError> {
+        let new_group_invite = &InsertableGroupInvite {
+            group_id: group.id,
+            code: generate_token(INVITE_CODE_LENGTH),
+            created_by: Some(created_by),
+            max_uses,
+            expires_at,
+        };
+
+        diesel::insert_into(group_invites::table)
+            .values(new_group_invite)
+            .get_result::<GroupInvite>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not create an invite code".to_string())
+            })
+    }
+
+    /// the group's codes that can still be used
+    pub fn read_active(
+        group: &Group,
+        connection: &PgConnection,
+    ) -> Result<Vec<GroupInvite>, ApiError> {
+        GroupInvite::belonging_to(group)
+            .filter(group_invites::revoked_at.is_null())
+            .filter(
+                group_invites::expires_at
+                    .is_null()
+                    .or(group_invites::expires_at.gt(Utc::now())),
+            )
+            .filter(
+                group_invites::max_uses
+                    .is_null()
+                    .or(group_invites::max_uses.gt(group_invites::uses.nullable())),
+            )
+            .order(group_invites::created_at.desc())
+            .load::<GroupInvite>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Invite codes not found".to_string())
+            })
+    }
+
+    pub fn revoke(
+        group: &Group,
+        invite_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        diesel::update(
+            GroupInvite::belonging_to(group)
+                .filter(group_invites::id.eq(invite_id))
+                .filter(group_invites::revoked_at.is_null()),
+        )
+        .set(group_invites::revoked_at.eq(Utc::now()))
+        .get_result::<GroupInvite>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Invite code not found".to_string())
+        })?;
+
+        Ok(())
+    }
+
+    /// join the group a code is for, returning the group. Any pending request or invite the user
+    /// already had is accepted.
+    pub fn redeem(code: &str, user_id: i32, connection: &PgConnection) -> Result<Group, ApiError> {
+        let group_invite = group_invites::table
+            .filter(group_invites::code.eq(code))
+            .first::<GroupInvite>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Invite code not found".to_string())
+            })?;
+
+        connection.transaction::<_, ApiError, _>(|| {
+            if GroupUser::role(group_invite.group_id, user_id, connection)?.is_some() {
+                return Err(ApiError::Conflict(
+                    "you are already a member of this group".to_string(),
+                ));
+            }
+
+            // use up the code in the same statement that checks it, so it is never used more
+            // than `max_uses` times
+            let updated = diesel::update(
+                group_invites::table
+                    .find(group_invite.id)
+                    .filter(group_invites::revoked_at.is_null())
+                    .filter(
+                        group_invites::expires_at
+                            .is_null()
+                            .or(group_invites::expires_at.gt(Utc::now())),
+                    )
+                    .filter(
+                        group_invites::max_uses
+                            .is_null()
+                            .or(group_invites::max_uses.gt(group_invites::uses.nullable())),
+                    ),
+            )
+            .set(group_invites::uses.eq(group_invites::uses + 1))
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not join the group".to_string())
+            })?;
+            if updated == 0 {
+                return Err(ApiError::Gone(
+                    "this invite code has expired, been used up or been revoked".to_string(),
+                ));
+            }
+
+            diesel::insert_into(groups_users::table)
+                .values(&InsertableGroupUser {
+                    group_id: group_invite.group_id,
+                    user_id,
+                    admin_accepted: true,
+                    user_accepted: true,
+                    role: GroupRole::Member.as_str().to_string(),
+                })
+                .on_conflict((groups_users::group_id, groups_users::user_id))
+                .do_update()
+                .set((
+                    groups_users::admin_accepted.eq(true),
+                    groups_users::user_accepted.eq(true),
+                ))
+                .execute(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not join the group".to_string())
+                })?;
+
+            let group = Group::find(group_invite.group_id, connection)?;
+
+            Notification::notify_group(
+                group_invite.created_by.unwrap_or(group.admin),
+                NotificationKind::GroupJoinedWithInvite,
+                user_id,
+                group.id,
+                connection,
+            )?;
+
+            Ok(group)
+        })
+    }
+}
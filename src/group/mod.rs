@@ -4,13 +4,20 @@
 use crate::schema::{groups, groups_users, sessions, users};
 use diesel::prelude::*;
 
+use crate::notification::{Notification, NotificationKind};
 use crate::session::Session;
 use crate::user::{Profile, User};
 
+pub mod invite;
+pub mod privacy;
+pub mod role;
 pub mod routes;
 
-use crate::api::ApiResponse;
-use rocket::http::Status;
+use crate::role::Role;
+use privacy::GroupPrivacy;
+use role::{GroupPermission, GroupRole};
+
+use crate::error::ApiError;
 
 use crate::config::DEFAULT_LIMIT;
 
@@ -18,6 +25,8 @@ use crate::database::dsl;
 
 use crate::database::Paginate;
 
+use crate::visibility::{require_visible, Viewable, Visibility};
+
 
 #[table_name = "groups"]
 #[derive(
@@ -30,6 +39,8 @@ pub struct Group {
     pub description: String,
     pub image: Option<String>,
     pub admin: i32,
+    /// one of "public", "listed" or "private"
+    pub privacy: String,
 }
 
 
@@ -40,6 +51,7 @@ pub struct InsertableGroup {
     pub name: String,
     pub description: String,
     pub admin: i32,
+    pub privacy: String,
 }
 
 #[derive(Serialize)]
@@ -49,11 +61,22 @@ pub struct GroupJson {
     pub slug: String,
     pub name: String,
     pub description: String,
+    pub privacy: String,
     pub admin: Profile,
-    pub members: Vec<Profile>,
+    pub members: Vec<GroupMember>,
     pub sessions: Vec<Session>,
 }
 
+/// A member of a group along with their role in it
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+    #[serde(flatten)]
+    pub profile: Profile,
+    /// one of "owner", "admin", "scheduler" or "member"
+    pub role: String,
+}
+
 
 #[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
 #[belongs_to(Group)]
@@ -65,6 +88,7 @@ pub struct GroupUser {
     pub user_id: i32,
     pub admin_accepted: bool,
     pub user_accepted: bool,
+    pub role: String,
 }
 
 
@@ -75,6 +99,7 @@ pub struct InsertableGroupUser {
     pub user_id: i32,
     pub admin_accepted: bool,
     pub user_accepted: bool,
+    pub role: String,
 }
 
 // TODO: remove clone when diesel will allow skipping fields
@@ -99,7 +124,7 @@ impl Group {
     pub fn attach(
         &self,
         admin: Profile,
-        members: Vec<Profile>,
+        members: Vec<GroupMember>,
         sessions: Vec<Session>,
     ) -> GroupJson {
         GroupJson {
@@ -107,6 +132,7 @@ impl Group {
             slug: self.slug.clone(),
             name: self.name.clone(),
             description: self.description.clone(),
+            privacy: self.privacy.clone(),
             admin,
             members,
             sessions,
@@ -116,13 +142,13 @@ impl Group {
         params: &FindGroups,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<GroupJson>, i64), ApiResponse> {
+    ) -> Result<(Vec<GroupJson>, i64), ApiError> {
         if params.global_search.unwrap_or(false) {
-            //get all groups regardless of what groups the current user is in
-
-            let mut pages_count: i64 = 0;
+            //get all groups regardless of what groups the current user is in, apart from private
+            //ones which can only be found by their members
 
             let mut query = groups::table
+                .filter(groups::privacy.ne(GroupPrivacy::Private.as_str()))
                 .inner_join(users::table) // admin details
                 .select((groups::all_columns, users::all_columns))
                 .into_boxed();
@@ -142,30 +168,21 @@ impl Group {
                 query = query.order(groups::name.asc())
             }
 
-            query
+            let (groups_and_admins, pages_count) = query
                 .paginate(params.page.unwrap_or(1))
                 .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
                 .load_and_count_pages::<(Group, User)>(connection)
                 .map_err(|error| {
                     println!("Error: {:#?}", error);
-                    ApiResponse {
-                        json: json!({"error": "Groups not found" }),
-                        status: Status::NotFound,
-                    }
-                })
-                .map(|(groups_and_admins, count)| {
-                    pages_count += count;
-                    groups_and_admins
-                })?
-                .iter()
-                .map(|(group, admin)| populate(group, admin.to_profile(), connection))
-                .collect::<Result<Vec<_>, _>>()
+                    ApiError::NotFound("Groups not found".to_string())
+                })?;
+
+            let (groups, admins) = split_admins(groups_and_admins);
+            populate_all_for(&groups, admins, user_id, connection)
                 .map(|group_jsons| (group_jsons, pages_count))
         } else {
             // get all groups belonging to the current user
 
-            let mut pages_count: i64 = 0;
-
             let user = User::find(user_id, connection).map_err(|response| response)?;
 
             let mut query = GroupUser::belonging_to(&user)
@@ -190,46 +207,52 @@ impl Group {
                 query = query.order(groups::name.asc())
             }
 
-            query
+            let (groups_and_admins, pages_count) = query
                 .paginate(params.page.unwrap_or(1))
                 .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
                 .load_and_count_pages::<(Group, User)>(connection)
                 .map_err(|error| {
                     println!("Error: {:#?}", error);
-                    ApiResponse {
-                        json: json!({"error": "Groups not found" }),
-                        status: Status::NotFound,
-                    }
-                })
-                .map(|(groups_and_admins, count)| {
-                    pages_count += count;
-                    groups_and_admins
-                })?
-                .iter()
-                .map(|(group, admin)| populate(group, admin.to_profile(), connection))
-                .collect::<Result<Vec<_>, _>>()
-                .map(|group_jsons| (group_jsons, pages_count))
+                    ApiError::NotFound("Groups not found".to_string())
+                })?;
+
+            let (groups, admins) = split_admins(groups_and_admins);
+            populate_all(&groups, admins, connection).map(|group_jsons| (group_jsons, pages_count))
         }
     }
 
-    pub fn find(group_id: i32, connection: &PgConnection) -> Result<Group, ApiResponse> {
+    pub fn find(group_id: i32, connection: &PgConnection) -> Result<Group, ApiError> {
         groups::table
             .find(group_id)
             .first::<Group>(connection)
             .map(|group| group)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Group not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Group not found".to_string())
             })
     }
 
+    pub fn privacy(&self) -> GroupPrivacy {
+        // the column is checked by the database, so this only falls back on a value we no longer
+        // know about
+        self.privacy.parse().unwrap_or(GroupPrivacy::Private)
+    }
+
+    /// the group's sessions are only listed to its members, unless the group is public
+    pub fn sessions_visible_to(
+        &self,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<bool, ApiError> {
+        Ok(self.privacy() == GroupPrivacy::Public
+            || GroupUser::role(self.id, user_id, connection)?.is_some())
+    }
+
     pub fn find_as_json(
         group_slug: &str,
+        user_id: i32,
         connection: &PgConnection,
-    ) -> Result<GroupJson, ApiResponse> {
+    ) -> Result<GroupJson, ApiError> {
         let group_and_admin = groups::table
             .filter(groups::slug.eq(group_slug))
             .inner_join(users::table) // admin details
@@ -237,143 +260,218 @@ impl Group {
             .first::<(Group, User)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Group not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Group not found".to_string())
             })?;
         let (group, admin) = group_and_admin;
-        populate(&group, admin.to_profile(), connection).map_err(|response| response)
+
+        require_visible(&group, user_id, connection)?;
+
+        populate_for(&group, admin.to_profile(), user_id, connection)
     }
 
+    /// join a public group straight away, without waiting for its admins to accept. Any pending
+    /// request or invite the user already had is accepted.
+    pub fn join(group: &Group, user_id: i32, connection: &PgConnection) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let new_group_user = &InsertableGroupUser {
+                group_id: group.id,
+                user_id,
+                admin_accepted: true,
+                user_accepted: true,
+                role: GroupRole::Member.as_str().to_string(),
+            };
+
+            diesel::insert_into(groups_users::table)
+                .values(new_group_user)
+                .on_conflict((
+                    groups_users::columns::group_id,
+                    groups_users::columns::user_id,
+                ))
+                .do_update()
+                .set((
+                    groups_users::columns::admin_accepted.eq(true),
+                    groups_users::columns::user_accepted.eq(true),
+                ))
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not join the group".to_string())
+                })?;
+
+            GroupUser::notify_member_managers(
+                group,
+                NotificationKind::GroupUserJoined,
+                user_id,
+                connection,
+            )?;
+
+            Ok(())
+        })
+    }
+
+    /// ask the group's admins to let the user join. Asking again, or while invited, changes
+    /// nothing.
     pub fn request_to_join(
         group_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let new_group_user = &InsertableGroupUser {
-            group_id,
-            user_id,
-            admin_accepted: false,
-            user_accepted: true,
-        };
-
-        diesel::insert_into(groups_users::table)
-            .values(new_group_user)
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not request to join the group", "details": error.to_string() }),
-                    status: Status::InternalServerError,
-                }
-            })?;
-        Ok(())
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let new_group_user = &InsertableGroupUser {
+                group_id,
+                user_id,
+                admin_accepted: false,
+                user_accepted: true,
+                role: GroupRole::Member.as_str().to_string(),
+            };
+
+            let requested = diesel::insert_into(groups_users::table)
+                .values(new_group_user)
+                .on_conflict_do_nothing()
+                .execute(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not request to join the group".to_string())
+                })?;
+            if requested == 0 {
+                return Ok(());
+            }
+
+            let group = Group::find(group_id, connection)?;
+            GroupUser::notify_member_managers(
+                &group,
+                NotificationKind::GroupRequestReceived,
+                user_id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn accept_to_join(
         group: &Group,
         user_id: i32,
+        accepted_by: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let group_user = GroupUser::belonging_to(group)
-            .filter(groups_users::columns::admin_accepted.eq(false))
-            .filter(groups_users::columns::user_accepted.eq(true))
-            .filter(groups_users::columns::user_id.eq(user_id))
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-        let updated_group_user = &UpdateGroupUser {
-            admin_accepted: true,
-            user_accepted: true,
-        };
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let group_user = GroupUser::belonging_to(group)
+                .filter(groups_users::columns::admin_accepted.eq(false))
+                .filter(groups_users::columns::user_accepted.eq(true))
+                .filter(groups_users::columns::user_id.eq(user_id))
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+            let updated_group_user = &UpdateGroupUser {
+                admin_accepted: true,
+                user_accepted: true,
+            };
 
-        diesel::update(groups_users::table.find((group_user.group_id, user_id)))
-            .set(updated_group_user)
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User could not be accepted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
+            diesel::update(groups_users::table.find((group_user.group_id, user_id)))
+                .set(updated_group_user)
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User could not be accepted".to_string())
+                })?;
 
-        Ok(())
+            Notification::notify_group(
+                user_id,
+                NotificationKind::GroupRequestAccepted,
+                accepted_by,
+                group.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn invite_to_join(
         group_id: i32,
         user_id: i32,
+        invited_by: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let new_group_user = &InsertableGroupUser {
-            group_id,
-            user_id,
-            admin_accepted: true,
-            user_accepted: false,
-        };
-
-        diesel::insert_into(groups_users::table)
-            .values(new_group_user)
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not make an invite to the user to join the group" }),
-                    status: Status::InternalServerError,
-                }
-            })?;
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let new_group_user = &InsertableGroupUser {
+                group_id,
+                user_id,
+                admin_accepted: true,
+                user_accepted: false,
+                role: GroupRole::Member.as_str().to_string(),
+            };
 
-        Ok(())
+            diesel::insert_into(groups_users::table)
+                .values(new_group_user)
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal(
+                        "Could not make an invite to the user to join the group".to_string(),
+                    )
+                })?;
+
+            let group = Group::find(group_id, connection)?;
+            Notification::notify_group(
+                user_id,
+                NotificationKind::GroupInviteReceived,
+                invited_by,
+                group.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn accept_invite_to_join(
         group: &Group,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let group_user = GroupUser::belonging_to(group)
-            .filter(groups_users::columns::admin_accepted.eq(true))
-            .filter(groups_users::columns::user_accepted.eq(false))
-            .filter(groups_users::columns::user_id.eq(user_id))
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-        let updated_group_user = &UpdateGroupUser {
-            admin_accepted: true,
-            user_accepted: true,
-        };
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let group_user = GroupUser::belonging_to(group)
+                .filter(groups_users::columns::admin_accepted.eq(true))
+                .filter(groups_users::columns::user_accepted.eq(false))
+                .filter(groups_users::columns::user_id.eq(user_id))
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+            let updated_group_user = &UpdateGroupUser {
+                admin_accepted: true,
+                user_accepted: true,
+            };
 
-        diesel::update(groups_users::table.find((group_user.group_id, user_id)))
-            .set(updated_group_user)
-            .get_result::<GroupUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not accept invite", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
+            diesel::update(groups_users::table.find((group_user.group_id, user_id)))
+                .set(updated_group_user)
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("Could not accept invite".to_string())
+                })?;
 
-        Ok(())
+            GroupUser::notify_member_managers(
+                group,
+                NotificationKind::GroupInviteAccepted,
+                user_id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn is_user_waiting_to_join(
         group_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         groups_users::table
             .find((group_id, user_id))
             .select((
@@ -383,10 +481,7 @@ impl Group {
             .get_result::<(bool, bool)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Group/User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Group/User not found".to_string())
             })
             .map(|(admin_accepted, user_accepted)| !admin_accepted && user_accepted)
     }
@@ -394,7 +489,7 @@ impl Group {
         group_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         groups_users::table
             .find((group_id, user_id))
             .select((
@@ -404,52 +499,129 @@ impl Group {
             .get_result::<(bool, bool)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Group/User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Group/User not found".to_string())
             })
             .map(|(admin_accepted, user_accepted)| admin_accepted && !user_accepted)
     }
 
-    pub fn remove_user(
+    /// refuse a user's request to join the group
+    pub fn deny_to_join(
         group: &Group,
         user_id: i32,
+        denied_by: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let group_user = GroupUser::belonging_to(group)
-            .filter(groups_users::columns::user_id.eq(user_id))
-            .get_result::<GroupUser>(connection)
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let denied = diesel::delete(
+                groups_users::table
+                    .find((group.id, user_id))
+                    .filter(groups_users::columns::admin_accepted.eq(false))
+                    .filter(groups_users::columns::user_accepted.eq(true)),
+            )
+            .execute(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Request could not be denied".to_string())
             })?;
+            if denied == 0 {
+                return Err(ApiError::NotFound("Request not found".to_string()));
+            }
 
-        diesel::delete(groups_users::table.find((group_user.group_id, user_id)))
+            Notification::notify_group(
+                user_id,
+                NotificationKind::GroupRequestDenied,
+                denied_by,
+                group.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
+    }
+
+    /// turn down an invite to join the group
+    pub fn decline_invite_to_join(
+        group: &Group,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let declined = diesel::delete(
+                groups_users::table
+                    .find((group.id, user_id))
+                    .filter(groups_users::columns::admin_accepted.eq(true))
+                    .filter(groups_users::columns::user_accepted.eq(false)),
+            )
             .execute(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Invite could not be declined".to_string())
             })?;
+            if declined == 0 {
+                return Err(ApiError::NotFound("Invite not found".to_string()));
+            }
 
-        Ok(())
+            GroupUser::notify_member_managers(
+                group,
+                NotificationKind::GroupInviteDeclined,
+                user_id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
-    pub fn delete(group: &Group, connection: &PgConnection) -> Result<(), ApiResponse> {
+    pub fn remove_user(
+        group: &Group,
+        user_id: i32,
+        removed_by: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let group_user = GroupUser::belonging_to(group)
+                .filter(groups_users::columns::user_id.eq(user_id))
+                .get_result::<GroupUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+
+            diesel::delete(groups_users::table.find((group_user.group_id, user_id)))
+                .execute(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User could not be deleted".to_string())
+                })?;
+
+            // either the user left or the admin removed them
+            if removed_by == user_id {
+                GroupUser::notify_member_managers(
+                    group,
+                    NotificationKind::GroupUserLeft,
+                    user_id,
+                    connection,
+                )?;
+            } else {
+                Notification::notify_group(
+                    user_id,
+                    NotificationKind::GroupUserRemoved,
+                    removed_by,
+                    group.id,
+                    connection,
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn delete(group: &Group, connection: &PgConnection) -> Result<(), ApiError> {
         diesel::delete(groups::table.find(group.id))
             .execute(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Group could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Group could not be deleted".to_string())
             })?;
 
         Ok(())
@@ -461,7 +633,7 @@ impl InsertableGroup {
         group: InsertableGroup,
         creator_id: i32,
         connection: &PgConnection,
-    ) -> Result<GroupJson, ApiResponse> {
+    ) -> Result<GroupJson, ApiError> {
         match connection
             .build_transaction()
             .run::<Group, diesel::result::Error, _>(|| {
@@ -474,19 +646,21 @@ impl InsertableGroup {
                     user_id: new_group.admin,
                     admin_accepted: true,
                     user_accepted: true,
+                    role: GroupRole::Owner.as_str().to_string(),
                 };
 
                 diesel::insert_into(groups_users::table)
                     .values(new_group_user)
                     .get_result::<GroupUser>(connection)?;
 
-                // add creator of group as member if they are not the admin
+                // add creator of group as an admin if they are not the owner
                 if new_group.admin != creator_id {
                     let creator_group_user = &InsertableGroupUser {
                         group_id: new_group.id,
                         user_id: creator_id,
                         admin_accepted: true,
                         user_accepted: true,
+                        role: GroupRole::Admin.as_str().to_string(),
                     };
 
                     diesel::insert_into(groups_users::table)
@@ -507,10 +681,8 @@ impl InsertableGroup {
             }
             Err(error) => {
                 println!("Error: {:#?}", error);
-                Err(ApiResponse {
-                    json: json!({"errors":  { "name": [ "Name must be unique" ] }, "details": error.to_string() }), // assume this as the most common cause due to slug and name not being unique
-                    status: Status::InternalServerError,
-                })
+                // assume this as the most common cause due to slug and name not being unique
+                Err(ApiError::invalid_field("name", "Name must be unique"))
             }
         }
     }
@@ -524,6 +696,7 @@ pub struct UpdateGroup {
     name: Option<String>,
     description: Option<String>,
     admin: Option<i32>,
+    privacy: Option<String>,
     #[serde(skip)]
     slug: Option<String>,
 }
@@ -533,18 +706,60 @@ impl UpdateGroup {
         id: i32,
         group: &UpdateGroup,
         connection: &PgConnection,
-    ) -> Result<GroupJson, ApiResponse> {
-        let updated_group = diesel::update(groups::table.find(id))
-            .set(group)
-            .get_result::<Group>(connection)
+    ) -> Result<GroupJson, ApiError> {
+        let previous_admin = Group::find(id, connection)?.admin;
+
+        let updated_group = connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let updated_group = diesel::update(groups::table.find(id))
+                    .set(group)
+                    .get_result::<Group>(connection)?;
+
+                if updated_group.admin != previous_admin {
+                    // the old owner stays on as an admin, the new one becomes a member if they
+                    // were not already
+                    diesel::update(groups_users::table.find((id, previous_admin)))
+                        .set(groups_users::columns::role.eq(GroupRole::Admin.as_str()))
+                        .execute(connection)?;
+
+                    diesel::insert_into(groups_users::table)
+                        .values(&InsertableGroupUser {
+                            group_id: id,
+                            user_id: updated_group.admin,
+                            admin_accepted: true,
+                            user_accepted: true,
+                            role: GroupRole::Owner.as_str().to_string(),
+                        })
+                        .on_conflict((
+                            groups_users::columns::group_id,
+                            groups_users::columns::user_id,
+                        ))
+                        .do_update()
+                        .set((
+                            groups_users::columns::admin_accepted.eq(true),
+                            groups_users::columns::user_accepted.eq(true),
+                            groups_users::columns::role.eq(GroupRole::Owner.as_str()),
+                        ))
+                        .execute(connection)?;
+                }
+
+                Ok(updated_group)
+            })
             .map_err(|error| {
                 println!("Cannot update group: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "error": "cannot update group" }),
-                    status: Status::UnprocessableEntity,
-                }
+                ApiError::Conflict("cannot update group".to_string())
             })?;
 
+        if updated_group.admin != previous_admin {
+            Notification::notify_group(
+                updated_group.admin,
+                NotificationKind::GroupAdminChanged,
+                previous_admin,
+                updated_group.id,
+                connection,
+            )?;
+        }
+
         let admin = User::find(updated_group.admin, connection)
             .map(|user| user.to_profile())
             .map_err(|response| response)?;
@@ -555,23 +770,186 @@ impl UpdateGroup {
     }
 }
 
+impl Viewable for Group {
+    const NOT_FOUND: &'static str = "Group not found";
+    const FORBIDDEN: &'static str = "you are not a member of this group";
+
+    /// anyone can see a group unless it is private, in which case only its members (and anyone
+    /// invited to, or asking to join, it) can tell it exists
+    fn visibility(&self, user_id: i32, connection: &PgConnection) -> Result<Visibility, ApiError> {
+        if self.privacy() != GroupPrivacy::Private {
+            return Ok(Visibility::Visible);
+        }
+
+        groups_users::table
+            .find((self.id, user_id))
+            .select(groups_users::columns::user_id)
+            .first::<i32>(connection)
+            .optional()
+            .map(|group_user| match group_user {
+                Some(_) => Visibility::Visible,
+                None => Visibility::Hidden,
+            })
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Group/User not found".to_string())
+            })
+    }
+}
+
 impl GroupUser {
+    /// the user's role in the group, if they are a member of it
+    pub fn role(
+        group_id: i32,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Option<GroupRole>, ApiError> {
+        groups_users::table
+            .find((group_id, user_id))
+            .filter(groups_users::columns::admin_accepted.eq(true))
+            .filter(groups_users::columns::user_accepted.eq(true))
+            .select(groups_users::columns::role)
+            .first::<String>(connection)
+            .optional()
+            .map(|role| role.and_then(|role| role.parse::<GroupRole>().ok()))
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Group/User not found".to_string())
+            })
+    }
+
+    /// whether the user's role in the group gives them `permission`
+    pub fn has_permission(
+        group_id: i32,
+        user_id: i32,
+        permission: GroupPermission,
+        connection: &PgConnection,
+    ) -> Result<bool, ApiError> {
+        GroupUser::role(group_id, user_id, connection)
+            .map(|role| role.map_or(false, |role| role.can(permission)))
+    }
+
+    /// the members whose role in the group gives them `permission`
+    pub fn with_permission(
+        group_id: i32,
+        permission: GroupPermission,
+        connection: &PgConnection,
+    ) -> Result<Vec<i32>, ApiError> {
+        groups_users::table
+            .filter(groups_users::columns::group_id.eq(group_id))
+            .filter(groups_users::columns::admin_accepted.eq(true))
+            .filter(groups_users::columns::user_accepted.eq(true))
+            .select((groups_users::columns::user_id, groups_users::columns::role))
+            .load::<(i32, String)>(connection)
+            .map(|members| {
+                members
+                    .into_iter()
+                    .filter(|(_, role)| {
+                        role.parse::<GroupRole>()
+                            .map_or(false, |role| role.can(permission))
+                    })
+                    .map(|(user_id, _)| user_id)
+                    .collect()
+            })
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Group/User not found".to_string())
+            })
+    }
+
+    /// tell everyone who can accept or remove members about a change to who is in the group
+    fn notify_member_managers(
+        group: &Group,
+        kind: NotificationKind,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        for manager in
+            GroupUser::with_permission(group.id, GroupPermission::ManageMembers, connection)?
+        {
+            Notification::notify_group(manager, kind, user_id, group.id, connection)?;
+        }
+
+        Ok(())
+    }
+
+    /// the user's role in the group, or an error if it does not give them `permission`
+    pub fn require_permission(
+        group_id: i32,
+        user_id: i32,
+        permission: GroupPermission,
+        connection: &PgConnection,
+    ) -> Result<GroupRole, ApiError> {
+        match GroupUser::role(group_id, user_id, connection)? {
+            Some(role) if role.can(permission) => Ok(role),
+            _ => Err(ApiError::Forbidden(permission.denied().to_string())),
+        }
+    }
+
+    /// give a member a new role. Nobody can make someone the owner this way (the owner is
+    /// changed by changing the group's admin), or change the role of someone who is not below
+    /// them, or give a role that is not below their own.
+    pub fn set_role(
+        group: &Group,
+        user_id: i32,
+        role: GroupRole,
+        changed_by: i32,
+        connection: &PgConnection,
+    ) -> Result<GroupMember, ApiError> {
+        let changer_role = GroupUser::require_permission(
+            group.id,
+            changed_by,
+            GroupPermission::ManageRoles,
+            connection,
+        )?;
+
+        let current_role = GroupUser::role(group.id, user_id, connection)?.ok_or_else(|| {
+            ApiError::NotFound("That user is not a member of this group".to_string())
+        })?;
+
+        if role == GroupRole::Owner {
+            return Err(ApiError::invalid_field(
+                "role",
+                "Change the group's admin to change its owner",
+            ));
+        }
+
+        if !changer_role.outranks(current_role) || !changer_role.outranks(role) {
+            return Err(ApiError::Forbidden(
+                GroupPermission::ManageRoles.denied().to_string(),
+            ));
+        }
+
+        diesel::update(groups_users::table.find((group.id, user_id)))
+            .set(groups_users::columns::role.eq(role.as_str()))
+            .get_result::<GroupUser>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not change the user's role".to_string())
+            })?;
+
+        let user = User::find(user_id, connection)?;
+
+        Ok(GroupMember {
+            profile: user.to_profile(),
+            role: role.as_str().to_string(),
+        })
+    }
+
     pub fn check_user_in_group(
         group_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
-        groups_users::table.find((group_id, user_id))
+    ) -> Result<bool, ApiError> {
+        groups_users::table
+            .find((group_id, user_id))
             .filter(groups_users::columns::admin_accepted.eq(true))
             .filter(groups_users::columns::user_accepted.eq(true))
             .get_result::<GroupUser>(connection)
             .map(|_| true)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "errors": { "group": [ "You are not a member of that group" ] } }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("You are not a member of that group".to_string())
             })
     }
 }
@@ -580,33 +958,98 @@ pub fn populate(
     group: &Group,
     admin: Profile,
     connection: &PgConnection,
-) -> Result<GroupJson, ApiResponse> {
-    let members = GroupUser::belonging_to(group)
+) -> Result<GroupJson, ApiError> {
+    populate_all(std::slice::from_ref(group), vec![admin], connection)?
+        .pop()
+        .ok_or_else(|| ApiError::NotFound("Group not found".to_string()))
+}
+
+/// `populate` for many groups at once, each with the profile of its admin in `admins`. Their
+/// members and sessions are loaded in one query each, rather than one per group.
+pub fn populate_all(
+    groups: &[Group],
+    admins: Vec<Profile>,
+    connection: &PgConnection,
+) -> Result<Vec<GroupJson>, ApiError> {
+    let members = GroupUser::belonging_to(groups)
         .filter(groups_users::columns::admin_accepted.eq(true))
         .filter(groups_users::columns::user_accepted.eq(true))
         .inner_join(users::table)
-        .select(users::all_columns)
-        .load::<User>(connection)
+        .load::<(GroupUser, User)>(connection)
         .map_err(|error| {
             println!("Error: {:#?}", error);
-            ApiResponse {
-                json: json!({"error": "Members not found" }),
-                status: Status::NotFound,
-            }
+            ApiError::NotFound("Members not found".to_string())
         })?
-        .iter()
-        .map(|user| user.to_profile())
-        .collect();
-    let sessions = Session::belonging_to(group)
+        .grouped_by(groups);
+    let sessions = Session::belonging_to(groups)
         .select(sessions::all_columns)
         .load::<Session>(connection)
         .map_err(|error| {
             println!("Error: {:#?}", error);
-            ApiResponse {
-                json: json!({"error": "Sessions not found" }),
-                status: Status::NotFound,
-            }
-        })?;
+            ApiError::NotFound("Sessions not found".to_string())
+        })?
+        .grouped_by(groups);
+
+    Ok(groups
+        .iter()
+        .zip(admins)
+        .zip(members.into_iter().zip(sessions))
+        .map(|((group, admin), (members, sessions))| {
+            let members = members
+                .into_iter()
+                .map(|(group_user, user)| GroupMember {
+                    profile: user.to_profile(),
+                    role: group_user.role,
+                })
+                .collect();
+
+            group.attach(admin, members, sessions)
+        })
+        .collect())
+}
+
+/// `populate`, leaving out the sessions if the user is not allowed to see them
+pub fn populate_for(
+    group: &Group,
+    admin: Profile,
+    user_id: i32,
+    connection: &PgConnection,
+) -> Result<GroupJson, ApiError> {
+    let mut group_jsons = populate_all_for(
+        std::slice::from_ref(group),
+        vec![admin],
+        user_id,
+        connection,
+    )?;
+
+    group_jsons
+        .pop()
+        .ok_or_else(|| ApiError::NotFound("Group not found".to_string()))
+}
+
+/// `populate_all`, leaving out the sessions of the groups the user is not allowed to see them
+/// in, see `Group::sessions_visible_to`
+pub fn populate_all_for(
+    groups: &[Group],
+    admins: Vec<Profile>,
+    user_id: i32,
+    connection: &PgConnection,
+) -> Result<Vec<GroupJson>, ApiError> {
+    let mut group_jsons = populate_all(groups, admins, connection)?;
+
+    for (group, group_json) in groups.iter().zip(group_jsons.iter_mut()) {
+        if !group.sessions_visible_to(user_id, connection)? {
+            group_json.sessions.clear();
+        }
+    }
+
+    Ok(group_jsons)
+}
 
-    Ok(group.attach(admin, members, sessions))
+/// split the results of a query for groups along with their admins
+fn split_admins(groups_and_admins: Vec<(Group, User)>) -> (Vec<Group>, Vec<Profile>) {
+    groups_and_admins
+        .into_iter()
+        .map(|(group, admin)| (group, admin.to_profile()))
+        .unzip()
 }
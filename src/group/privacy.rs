@@ -0,0 +1,35 @@
+use std::str::FromStr;
+
+/// Who can find a group and how they can join it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupPrivacy {
+    /// shown in global search, and anyone can join straight away
+    Public,
+    /// shown in global search, and joining needs an admin to accept the request
+    Listed,
+    /// hidden from everyone but its members, and can only be joined by invite
+    Private,
+}
+
+impl GroupPrivacy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GroupPrivacy::Public => "public",
+            GroupPrivacy::Listed => "listed",
+            GroupPrivacy::Private => "private",
+        }
+    }
+}
+
+impl FromStr for GroupPrivacy {
+    type Err = String;
+
+    fn from_str(privacy: &str) -> Result<GroupPrivacy, String> {
+        match privacy {
+            "public" => Ok(GroupPrivacy::Public),
+            "listed" => Ok(GroupPrivacy::Listed),
+            "private" => Ok(GroupPrivacy::Private),
+            _ => Err(format!("{} is not a group privacy level", privacy)),
+        }
+    }
+}
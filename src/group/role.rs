@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use crate::role::Role;
+
+/// What a member of a group is allowed to do there, see `Role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupRole {
+    Member,
+    Scheduler,
+    Admin,
+    /// the group's `admin`; there is exactly one per group
+    Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupPermission {
+    /// create sessions in the group
+    CreateSessions,
+    /// edit, cancel and delete any session in the group, not just the ones you DM
+    ManageSessions,
+    /// edit the group's name and description
+    EditGroup,
+    /// accept, deny, invite and remove members
+    ManageMembers,
+    /// change members' roles
+    ManageRoles,
+    /// hand the group over to someone else
+    TransferOwnership,
+    DeleteGroup,
+}
+
+impl Role for GroupRole {
+    type Permission = GroupPermission;
+
+    const ALL: &'static [GroupRole] = &[
+        GroupRole::Member,
+        GroupRole::Scheduler,
+        GroupRole::Admin,
+        GroupRole::Owner,
+    ];
+    const NAME: &'static str = "group role";
+
+    fn as_str(self) -> &'static str {
+        match self {
+            GroupRole::Member => "member",
+            GroupRole::Scheduler => "scheduler",
+            GroupRole::Admin => "admin",
+            GroupRole::Owner => "owner",
+        }
+    }
+
+    fn required_for(permission: GroupPermission) -> GroupRole {
+        match permission {
+            GroupPermission::CreateSessions => GroupRole::Member,
+            GroupPermission::ManageSessions => GroupRole::Scheduler,
+            GroupPermission::EditGroup
+            | GroupPermission::ManageMembers
+            | GroupPermission::ManageRoles => GroupRole::Admin,
+            GroupPermission::TransferOwnership | GroupPermission::DeleteGroup => GroupRole::Owner,
+        }
+    }
+}
+
+impl GroupRole {
+    /// whether someone with this role can leave the group; the owner has to hand it over first
+    pub fn can_leave(self) -> bool {
+        !self.can(GroupPermission::TransferOwnership)
+    }
+}
+
+impl FromStr for GroupRole {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<GroupRole, String> {
+        GroupRole::from_name(role)
+    }
+}
+
+impl GroupPermission {
+    /// the error given to someone without this permission
+    pub fn denied(self) -> &'static str {
+        match self {
+            GroupPermission::CreateSessions => "you are not a member of this group",
+            GroupPermission::ManageSessions => "you cannot manage sessions in this group",
+            GroupPermission::EditGroup => "you cannot edit this group",
+            GroupPermission::ManageMembers => "you cannot manage the members of this group",
+            GroupPermission::ManageRoles => "you cannot change roles in this group",
+            GroupPermission::TransferOwnership => "you are not the owner of this group",
+            GroupPermission::DeleteGroup => "you are not the owner of this group",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_can_do_everything_below_them() {
+        assert!(GroupRole::Member.can(GroupPermission::CreateSessions));
+        assert!(!GroupRole::Member.can(GroupPermission::ManageSessions));
+
+        assert!(GroupRole::Scheduler.can(GroupPermission::ManageSessions));
+        assert!(!GroupRole::Scheduler.can(GroupPermission::ManageMembers));
+
+        assert!(GroupRole::Admin.can(GroupPermission::ManageSessions));
+        assert!(GroupRole::Admin.can(GroupPermission::ManageRoles));
+        assert!(!GroupRole::Admin.can(GroupPermission::DeleteGroup));
+
+        assert!(GroupRole::Owner.can(GroupPermission::TransferOwnership));
+        assert!(GroupRole::Owner.can(GroupPermission::DeleteGroup));
+    }
+
+    #[test]
+    fn roles_only_outrank_roles_below_them() {
+        assert!(GroupRole::Owner.outranks(GroupRole::Admin));
+        assert!(GroupRole::Admin.outranks(GroupRole::Scheduler));
+        assert!(!GroupRole::Admin.outranks(GroupRole::Admin));
+        assert!(!GroupRole::Scheduler.outranks(GroupRole::Admin));
+    }
+
+    #[test]
+    fn only_the_owner_cannot_leave() {
+        assert!(GroupRole::Member.can_leave());
+        assert!(GroupRole::Admin.can_leave());
+        assert!(!GroupRole::Owner.can_leave());
+    }
+
+    #[test]
+    fn roles_are_stored_as_their_names() {
+        for role in GroupRole::ALL {
+            assert_eq!(role.as_str().parse::<GroupRole>(), Ok(*role));
+        }
+        assert!("dm".parse::<GroupRole>().is_err());
+    }
+}
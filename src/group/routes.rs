@@ -1,32 +1,40 @@
 use crate::database::DnDAgendaDB;
 use crate::group;
+use crate::group::invite::GroupInvite;
+use crate::group::privacy::GroupPrivacy;
+use crate::group::role::GroupPermission;
+use crate::role::Role;
 
 use rocket_contrib::json::Json;
 use rocket_contrib::json::JsonError;
-use rocket_contrib::json::JsonValue;
 
 use rocket::request::Form;
 
 use crate::api::ApiResponse;
 use crate::api::Auth;
+use crate::config::AppConfig;
+use crate::error::ApiError;
 use rocket::http::Status;
+use rocket::State;
 
-use crate::api::validate_user_exists;
+use crate::api::validate_group_privacy;
+use crate::api::validate_group_role;
 use crate::api::FieldValidator;
 use validator::Validate;
 
-use crate::mailgun::{send_mail, MailType};
+use crate::mail::{send_mail, MailType};
 
 use crate::user::User;
+use crate::visibility::require_visible;
 
-use std::thread;
+use chrono::{Duration, Utc};
 
 #[get("/?<params..>")]
 pub fn get_all(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<group::FindGroups>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => group::Group::read(&params, auth.id, &connection)
             .map(|(groups, pages_count)| ApiResponse {
@@ -34,31 +42,25 @@ pub fn get_all(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_slug>")]
 pub fn get_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_slug: String,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => match group::Group::find_as_json(&group_slug, &connection) {
-            Ok(group_json) => ApiResponse {
+        Ok(auth) => match group::Group::find_as_json(&group_slug, auth.id, &connection) {
+            Ok(group_json) => Ok(ApiResponse {
                 json: json!({ "group": group_json }),
                 status: Status::Ok,
-            },
-            Err(response) => response,
-        },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
+            }),
+            Err(response) => Err(response),
         },
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -68,16 +70,18 @@ pub struct NewGroup {
     pub name: Option<String>,
     #[validate(length(min = 1, code = "Description must be at least 1 character long"))]
     pub description: Option<String>,
-    #[validate(custom = "validate_user_exists")]
     pub admin: Option<i32>,
+    /// defaults to listed
+    #[validate(custom = "validate_group_privacy")]
+    pub privacy: Option<String>,
 }
 
 #[post("/", format = "application/json", data = "<group>")] // data attribute tells rocket to expect Body Data - then map the body to a parameter
 pub fn create(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group: Result<Json<NewGroup>, JsonError>,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => match group {
             Ok(json_group) => {
@@ -85,10 +89,14 @@ pub fn create(
 
                 let empty_flag = false; // i.e. should we ignore empty fields?
                 let mut extractor = FieldValidator::validate(&new_group);
+                extractor.users_exist(&[("admin", new_group.admin)], &connection)?;
                 let name = extractor.extract("name", new_group.name, empty_flag);
                 let description =
                     extractor.extract("description", new_group.description, empty_flag);
                 let admin = extractor.extract("admin", new_group.admin, empty_flag);
+                let privacy = new_group
+                    .privacy
+                    .unwrap_or_else(|| GroupPrivacy::Listed.as_str().to_string());
 
                 let check = extractor.check();
 
@@ -99,34 +107,23 @@ pub fn create(
                             name,
                             description,
                             admin,
+                            privacy,
                         };
                         match group::InsertableGroup::create(insertable_group, auth.id, &connection)
                         {
-                            Ok(group) => ApiResponse {
+                            Ok(group) => Ok(ApiResponse {
                                 json: json!({ "group": group }),
                                 status: Status::Created,
-                            },
-                            Err(response) => response,
+                            }),
+                            Err(response) => Err(response),
                         }
                     }
-                    Err(response) => response,
+                    Err(response) => Err(response),
                 }
             }
-            Err(json_error) => match json_error {
-                JsonError::Parse(_req, err) => ApiResponse {
-                    json: json!({ "error": err.to_string() }),
-                    status: Status::BadRequest,
-                },
-                JsonError::Io(_err) => ApiResponse {
-                    json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                    status: Status::InternalServerError,
-                },
-            },
-        },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
+            Err(json_error) => Err(ApiError::from(json_error)),
         },
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -136,36 +133,32 @@ pub struct UpdateGroupData {
     pub name: Option<String>,
     #[validate(length(min = 1, code = "Description must be at least 1 character long"))]
     pub description: Option<String>,
+    #[validate(custom = "validate_group_privacy")]
+    pub privacy: Option<String>,
 
     slug: Option<String>,
 }
 
 #[patch("/<group_id>", format = "application/json", data = "<group>")]
 pub fn patch_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group: Result<Json<UpdateGroupData>, JsonError>,
     group_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
-                let mut group_update_details = group.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::EditGroup,
+                &connection,
+            )? {
+                let mut group_update_details = group.map_err(ApiError::from)?.into_inner();
 
                 if let Some(ref name) = group_update_details.name {
                     group_update_details.slug = Some(slugify(&name));
@@ -190,6 +183,7 @@ pub fn patch_group(
                     name: group_update_details.name,
                     description: group_update_details.description,
                     admin: None,
+                    privacy: group_update_details.privacy,
 
                     slug: group_update_details.slug,
                 };
@@ -201,56 +195,46 @@ pub fn patch_group(
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::EditGroup.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[derive(Deserialize, Validate, Clone)]
 pub struct UpdateGroupAdminData {
-    #[validate(custom = "validate_user_exists")]
     pub admin: Option<i32>,
 }
 
 #[patch("/<group_id>/admin", format = "application/json", data = "<group>")]
 pub fn patch_admin_of_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group: Result<Json<UpdateGroupAdminData>, JsonError>,
     group_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
-                let group_update_details = group.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::TransferOwnership,
+                &connection,
+            )? {
+                let group_update_details = group.map_err(ApiError::from)?.into_inner();
 
                 let group_validator_details = group_update_details.clone();
 
                 let empty_flag = true; // i.e. do not emit error if empty
                 let mut extractor = FieldValidator::validate(&group_update_details);
+                extractor.users_exist(&[("admin", group_update_details.admin)], &connection)?;
                 let _new_admin =
                     extractor.extract("admin", group_validator_details.admin, empty_flag);
 
@@ -259,6 +243,7 @@ pub fn patch_admin_of_group(
                 let update_group = group::UpdateGroup {
                     name: None,
                     description: None,
+                    privacy: None,
                     slug: None,
 
                     admin: group_update_details.admin,
@@ -271,329 +256,364 @@ pub fn patch_admin_of_group(
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::TransferOwnership.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/join", format = "application/json")]
 pub fn join_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            group::Group::request_to_join(group_details.id, auth.id, &connection)
-                .map(|_| ApiResponse {
-                    json: json!({ "message": "requested to join group successfully" }),
+            // joining a group you are already in changes nothing, whatever its privacy
+            if group::GroupUser::role(group_details.id, auth.id, &connection)?.is_some() {
+                return Ok(ApiResponse {
+                    json: json!({ "message": "already a member of this group" }),
                     status: Status::Ok,
-                })
-                .map_err(|response| response)
+                });
+            }
+
+            match group_details.privacy() {
+                GroupPrivacy::Public => group::Group::join(&group_details, auth.id, &connection)
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "joined group successfully" }),
+                        status: Status::Ok,
+                    }),
+                GroupPrivacy::Listed => {
+                    group::Group::request_to_join(group_details.id, auth.id, &connection).map(
+                        |_| ApiResponse {
+                            json: json!({ "message": "requested to join group successfully" }),
+                            status: Status::Ok,
+                        },
+                    )
+                }
+                GroupPrivacy::Private => {
+                    // do not give away that the group exists to those who cannot see it
+                    require_visible(&group_details, auth.id, &connection)?;
+
+                    Err(ApiError::Forbidden("this group is invite only".to_string()))
+                }
+            }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/accept/<user_id>", format = "application/json")]
 pub fn accept_to_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
-                group::Group::accept_to_join(&group_details, user_id, &connection)
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )? {
+                group::Group::accept_to_join(&group_details, user_id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "successfully accepted user to group" }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/deny/<user_id>", format = "application/json")]
 pub fn deny_to_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
-                group::Group::remove_user(&group_details, user_id, &connection)
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )? {
+                group::Group::deny_to_join(&group_details, user_id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "successfully denied user to group" }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the Admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/invite/<user_id>", format = "application/json", rank = 2)]
 pub fn invite_to_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
-            if auth.id == group_details.admin {
-                group::Group::invite_to_join(group_details.id, user_id, &connection)
-                    .map(|_| {
-                        // since invite_to_join succeeded, the user and admin must exist
-                        let user = User::find(user_id, &connection).unwrap();
-                        let admin = User::find(group_details.admin, &connection).unwrap();
-
-                        thread::spawn(|| {
-                            send_mail(
-                                MailType::GroupInviteReceived,
-                                user,
-                                group_details.name,
-                                group_details.slug,
-                                admin,
-                            );
-                        });
-                        ApiResponse {
-                            json: json!({ "message": "invited user to join group successfully" }),
-                            status: Status::Ok,
-                        }
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )? {
+                // the mail is queued in the same transaction, so it is only sent for an invite
+                // that was made
+                connection
+                    .transaction::<_, ApiError, _>(|| {
+                        group::Group::invite_to_join(
+                            group_details.id,
+                            user_id,
+                            auth.id,
+                            &connection,
+                        )?;
+
+                        let user = User::find(user_id, &connection)?;
+                        let inviter = User::find(auth.id, &connection)?;
+
+                        send_mail(
+                            MailType::GroupInviteReceived,
+                            user,
+                            group_details.name,
+                            group_details.slug,
+                            inviter,
+                            &app_config,
+                            &connection,
+                        )
+                    })
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "invited user to join group successfully" }),
+                        status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/invite/accept", format = "application/json")]
 pub fn accept_invite_to_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            group::Group::accept_invite_to_join(&group_details, auth.id, &connection)
-                .map(|_| {
-                    // since accept_invite_to_join succeeded, the user and admin must exist
-                    let user = User::find(auth.id, &connection).unwrap();
-                    let admin = User::find(group_details.admin, &connection).unwrap();
-
-                    thread::spawn(|| {
-                        send_mail(
-                            MailType::GroupInviteAccepted,
-                            user,
-                            group_details.name,
-                            group_details.slug,
-                            admin,
-                        );
-                    });
-                    ApiResponse {
-                        json: json!({ "message": "Joined group successfully" }),
-                        status: Status::Ok,
-                    }
+            connection
+                .transaction::<_, ApiError, _>(|| {
+                    group::Group::accept_invite_to_join(&group_details, auth.id, &connection)?;
+
+                    let user = User::find(auth.id, &connection)?;
+                    let admin = User::find(group_details.admin, &connection)?;
+
+                    send_mail(
+                        MailType::GroupInviteAccepted,
+                        user,
+                        group_details.name,
+                        group_details.slug,
+                        admin,
+                        &app_config,
+                        &connection,
+                    )
+                })
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "Joined group successfully" }),
+                    status: Status::Ok,
                 })
                 .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/invite/deny", format = "application/json")]
 pub fn deny_invite_to_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            group::Group::remove_user(&group_details, auth.id, &connection)
-                .map(|_| {
-                    // since remove_user succeeded, the user and admin must exist
-                    let user = User::find(auth.id, &connection).unwrap();
-                    let admin = User::find(group_details.admin, &connection).unwrap();
-
-                    thread::spawn(|| {
-                        send_mail(
-                            MailType::GroupInviteDeclined,
-                            user,
-                            group_details.name,
-                            group_details.slug,
-                            admin,
-                        );
-                    });
-                    ApiResponse {
-                        json: json!({ "message": "Denied invite to group successfully" }),
-                        status: Status::Ok,
-                    }
+            connection
+                .transaction::<_, ApiError, _>(|| {
+                    group::Group::decline_invite_to_join(&group_details, auth.id, &connection)?;
+
+                    let user = User::find(auth.id, &connection)?;
+                    let admin = User::find(group_details.admin, &connection)?;
+
+                    send_mail(
+                        MailType::GroupInviteDeclined,
+                        user,
+                        group_details.name,
+                        group_details.slug,
+                        admin,
+                        &app_config,
+                        &connection,
+                    )
+                })
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "Denied invite to group successfully" }),
+                    status: Status::Ok,
                 })
                 .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/waiting/<user_id>", format = "application/json")]
 pub fn is_user_waiting_to_join(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => group::Group::is_user_waiting_to_join(group_id, user_id, &connection)
-            .map(|is_waiting| ApiResponse {
-                json: json!({ "waiting": is_waiting }),
-                status: Status::Ok,
-            })
-            .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Ok(auth) => {
+            let group_details =
+                group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+            require_visible(&group_details, auth.id, &connection)?;
+
+            group::Group::is_user_waiting_to_join(group_id, user_id, &connection)
+                .map(|is_waiting| ApiResponse {
+                    json: json!({ "waiting": is_waiting }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<group_id>/invited/<user_id>", format = "application/json")]
 pub fn is_user_invited_to_join(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => group::Group::is_user_invited_to_join(group_id, user_id, &connection)
-            .map(|is_invited| ApiResponse {
-                json: json!({ "invited": is_invited }),
-                status: Status::Ok,
-            })
-            .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Ok(auth) => {
+            let group_details =
+                group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+            require_visible(&group_details, auth.id, &connection)?;
+
+            group::Group::is_user_invited_to_join(group_id, user_id, &connection)
+                .map(|is_invited| ApiResponse {
+                    json: json!({ "invited": is_invited }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<group_id>/leave")]
 pub fn leave_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id != group_details.admin {
-                group::Group::remove_user(&group_details, auth.id, &connection)
+            // anyone who has not joined yet can still take back their request
+            match group::GroupUser::role(group_details.id, auth.id, &connection)? {
+                Some(role) if !role.can_leave() => Err(ApiError::Forbidden(
+                    "you are the owner, so you cannot leave".to_string(),
+                )),
+                _ => group::Group::remove_user(&group_details, auth.id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "left group successfully" }),
                         status: Status::Ok,
                     })
-                    .map_err(|response| response)
-            } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are the admin, so you cannot leave" }),
-                    status: Status::Unauthorized,
-                })
+                    .map_err(|response| response),
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<group_id>")]
 pub fn delete_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
+            if group::GroupUser::has_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::DeleteGroup,
+                &connection,
+            )? {
                 group::Group::delete(&group_details, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "group deleted successfully" }),
@@ -601,60 +621,261 @@ pub fn delete_group(
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    GroupPermission::DeleteGroup.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<group_id>/remove/<user_id>")]
 pub fn remove_user_from_group(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     group_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. group does not exist)
             let group_details =
                 group::Group::find(group_id, &connection).map_err(|response| response)?;
 
-            if auth.id == group_details.admin {
-                if user_id != auth.id {
-                    group::Group::remove_user(&group_details, user_id, &connection)
-                        .map(|_| ApiResponse {
-                            json: json!({ "message": "removed user from group successfully" }),
-                            status: Status::Ok,
-                        })
-                        .map_err(|response| response)
-                } else {
-                    Err(ApiResponse {
-                        json: json!({ "error": "you are the admin, so you cannot be removed" }),
-                        status: Status::Unauthorized,
+            let role = group::GroupUser::require_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )?;
+
+            // members who have not been accepted yet have no role to outrank
+            let user_role = group::GroupUser::role(group_details.id, user_id, &connection)?;
+
+            if user_role.map_or(true, |user_role| role.outranks(user_role)) {
+                group::Group::remove_user(&group_details, user_id, auth.id, &connection)
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "removed user from group successfully" }),
+                        status: Status::Ok,
                     })
-                }
+                    .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the admin" }),
-                    status: Status::Unauthorized,
+                Err(ApiError::Forbidden(
+                    "you can only remove members whose role is below yours".to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct GroupRoleData {
+    #[validate(custom = "validate_group_role")]
+    pub role: Option<String>,
+}
+
+/// change a member's role, only to and from roles below your own
+#[put(
+    "/<group_id>/role/<user_id>",
+    format = "application/json",
+    data = "<role>"
+)]
+pub fn set_role_in_group(
+    auth: Result<Auth, ApiError>,
+    role: Result<Json<GroupRoleData>, JsonError>,
+    group_id: i32,
+    user_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match role {
+            Ok(json_role) => {
+                let role_details = json_role.into_inner();
+
+                let mut extractor = FieldValidator::validate(&role_details);
+                let role = extractor.extract("role", role_details.role, false);
+
+                extractor.check()?;
+
+                // get error if there is any (i.e. group does not exist)
+                let group_details =
+                    group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+                // already checked by validate_group_role
+                let role = role
+                    .parse::<group::role::GroupRole>()
+                    .map_err(|error| ApiError::invalid_field("role", error))?;
+
+                group::GroupUser::set_role(&group_details, user_id, role, auth.id, &connection)
+                    .map(|member| ApiResponse {
+                        json: json!({ "member": member }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
+        },
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct NewGroupInviteData {
+    /// unlimited if not given
+    #[validate(range(min = 1, code = "Max uses must be at least 1"))]
+    pub max_uses: Option<i32>,
+    /// never expires if not given
+    #[validate(range(
+        min = 1,
+        max = 8760,
+        code = "Invite codes must expire between 1 hour and 1 year from now"
+    ))]
+    pub expires_in_hours: Option<i32>,
+}
+
+/// make a code (and link) that lets anyone join the group in one step
+#[post("/<group_id>/invites", format = "application/json", data = "<invite>")]
+pub fn create_invite(
+    auth: Result<Auth, ApiError>,
+    invite: Result<Json<NewGroupInviteData>, JsonError>,
+    group_id: i32,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match invite {
+            Ok(json_invite) => {
+                let invite_details = json_invite.into_inner();
+
+                FieldValidator::validate(&invite_details).check()?;
+
+                // get error if there is any (i.e. group does not exist)
+                let group_details =
+                    group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+                group::GroupUser::require_permission(
+                    group_details.id,
+                    auth.id,
+                    GroupPermission::ManageMembers,
+                    &connection,
+                )?;
+
+                let expires_at = invite_details
+                    .expires_in_hours
+                    .map(|hours| Utc::now() + Duration::hours(i64::from(hours)));
+
+                GroupInvite::create(
+                    &group_details,
+                    auth.id,
+                    invite_details.max_uses,
+                    expires_at,
+                    &connection,
+                )
+                .map(|invite| ApiResponse {
+                    json: json!({
+                        "invite": invite,
+                        "inviteLink": invite_link(&app_config, &invite)
+                    }),
+                    status: Status::Created,
                 })
+                .map_err(|response| response)
             }
+            Err(json_error) => Err(ApiError::from(json_error)),
+        },
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// the group's invite codes that have not expired, been used up or been revoked
+#[get("/<group_id>/invites")]
+pub fn get_invites(
+    auth: Result<Auth, ApiError>,
+    group_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. group does not exist)
+            let group_details =
+                group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+            group::GroupUser::require_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )?;
+
+            GroupInvite::read_active(&group_details, &connection)
+                .map(|invites| ApiResponse {
+                    json: json!({ "invites": invites }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[delete("/<group_id>/invites/<invite_id>")]
+pub fn revoke_invite(
+    auth: Result<Auth, ApiError>,
+    group_id: i32,
+    invite_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. group does not exist)
+            let group_details =
+                group::Group::find(group_id, &connection).map_err(|response| response)?;
+
+            group::GroupUser::require_permission(
+                group_details.id,
+                auth.id,
+                GroupPermission::ManageMembers,
+                &connection,
+            )?;
+
+            GroupInvite::revoke(&group_details, invite_id, &connection)
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "revoked invite code successfully" }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
+/// join a group straight away with an invite code
+#[post("/invites/<code>/join")]
+pub fn join_group_with_invite(
+    auth: Result<Auth, ApiError>,
+    code: String,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            let group_details = GroupInvite::redeem(&code, auth.id, &connection)?;
+
+            group::Group::find_as_json(&group_details.slug, auth.id, &connection)
+                .map(|group_json| ApiResponse {
+                    json: json!({ "group": group_json }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+fn invite_link(app_config: &AppConfig, invite: &GroupInvite) -> String {
+    format!("{}/#/groups/join/{}", app_config.app_url, invite.code)
+}
+
 fn slugify(name: &str) -> String {
     slug::slugify(name)
 }
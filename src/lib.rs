@@ -10,10 +10,8 @@ extern crate serde_derive;
 extern crate diesel;
 #[macro_use]
 extern crate lazy_static;
-#[macro_use]
-extern crate dotenv_codegen;
 
-use dotenv::dotenv;
+use rocket::fairing::AdHoc;
 
 #[macro_use]
 extern crate validator_derive;
@@ -22,28 +20,54 @@ extern crate frank_jwt;
 
 mod api;
 mod database;
+mod error;
 mod schema;
 
 mod config;
 
+mod calendar;
 mod group;
+mod notification;
+mod poll;
+mod role;
 mod session;
 mod user;
+mod visibility;
+
+pub mod mail;
+mod reminder;
 
-mod mailgun;
+use config::AppConfig;
 
 pub fn rocket() -> rocket::Rocket {
-    dotenv().ok();
+    let env_file = config::load_env_file();
     rocket::ignite()
+        .attach(AdHoc::on_attach("App Config", |rocket| {
+            match env_file.and_then(|_| AppConfig::load(rocket.config())) {
+                Ok(app_config) => Ok(rocket.manage(app_config)),
+                Err(error) => {
+                    println!("Error loading config: {}", error);
+                    Err(rocket)
+                }
+            }
+        }))
         .mount(
             "/api/v1/users",
             routes![
                 user::routes::create,
                 user::routes::login,
+                user::routes::refresh_token,
+                user::routes::logout,
+                user::routes::logout_everywhere,
+                user::routes::request_password_reset,
+                user::routes::confirm_password_reset,
+                user::routes::verify_email,
+                user::routes::resend_verification,
                 user::routes::get_self,
                 user::routes::get_all,
                 user::routes::get_sessions_requests,
                 user::routes::get_sessions_invites,
+                user::routes::get_conflicts,
                 user::routes::get_groups_requests,
                 user::routes::get_groups_invites,
                 user::routes::patch_self,
@@ -52,11 +76,21 @@ pub fn rocket() -> rocket::Rocket {
                 user::routes::get_profile,
             ],
         )
+        .mount(
+            "/api/v1/users/self/notifications",
+            routes![
+                notification::routes::get_all,
+                notification::routes::get_unread_count,
+                notification::routes::mark_read,
+                notification::routes::mark_all_read,
+            ],
+        )
         .mount(
             "/api/v1/sessions",
             routes![
                 session::routes::create,
                 session::routes::get_session,
+                session::routes::get_session_ics,
                 session::routes::get_all,
                 session::routes::patch_session,
                 session::routes::patch_dm_of_session,
@@ -71,11 +105,24 @@ pub fn rocket() -> rocket::Rocket {
                 session::routes::is_user_waiting_to_join,
                 session::routes::leave_session,
                 session::routes::remove_user_from_session,
+                session::routes::set_role_in_session,
                 session::routes::get_guest_link,
                 session::routes::get_session_as_guest,
+                session::routes::rsvp_as_guest,
+                session::routes::guest_reminders,
+                session::routes::upgrade_guest,
                 session::routes::get_guests,
                 session::routes::remove_guest_from_session,
-                session::routes::is_user_invited_to_join
+                session::routes::rotate_guest_link,
+                session::routes::revoke_guest_link,
+                session::routes::get_guest_links,
+                session::routes::is_user_invited_to_join,
+                session::routes::patch_occurrence,
+                session::routes::cancel_occurrence,
+                session::routes::rsvp_to_session,
+                session::routes::rsvp_to_occurrence,
+                session::routes::get_headcount,
+                session::routes::get_occurrence_headcount
             ],
         )
         .mount(
@@ -96,9 +143,55 @@ pub fn rocket() -> rocket::Rocket {
                 group::routes::is_user_waiting_to_join,
                 group::routes::leave_group,
                 group::routes::remove_user_from_group,
+                group::routes::set_role_in_group,
+                group::routes::create_invite,
+                group::routes::get_invites,
+                group::routes::revoke_invite,
+                group::routes::join_group_with_invite,
                 group::routes::is_user_invited_to_join
             ],
         )
+        .mount(
+            "/api/v1/calendar",
+            routes![
+                calendar::routes::get_feed,
+                calendar::routes::create_feed,
+                calendar::routes::revoke_feed,
+                calendar::routes::get_feed_ics,
+            ],
+        )
+        .mount(
+            "/api/v1/polls",
+            routes![
+                poll::routes::create,
+                poll::routes::get_poll,
+                poll::routes::get_all,
+                poll::routes::vote,
+                poll::routes::close_poll,
+                poll::routes::delete_poll,
+            ],
+        )
+        .mount(
+            "/api/v1/admin/mails",
+            routes![mail::routes::get_all, mail::routes::retry_mail],
+        )
+        .register(catchers![
+            error::bad_request,
+            error::unauthorized,
+            error::not_found,
+            error::unprocessable_entity,
+            error::internal_error,
+        ])
         .attach(database::DnDAgendaDB::fairing())
+        .attach(AdHoc::on_launch("Reminder Scheduler", |rocket| {
+            if let Some(app_config) = rocket.state::<AppConfig>() {
+                reminder::start(app_config.clone())
+            }
+        }))
+        .attach(AdHoc::on_launch("Mail Worker", |rocket| {
+            if let Some(app_config) = rocket.state::<AppConfig>() {
+                mail::queue::start(app_config.clone())
+            }
+        }))
         .attach(rocket_cors::Cors::from_options(&rocket_cors::CorsOptions::default()).unwrap())
 }
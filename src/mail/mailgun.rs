@@ -0,0 +1,48 @@
+use super::{Mail, MailTransport};
+
+use crate::config::AppConfig;
+
+/// Sends mails through Mailgun's HTTP API
+pub struct Mailgun {
+    url: String,
+    api_key: String,
+}
+
+impl Mailgun {
+    /// configured by `AppConfig::mailgun_url` and `AppConfig::mailgun_api_key`
+    pub fn from_config(app_config: &AppConfig) -> Result<Mailgun, String> {
+        Ok(Mailgun {
+            url: app_config
+                .mailgun_url
+                .clone()
+                .ok_or_else(|| "MAILGUN_URL is not set".to_string())?,
+            api_key: app_config
+                .mailgun_api_key
+                .clone()
+                .ok_or_else(|| "MAILGUN_API_KEY is not set".to_string())?,
+        })
+    }
+}
+
+impl MailTransport for Mailgun {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let response = reqwest::blocking::Client::new()
+            .post(&self.url)
+            .basic_auth("api", Some(&self.api_key))
+            .form(&[
+                ("from", &mail.from),
+                ("to", &mail.to),
+                ("subject", &mail.subject),
+                ("html", &mail.html),
+                ("text", &mail.text),
+            ])
+            .send()
+            .map_err(|error| format!("{:#?}", error))?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Mailgun responded with {}", response.status()))
+        }
+    }
+}
@@ -1,7 +1,67 @@
 use crate::user::User;
+use diesel::pg::PgConnection;
 
-const MAILGUN_URL: &str = dotenv!("MAILGUN_URL");
-const MAILGUN_API_KEY: Option<&str> = Some(dotenv!("MAILGUN_API_KEY"));
+use chrono::{DateTime, Utc};
+
+use crate::config::AppConfig;
+use crate::config::{EMAIL_VERIFICATION_EXPIRY_HOURS, PASSWORD_RESET_EXPIRY_MINUTES};
+use crate::error::ApiError;
+
+use std::path::PathBuf;
+
+pub mod mailgun;
+pub mod outbox;
+pub mod queue;
+pub mod routes;
+pub mod smtp;
+
+const FROM: &str = "DnDearAll <no-reply@mg.dndearall.com>";
+
+/// A composed email, ready to be handed to a `MailTransport`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Something that can deliver mails, e.g. Mailgun's API, an SMTP server or a local outbox
+pub trait MailTransport: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+/// choose the transport from `AppConfig::mail_transport` (`mailgun`, `smtp`, `file` or
+/// `memory`), which `AppConfig::load` has already checked is one of them
+pub fn transport(app_config: &AppConfig) -> Box<dyn MailTransport> {
+    let configured: Result<Box<dyn MailTransport>, String> =
+        match app_config.mail_transport.as_str() {
+            "mailgun" => mailgun::Mailgun::from_config(app_config)
+                .map(|mailgun| Box::new(mailgun) as Box<dyn MailTransport>),
+            "smtp" => smtp::Smtp::from_config(app_config)
+                .map(|smtp| Box::new(smtp) as Box<dyn MailTransport>),
+            "file" => Ok(Box::new(outbox::FileOutbox::new(PathBuf::from(
+                &app_config.mail_outbox_dir,
+            )))),
+            "memory" => Ok(Box::new(outbox::MemoryOutbox)),
+            other => Err(format!("unknown MAIL_TRANSPORT {}", other)),
+        };
+
+    configured.unwrap_or_else(|error| {
+        println!("Error configuring mail transport: {}", error);
+        Box::new(Unconfigured(error))
+    })
+}
+
+/// used when the configured transport is invalid, so that every send reports why
+struct Unconfigured(String);
+
+impl MailTransport for Unconfigured {
+    fn send(&self, _mail: &Mail) -> Result<(), String> {
+        Err(self.0.clone())
+    }
+}
 
 #[derive(Debug)]
 pub enum MailType {
@@ -25,12 +85,20 @@ pub fn send_mail(
     parent_name: String,
     parent_slug: String,
     parent_owner: User,
-) -> Result<u16, String> {
-  println!("sending email");
+    app_config: &AppConfig,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    // only email addresses their owners have verified
+    let recipient = match mail_type {
+        MailType::SessionInviteReceived | MailType::GroupInviteReceived => &user,
+        _ => &parent_owner,
+    };
+    if !recipient.email_verified {
+        return Ok(());
+    }
+
     let mut to = parent_owner.email;
 
-    let client = reqwest::blocking::Client::new();
-    let from = "DnDearAll <no-reply@mg.dndearall.com>";
     let subject: &str;
     let message: &str;
     let html: String;
@@ -42,6 +110,7 @@ pub fn send_mail(
             subject = "You've Been Invited to a Session";
             message = "Invited you";
             html = compose_html_email(
+                app_config,
                 &parent_owner.username,
                 message,
                 ParentType::Session,
@@ -49,6 +118,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &parent_owner.username,
                 message,
                 ParentType::Session,
@@ -60,6 +130,7 @@ pub fn send_mail(
             subject = "Your Session Invite was Accepted";
             message = "Accepted your Invite";
             html = compose_html_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Session,
@@ -67,6 +138,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Session,
@@ -78,6 +150,7 @@ pub fn send_mail(
             subject = "Your Session Invite was Declined";
             message = "Declined your Invite";
             html = compose_html_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Session,
@@ -85,6 +158,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Session,
@@ -97,6 +171,7 @@ pub fn send_mail(
             subject = "You've Been Invited to a Group";
             message = "Invited you";
             html = compose_html_email(
+                app_config,
                 &parent_owner.username,
                 message,
                 ParentType::Group,
@@ -104,6 +179,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &parent_owner.username,
                 message,
                 ParentType::Group,
@@ -115,6 +191,7 @@ pub fn send_mail(
             subject = "Your Group Invite was Accepted";
             message = "Accepted your Invite";
             html = compose_html_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Group,
@@ -122,6 +199,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Group,
@@ -133,6 +211,7 @@ pub fn send_mail(
             subject = "Your Group Invite was Declined";
             message = "Declined your Invite";
             html = compose_html_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Group,
@@ -140,6 +219,7 @@ pub fn send_mail(
                 &parent_slug,
             );
             text = compose_plaintext_email(
+                app_config,
                 &user.username,
                 message,
                 ParentType::Group,
@@ -149,25 +229,137 @@ pub fn send_mail(
         }
     };
 
-    client
-        .post(MAILGUN_URL)
-        .basic_auth("api", MAILGUN_API_KEY)
-        .form(&[
-            ("from", from),
-            ("to", &to),
-            ("subject", subject),
-            ("html", &html),
-            ("text", &text),
-        ])
-        .send()
-        .map(|res| match res.status().as_u16() {
-            200 => 200,
-            _ => res.status().as_u16(),
-        })
-        .map_err(|res| format!("{:#?}", res))
+    queue::OutboxMail::enqueue(
+        &Mail {
+            from: FROM.to_string(),
+            to,
+            subject: subject.to_string(),
+            html,
+            text,
+        },
+        connection,
+    )?;
+
+    Ok(())
+}
+
+/// remind someone (a member or a guest) that a session is coming up
+pub fn send_reminder(
+    to: &str,
+    name: &str,
+    guest: bool,
+    session_title: &str,
+    session_slug: &str,
+    session_date: DateTime<Utc>,
+    app_config: &AppConfig,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    let subject = format!("Reminder: {} is coming up", session_title);
+    let session_link = format!("{}/#/sessions/{}", app_config.app_url, session_slug);
+    let when = session_date.format("%A %e %B at %H:%M UTC").to_string();
+    // guests have no settings, so they turn reminders off from their guest link instead
+    let opt_out = if guest {
+        "You can turn off these reminders from your guest link."
+    } else {
+        "You can turn off these reminders in your DnDearAll settings."
+    };
+
+    let html = format!(
+        "<p>Hi {},</p><p><a href=\"{}\">{}</a> starts on {}.</p><p>{}</p>",
+        name, session_link, session_title, when, opt_out
+    );
+    let text = format!(
+        "Hi {},\n\n{} starts on {}.\n\nSee\n{} ( {} )\n\n{}",
+        name, session_title, when, session_title, session_link, opt_out
+    );
+
+    queue::OutboxMail::enqueue(
+        &Mail {
+            from: FROM.to_string(),
+            to: to.to_string(),
+            subject,
+            html,
+            text,
+        },
+        connection,
+    )?;
+
+    Ok(())
+}
+
+/// send someone a link to set a new password, e.g. after they forgot it
+pub fn send_password_reset(
+    to: &str,
+    name: &str,
+    token: &str,
+    app_config: &AppConfig,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    let subject = "Reset your DnDearAll password".to_string();
+    let reset_link = format!("{}/#/password-reset?token={}", app_config.app_url, token);
+
+    let html = format!(
+        "<p>Hi {},</p><p>Someone asked to reset your DnDearAll password. \
+         <a href=\"{}\">Choose a new password</a> within {} minutes.</p>\
+         <p>If it was not you, you can ignore this email.</p>",
+        name, reset_link, PASSWORD_RESET_EXPIRY_MINUTES
+    );
+    let text = format!(
+        "Hi {},\n\nSomeone asked to reset your DnDearAll password. Choose a new password within {} minutes at\n{}\n\nIf it was not you, you can ignore this email.",
+        name, PASSWORD_RESET_EXPIRY_MINUTES, reset_link
+    );
+
+    queue::OutboxMail::enqueue(
+        &Mail {
+            from: FROM.to_string(),
+            to: to.to_string(),
+            subject,
+            html,
+            text,
+        },
+        connection,
+    )?;
+
+    Ok(())
+}
+
+/// send someone a link that proves they own their email address
+pub fn send_verification(
+    to: &str,
+    name: &str,
+    token: &str,
+    app_config: &AppConfig,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    let subject = "Verify your DnDearAll email".to_string();
+    let verify_link = format!("{}/#/verify-email?token={}", app_config.app_url, token);
+
+    let html = format!(
+        "<p>Hi {},</p><p>Please <a href=\"{}\">verify your email address</a> \
+         within {} hours, so that we can send you invites and reminders.</p>",
+        name, verify_link, EMAIL_VERIFICATION_EXPIRY_HOURS
+    );
+    let text = format!(
+        "Hi {},\n\nPlease verify your email address within {} hours, so that we can send you invites and reminders:\n{}",
+        name, EMAIL_VERIFICATION_EXPIRY_HOURS, verify_link
+    );
+
+    queue::OutboxMail::enqueue(
+        &Mail {
+            from: FROM.to_string(),
+            to: to.to_string(),
+            subject,
+            html,
+            text,
+        },
+        connection,
+    )?;
+
+    Ok(())
 }
 
 fn compose_html_email(
+    app_config: &AppConfig,
     user_name: &str,
     message: &str,
     parent_type: ParentType,
@@ -178,10 +370,10 @@ fn compose_html_email(
         ParentType::Session => "sessions",
         ParentType::Group => "groups",
     };
-    let parent_link = format!("https://dndearall.com/#/{}/{}", parent_group, parent_slug);
+    let parent_link = format!("{}/#/{}/{}", app_config.app_url, parent_group, parent_slug);
     let unsubscribe_link = format!(
-        "https://dndearall.com/#/unsubscribe?token={}",
-        "generateUnsubscribeToken"
+        "{}/#/unsubscribe?token={}",
+        app_config.app_url, "generateUnsubscribeToken"
     );
     return format!("<!DOCTYPE html
     PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">
@@ -358,6 +550,7 @@ fn compose_html_email(
 }
 
 fn compose_plaintext_email(
+    app_config: &AppConfig,
     user_name: &str,
     message: &str,
     parent_type: ParentType,
@@ -368,10 +561,10 @@ fn compose_plaintext_email(
         ParentType::Session => "sessions",
         ParentType::Group => "groups",
     };
-    let parent_link = format!("https://dndearall.com/#/{}/{}", parent_group, parent_slug);
+    let parent_link = format!("{}/#/{}/{}", app_config.app_url, parent_group, parent_slug);
     let unsubscribe_link = format!(
-        "https://dndearall.com/#/unsubscribe?token={}",
-        "generateUnsubscribeToken"
+        "{}/#/unsubscribe?token={}",
+        app_config.app_url, "generateUnsubscribeToken"
     );
     return format!(
         "*****************************************
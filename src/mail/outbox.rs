@@ -0,0 +1,65 @@
+use super::{Mail, MailTransport};
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+lazy_static! {
+    static ref MEMORY_OUTBOX: Mutex<Vec<Mail>> = Mutex::new(Vec::new());
+}
+
+/// Writes every mail to a JSON file in a directory instead of sending it, for local development
+pub struct FileOutbox {
+    dir: PathBuf,
+}
+
+impl FileOutbox {
+    pub fn new(dir: PathBuf) -> FileOutbox {
+        FileOutbox { dir }
+    }
+}
+
+impl MailTransport for FileOutbox {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|error| format!("{:#?}", error))?;
+
+        let json = serde_json::to_string_pretty(mail).map_err(|error| format!("{:#?}", error))?;
+        let file_name = format!(
+            "{}-{}.json",
+            Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            slug::slugify(&mail.to)
+        );
+
+        fs::write(self.dir.join(file_name), json).map_err(|error| format!("{:#?}", error))
+    }
+}
+
+/// Keeps every mail in memory instead of sending it, so that tests can assert against them
+pub struct MemoryOutbox;
+
+impl MemoryOutbox {
+    /// every mail sent so far
+    pub fn sent() -> Vec<Mail> {
+        MEMORY_OUTBOX
+            .lock()
+            .map(|outbox| outbox.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn clear() {
+        if let Ok(mut outbox) = MEMORY_OUTBOX.lock() {
+            outbox.clear();
+        }
+    }
+}
+
+impl MailTransport for MemoryOutbox {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        MEMORY_OUTBOX
+            .lock()
+            .map(|mut outbox| outbox.push(mail.clone()))
+            .map_err(|error| format!("{:#?}", error))
+    }
+}
@@ -0,0 +1,358 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::mail_outbox;
+use diesel::prelude::*;
+
+use super::{transport, Mail, MailTransport};
+
+use crate::config::AppConfig;
+use crate::config::{
+    DEFAULT_LIMIT, MAIL_BATCH_SIZE, MAIL_CLAIM_SECONDS, MAIL_MAX_ATTEMPTS, MAIL_RETRY_BASE_SECONDS,
+    MAIL_RETRY_MAX_SECONDS, MAIL_WORKER_INTERVAL_SECONDS,
+};
+use chrono::{DateTime, Duration, Utc};
+
+use std::thread;
+use std::time;
+
+use crate::error::ApiError;
+
+use crate::database::{worker_pool, Paginate};
+
+/// A mail waiting in (or delivered from) the outbox. Mails are queued here rather than sent
+/// straight away, so that a mail provider outage delays them instead of losing them.
+#[table_name = "mail_outbox"]
+#[derive(Identifiable, Queryable, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMail {
+    pub id: i32,
+    pub from_address: String,
+    pub to_address: String,
+    pub subject: String,
+    #[serde(skip_serializing)]
+    pub html: String,
+    #[serde(skip_serializing)]
+    pub text: String,
+    /// one of "pending", "sent" or "dead" (gave up after `MAIL_MAX_ATTEMPTS`)
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[table_name = "mail_outbox"]
+#[derive(Insertable)]
+pub struct InsertableOutboxMail {
+    pub from_address: String,
+    pub to_address: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "mail_outbox"]
+#[changeset_options(treat_none_as_null = "true")]
+struct UpdateOutboxMail {
+    status: String,
+    attempts: i32,
+    last_error: Option<String>,
+    next_attempt_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromForm, Default)]
+pub struct FindMails {
+    status: Option<String>,
+    pub limit: Option<i64>,
+    pub page: Option<i64>,
+}
+
+impl OutboxMail {
+    fn to_mail(&self) -> Mail {
+        Mail {
+            from: self.from_address.clone(),
+            to: self.to_address.clone(),
+            subject: self.subject.clone(),
+            html: self.html.clone(),
+            text: self.text.clone(),
+        }
+    }
+
+    /// queue a mail for the mail worker to deliver
+    pub fn enqueue(mail: &Mail, connection: &PgConnection) -> Result<OutboxMail, ApiError> {
+        let new_mail = &InsertableOutboxMail {
+            from_address: mail.from.clone(),
+            to_address: mail.to.clone(),
+            subject: mail.subject.clone(),
+            html: mail.html.clone(),
+            text: mail.text.clone(),
+        };
+
+        diesel::insert_into(mail_outbox::table)
+            .values(new_mail)
+            .get_result::<OutboxMail>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not queue the mail".to_string())
+            })
+    }
+
+    pub fn read(
+        params: &FindMails,
+        connection: &PgConnection,
+    ) -> Result<(Vec<OutboxMail>, i64), ApiError> {
+        let mut query = mail_outbox::table
+            .order(mail_outbox::id.desc())
+            .into_boxed();
+
+        if let Some(ref status) = params.status {
+            query = query.filter(mail_outbox::status.eq(status))
+        }
+
+        query
+            .paginate(params.page.unwrap_or(1))
+            .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
+            .load_and_count_pages::<OutboxMail>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Mails not found".to_string())
+            })
+    }
+
+    pub fn find(mail_id: i32, connection: &PgConnection) -> Result<OutboxMail, ApiError> {
+        mail_outbox::table
+            .find(mail_id)
+            .first::<OutboxMail>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Mail not found".to_string())
+            })
+    }
+
+    /// give a failed mail a fresh set of attempts, starting straight away
+    pub fn retry(mail: &OutboxMail, connection: &PgConnection) -> Result<OutboxMail, ApiError> {
+        if mail.status == "sent" {
+            return Err(ApiError::Conflict(
+                "This mail has already been sent".to_string(),
+            ));
+        }
+
+        let retried_mail = &UpdateOutboxMail {
+            status: "pending".to_string(),
+            attempts: 0,
+            last_error: mail.last_error.clone(),
+            next_attempt_at: Utc::now(),
+            sent_at: None,
+        };
+
+        diesel::update(mail_outbox::table.find(mail.id))
+            .set(retried_mail)
+            .get_result::<OutboxMail>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not retry the mail".to_string())
+            })
+    }
+
+    /// attempt to deliver every pending mail that is due. The mails are claimed in a short
+    /// transaction first, so that nothing is locked while they are being sent, and the result of
+    /// each attempt is recorded on its own as soon as it is known.
+    pub fn deliver_due(
+        now: DateTime<Utc>,
+        transport: &dyn MailTransport,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let due_mails = OutboxMail::claim_due(now, connection).map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not claim the queued mails".to_string())
+        })?;
+
+        for mail in due_mails {
+            let attempt = attempt_result(&mail, transport.send(&mail.to_mail()), now);
+
+            if let Err(error) = diesel::update(mail_outbox::table.find(mail.id))
+                .set(&attempt)
+                .execute(connection)
+            {
+                println!("Error recording delivery of mail {}: {:#?}", mail.id, error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// take the pending mails that are due for this worker, by pushing their next attempt back
+    /// by `MAIL_CLAIM_SECONDS`. If the worker stops before recording how an attempt went, the
+    /// mail is tried again once the claim runs out.
+    fn claim_due(
+        now: DateTime<Utc>,
+        connection: &PgConnection,
+    ) -> Result<Vec<OutboxMail>, diesel::result::Error> {
+        connection.transaction(|| {
+            // skip mails locked by another worker, so that no mail is claimed twice
+            let due_mails = mail_outbox::table
+                .filter(mail_outbox::status.eq("pending"))
+                .filter(mail_outbox::next_attempt_at.le(now))
+                .order(mail_outbox::next_attempt_at.asc())
+                .limit(MAIL_BATCH_SIZE)
+                .for_update()
+                .skip_locked()
+                .load::<OutboxMail>(connection)?;
+
+            let mail_ids = due_mails.iter().map(|mail| mail.id).collect::<Vec<_>>();
+            diesel::update(mail_outbox::table.filter(mail_outbox::id.eq_any(mail_ids)))
+                .set(mail_outbox::next_attempt_at.eq(now + Duration::seconds(MAIL_CLAIM_SECONDS)))
+                .execute(connection)?;
+
+            Ok(due_mails)
+        })
+    }
+}
+
+/// what to record after an attempt at delivering a mail, backing off exponentially after each
+/// failure and dead-lettering the mail once it runs out of attempts
+fn attempt_result(
+    mail: &OutboxMail,
+    result: Result<(), String>,
+    now: DateTime<Utc>,
+) -> UpdateOutboxMail {
+    let attempts = mail.attempts + 1;
+
+    match result {
+        Ok(()) => UpdateOutboxMail {
+            status: "sent".to_string(),
+            attempts,
+            last_error: None,
+            next_attempt_at: mail.next_attempt_at,
+            sent_at: Some(Utc::now()),
+        },
+        Err(error) => UpdateOutboxMail {
+            status: if attempts >= MAIL_MAX_ATTEMPTS {
+                "dead".to_string()
+            } else {
+                "pending".to_string()
+            },
+            attempts,
+            last_error: Some(error),
+            next_attempt_at: now + backoff(attempts),
+            sent_at: None,
+        },
+    }
+}
+
+/// how long to wait after the given number of failed attempts
+fn backoff(attempts: i32) -> Duration {
+    let exponent = (attempts - 1).max(0) as u32;
+    let seconds = MAIL_RETRY_BASE_SECONDS.saturating_mul(2_i64.saturating_pow(exponent));
+
+    Duration::seconds(seconds.min(MAIL_RETRY_MAX_SECONDS))
+}
+
+/// start the background thread that delivers queued mails
+pub fn start(app_config: AppConfig) {
+    let transport = transport(&app_config);
+
+    thread::spawn(move || {
+        let pool = worker_pool(&app_config.database_url);
+
+        loop {
+            match pool.get() {
+                Ok(connection) => {
+                    if let Err(response) =
+                        OutboxMail::deliver_due(Utc::now(), &*transport, &connection)
+                    {
+                        println!("Error delivering mails: {:#?}", response);
+                    }
+                }
+                Err(error) => println!("Error: {:#?}", error),
+            }
+
+            thread::sleep(time::Duration::from_secs(MAIL_WORKER_INTERVAL_SECONDS));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_mail(attempts: i32) -> OutboxMail {
+        let now = Utc::now();
+        OutboxMail {
+            id: 1,
+            from_address: "from@example.com".to_string(),
+            to_address: "to@example.com".to_string(),
+            subject: "subject".to_string(),
+            html: "<p>html</p>".to_string(),
+            text: "text".to_string(),
+            status: "pending".to_string(),
+            attempts,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+            sent_at: None,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_after_each_attempt() {
+        assert_eq!(backoff(1), Duration::seconds(MAIL_RETRY_BASE_SECONDS));
+        assert_eq!(backoff(2), Duration::seconds(MAIL_RETRY_BASE_SECONDS * 2));
+        assert_eq!(backoff(3), Duration::seconds(MAIL_RETRY_BASE_SECONDS * 4));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff(30), Duration::seconds(MAIL_RETRY_MAX_SECONDS));
+        assert_eq!(
+            backoff(i32::max_value()),
+            Duration::seconds(MAIL_RETRY_MAX_SECONDS)
+        );
+    }
+
+    #[test]
+    fn delivered_mails_are_sent() {
+        let attempt = attempt_result(&pending_mail(2), Ok(()), Utc::now());
+
+        assert_eq!(attempt.status, "sent");
+        assert_eq!(attempt.attempts, 3);
+        assert_eq!(attempt.last_error, None);
+        assert!(attempt.sent_at.is_some());
+    }
+
+    #[test]
+    fn failed_mails_are_retried_later() {
+        let now = Utc::now();
+        let attempt = attempt_result(&pending_mail(0), Err("timed out".to_string()), now);
+
+        assert_eq!(attempt.status, "pending");
+        assert_eq!(attempt.attempts, 1);
+        assert_eq!(attempt.last_error, Some("timed out".to_string()));
+        assert_eq!(attempt.next_attempt_at, now + backoff(1));
+        assert_eq!(attempt.sent_at, None);
+    }
+
+    #[test]
+    fn mails_are_dead_lettered_after_the_last_attempt() {
+        let now = Utc::now();
+
+        let attempt = attempt_result(
+            &pending_mail(MAIL_MAX_ATTEMPTS - 2),
+            Err("".to_string()),
+            now,
+        );
+        assert_eq!(attempt.status, "pending");
+
+        let attempt = attempt_result(
+            &pending_mail(MAIL_MAX_ATTEMPTS - 1),
+            Err("".to_string()),
+            now,
+        );
+        assert_eq!(attempt.status, "dead");
+        assert_eq!(attempt.attempts, MAIL_MAX_ATTEMPTS);
+    }
+}
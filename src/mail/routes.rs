@@ -0,0 +1,66 @@
+use crate::database::DnDAgendaDB;
+use crate::mail::queue;
+use diesel::pg::PgConnection;
+
+use rocket::request::Form;
+
+use crate::api::ApiResponse;
+use crate::api::Auth;
+use crate::error::ApiError;
+use rocket::http::Status;
+
+use crate::user::User;
+
+/// only site admins can look at or retry other people's mail
+fn check_site_admin(auth: &Auth, connection: &PgConnection) -> Result<(), ApiError> {
+    if User::find(auth.id, connection)?.site_admin {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden("you are not a site admin".to_string()))
+    }
+}
+
+#[get("/?<params..>")]
+pub fn get_all(
+    auth: Result<Auth, ApiError>,
+    params: Form<queue::FindMails>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            check_site_admin(&auth, &connection)?;
+
+            queue::OutboxMail::read(&params, &connection)
+                .map(|(mails, pages_count)| ApiResponse {
+                    json: json!({ "mails": mails, "mailsPagesCount": pages_count }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// send a dead (or still pending) mail again straight away
+#[post("/<mail_id>/retry")]
+pub fn retry_mail(
+    auth: Result<Auth, ApiError>,
+    mail_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            check_site_admin(&auth, &connection)?;
+
+            let mail = queue::OutboxMail::find(mail_id, &connection)?;
+
+            queue::OutboxMail::retry(&mail, &connection)
+                .map(|mail| ApiResponse {
+                    json: json!({ "mail": mail }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
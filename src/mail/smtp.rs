@@ -0,0 +1,60 @@
+use super::{Mail, MailTransport};
+
+use lettre::smtp::authentication::Credentials;
+use lettre::{SmtpClient, Transport};
+use lettre_email::{EmailBuilder, Mailbox};
+
+use crate::config::AppConfig;
+
+/// Sends mails through an SMTP server, over TLS on the submissions port
+pub struct Smtp {
+    host: String,
+    credentials: Option<(String, String)>,
+}
+
+impl Smtp {
+    pub fn from_config(app_config: &AppConfig) -> Result<Smtp, String> {
+        Ok(Smtp {
+            host: app_config
+                .smtp_host
+                .clone()
+                .ok_or_else(|| "SMTP_HOST is not set".to_string())?,
+            credentials: app_config.smtp_credentials.clone(),
+        })
+    }
+}
+
+impl MailTransport for Smtp {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let email = EmailBuilder::new()
+            .from(mailbox(&mail.from))
+            .to(mailbox(&mail.to))
+            .subject(mail.subject.as_str())
+            .alternative(mail.html.as_str(), mail.text.as_str())
+            .build()
+            .map_err(|error| format!("{:#?}", error))?;
+
+        let mut client =
+            SmtpClient::new_simple(&self.host).map_err(|error| format!("{:#?}", error))?;
+        if let Some((ref username, ref password)) = self.credentials {
+            client = client.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        client
+            .transport()
+            .send(email.into())
+            .map(|_| ())
+            .map_err(|error| format!("{:#?}", error))
+    }
+}
+
+/// parse an address written as either `address` or `Name <address>`
+fn mailbox(address: &str) -> Mailbox {
+    match (address.find('<'), address.rfind('>')) {
+        (Some(start), Some(end)) if start < end => Mailbox::new_with_name(
+            address[..start].trim().to_string(),
+            address[start + 1..end].trim().to_string(),
+        ),
+        _ => Mailbox::new(address.trim().to_string()),
+    }
+}
@@ -0,0 +1,245 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::notifications;
+use diesel::prelude::*;
+
+use chrono::{DateTime, Utc};
+
+pub mod routes;
+
+use crate::error::ApiError;
+
+use crate::config::DEFAULT_LIMIT;
+
+use crate::database::Paginate;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    SessionRequestReceived,
+    SessionRequestAccepted,
+    SessionRequestDenied,
+    SessionInviteReceived,
+    SessionInviteAccepted,
+    SessionInviteDeclined,
+    SessionUserLeft,
+    SessionUserRemoved,
+    SessionDMChanged,
+    SessionGuestUpgraded,
+    GroupRequestReceived,
+    GroupRequestAccepted,
+    GroupRequestDenied,
+    GroupInviteReceived,
+    GroupInviteAccepted,
+    GroupInviteDeclined,
+    GroupUserLeft,
+    GroupUserRemoved,
+    GroupAdminChanged,
+    GroupJoinedWithInvite,
+    GroupUserJoined,
+}
+
+impl NotificationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::SessionRequestReceived => "session_request_received",
+            NotificationKind::SessionRequestAccepted => "session_request_accepted",
+            NotificationKind::SessionRequestDenied => "session_request_denied",
+            NotificationKind::SessionInviteReceived => "session_invite_received",
+            NotificationKind::SessionInviteAccepted => "session_invite_accepted",
+            NotificationKind::SessionInviteDeclined => "session_invite_declined",
+            NotificationKind::SessionUserLeft => "session_user_left",
+            NotificationKind::SessionUserRemoved => "session_user_removed",
+            NotificationKind::SessionDMChanged => "session_dm_changed",
+            NotificationKind::SessionGuestUpgraded => "session_guest_upgraded",
+            NotificationKind::GroupRequestReceived => "group_request_received",
+            NotificationKind::GroupRequestAccepted => "group_request_accepted",
+            NotificationKind::GroupRequestDenied => "group_request_denied",
+            NotificationKind::GroupInviteReceived => "group_invite_received",
+            NotificationKind::GroupInviteAccepted => "group_invite_accepted",
+            NotificationKind::GroupInviteDeclined => "group_invite_declined",
+            NotificationKind::GroupUserLeft => "group_user_left",
+            NotificationKind::GroupUserRemoved => "group_user_removed",
+            NotificationKind::GroupAdminChanged => "group_admin_changed",
+            NotificationKind::GroupJoinedWithInvite => "group_joined_with_invite",
+            NotificationKind::GroupUserJoined => "group_user_joined",
+        }
+    }
+}
+
+#[table_name = "notifications"]
+#[derive(Identifiable, Queryable, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub user_id: i32,
+    pub kind: String,
+    /// the user whose action caused the notification
+    pub actor_id: Option<i32>,
+    pub session_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[table_name = "notifications"]
+#[derive(Insertable)]
+pub struct InsertableNotification {
+    pub user_id: i32,
+    pub kind: String,
+    pub actor_id: Option<i32>,
+    pub session_id: Option<i32>,
+    pub group_id: Option<i32>,
+}
+
+#[derive(FromForm, Default)]
+pub struct FindNotifications {
+    /// only show notifications that have not been read yet
+    unread: Option<bool>,
+    pub limit: Option<i64>,
+    pub page: Option<i64>,
+}
+
+impl Notification {
+    /// notify `user_id` about something `actor_id` did in a session
+    pub fn notify_session(
+        user_id: i32,
+        kind: NotificationKind,
+        actor_id: i32,
+        session_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        InsertableNotification::create(
+            InsertableNotification {
+                user_id,
+                kind: kind.as_str().to_string(),
+                actor_id: Some(actor_id),
+                session_id: Some(session_id),
+                group_id: None,
+            },
+            connection,
+        )
+    }
+
+    /// notify `user_id` about something `actor_id` did in a group
+    pub fn notify_group(
+        user_id: i32,
+        kind: NotificationKind,
+        actor_id: i32,
+        group_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        InsertableNotification::create(
+            InsertableNotification {
+                user_id,
+                kind: kind.as_str().to_string(),
+                actor_id: Some(actor_id),
+                session_id: None,
+                group_id: Some(group_id),
+            },
+            connection,
+        )
+    }
+
+    pub fn read(
+        params: &FindNotifications,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(Vec<Notification>, i64), ApiError> {
+        let mut query = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .order(notifications::id.desc())
+            .into_boxed();
+
+        if let Some(true) = params.unread {
+            query = query.filter(notifications::read_at.is_null())
+        }
+
+        query
+            .paginate(params.page.unwrap_or(1))
+            .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
+            .load_and_count_pages::<Notification>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Notifications not found".to_string())
+            })
+    }
+
+    pub fn unread_count(user_id: i32, connection: &PgConnection) -> Result<i64, ApiError> {
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null())
+            .count()
+            .get_result::<i64>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Notifications not found".to_string())
+            })
+    }
+
+    pub fn mark_read(
+        notification_id: i32,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Notification, ApiError> {
+        let notification = notifications::table
+            .filter(notifications::id.eq(notification_id))
+            .filter(notifications::user_id.eq(user_id))
+            .first::<Notification>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Notification not found".to_string())
+            })?;
+
+        // keep the original read time if it was already read
+        if notification.read_at.is_some() {
+            return Ok(notification);
+        }
+
+        diesel::update(&notification)
+            .set(notifications::read_at.eq(Utc::now()))
+            .get_result::<Notification>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not mark the notification as read".to_string())
+            })
+    }
+
+    /// returns how many notifications were marked as read
+    pub fn mark_all_read(user_id: i32, connection: &PgConnection) -> Result<usize, ApiError> {
+        diesel::update(
+            notifications::table
+                .filter(notifications::user_id.eq(user_id))
+                .filter(notifications::read_at.is_null()),
+        )
+        .set(notifications::read_at.eq(Utc::now()))
+        .execute(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not mark notifications as read".to_string())
+        })
+    }
+}
+
+impl InsertableNotification {
+    pub fn create(
+        notification: InsertableNotification,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        // nobody needs to be told about their own actions
+        if notification.actor_id == Some(notification.user_id) {
+            return Ok(());
+        }
+
+        diesel::insert_into(notifications::table)
+            .values(&notification)
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not create the notification".to_string())
+            })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use crate::database::DnDAgendaDB;
+use crate::notification;
+
+use rocket::request::Form;
+
+use crate::api::ApiResponse;
+use crate::api::Auth;
+use crate::error::ApiError;
+use rocket::http::Status;
+
+#[get("/?<params..>")]
+pub fn get_all(
+    auth: Result<Auth, ApiError>,
+    params: Form<notification::FindNotifications>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => notification::Notification::read(&params, auth.id, &connection)
+            .map(|(notifications, pages_count)| ApiResponse {
+                json: json!({ "notifications": notifications, "notificationsPagesCount": pages_count }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[get("/unread")]
+pub fn get_unread_count(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => notification::Notification::unread_count(auth.id, &connection)
+            .map(|unread_count| ApiResponse {
+                json: json!({ "unreadCount": unread_count }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[post("/<notification_id>/read")]
+pub fn mark_read(
+    auth: Result<Auth, ApiError>,
+    notification_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => notification::Notification::mark_read(notification_id, auth.id, &connection)
+            .map(|notification| ApiResponse {
+                json: json!({ "notification": notification }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[post("/read")]
+pub fn mark_all_read(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => notification::Notification::mark_all_read(auth.id, &connection)
+            .map(|marked_count| ApiResponse {
+                json: json!({ "message": "marked notifications as read successfully", "markedCount": marked_count }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
@@ -0,0 +1,444 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::{
+    groups, groups_users, polls, polls_options, polls_votes, sessions_users, users,
+};
+use diesel::prelude::*;
+
+use crate::group::role::GroupPermission;
+use crate::group::{Group, GroupUser};
+use crate::role::Role;
+use crate::session::role::SessionRole;
+use crate::session::{self, unique_slug, InsertableSession, InsertableSessionUser, SessionJson};
+use crate::user::{Profile, User};
+
+use crate::config::DATE_FORMAT;
+use chrono::{DateTime, Utc};
+
+pub mod routes;
+
+use crate::error::ApiError;
+
+use crate::config::{DEFAULT_LIMIT, DEFAULT_SESSION_DURATION_MINUTES};
+
+use crate::database::Paginate;
+
+/// A scheduling poll: a DM proposes several dates for a group's next session, members vote on
+/// them, and the DM then closes the poll by turning the winning date into a real session.
+#[table_name = "polls"]
+#[belongs_to(Group)]
+#[derive(Associations, Debug, Identifiable, Serialize, Deserialize, Queryable)]
+pub struct Poll {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub dm: i32,
+    pub colour: String,
+    pub group_id: i32,
+    /// the session the poll was turned into, once it is closed
+    pub session_id: Option<i32>,
+}
+
+#[table_name = "polls"]
+#[derive(Serialize, Deserialize, Insertable)]
+pub struct InsertablePoll {
+    pub title: String,
+    pub description: String,
+    pub dm: i32,
+    pub colour: String,
+    pub group_id: i32,
+}
+
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
+#[belongs_to(Poll)]
+#[table_name = "polls_options"]
+pub struct PollOption {
+    pub id: i32,
+    pub poll_id: i32,
+    pub option_date: DateTime<Utc>,
+}
+
+#[table_name = "polls_options"]
+#[derive(Serialize, Deserialize, Insertable)]
+pub struct InsertablePollOption {
+    pub poll_id: i32,
+    pub option_date: DateTime<Utc>,
+}
+
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
+#[belongs_to(PollOption, foreign_key = "option_id")]
+#[belongs_to(User)]
+#[primary_key(option_id, user_id)]
+#[table_name = "polls_votes"]
+pub struct PollVote {
+    pub option_id: i32,
+    pub user_id: i32,
+    /// one of "yes", "maybe" or "no"
+    pub vote: String,
+}
+
+#[table_name = "polls_votes"]
+#[derive(Serialize, Deserialize, Insertable, AsChangeset)]
+pub struct InsertablePollVote {
+    pub option_id: i32,
+    pub user_id: i32,
+    pub vote: String,
+}
+
+#[derive(FromForm, Default)]
+pub struct FindPolls {
+    group: Option<i32>,
+    pub limit: Option<i64>,
+    pub page: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PollJson {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub dm: Profile,
+    pub colour: String,
+    pub group: Group,
+    pub session_id: Option<i32>,
+    pub options: Vec<PollOptionJson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PollOptionJson {
+    pub id: i32,
+    pub option_date: String,
+    pub yes: Vec<Profile>,
+    pub maybe: Vec<Profile>,
+    pub no: Vec<Profile>,
+}
+
+impl Poll {
+    /// whether the user can close or delete the poll: its DM, or anyone allowed to manage every
+    /// session in its group
+    pub fn can_manage(&self, user_id: i32, connection: &PgConnection) -> Result<bool, ApiError> {
+        if self.dm == user_id {
+            return Ok(true);
+        }
+
+        GroupUser::has_permission(
+            self.group_id,
+            user_id,
+            GroupPermission::ManageSessions,
+            connection,
+        )
+    }
+
+    pub fn attach(&self, dm: Profile, group: Group, options: Vec<PollOptionJson>) -> PollJson {
+        PollJson {
+            id: self.id,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            dm,
+            colour: self.colour.clone(),
+            group,
+            session_id: self.session_id,
+            options,
+        }
+    }
+
+    /// read the polls of every group the user is a member of
+    pub fn read(
+        params: &FindPolls,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(Vec<PollJson>, i64), ApiError> {
+        let users_groups = groups_users::table
+            .filter(groups_users::columns::user_id.eq(user_id))
+            .filter(groups_users::columns::admin_accepted.eq(true))
+            .filter(groups_users::columns::user_accepted.eq(true))
+            .select(groups_users::columns::group_id);
+
+        let mut query = polls::table
+            .filter(polls::group_id.eq_any(users_groups))
+            .inner_join(users::table) // dm details
+            .inner_join(groups::table)
+            .select((polls::all_columns, users::all_columns, groups::all_columns))
+            .order(polls::id.desc())
+            .into_boxed();
+
+        if let Some(group_id) = params.group {
+            query = query.filter(polls::group_id.eq(group_id));
+        }
+
+        let (polls_dms_and_groups, pages_count) = query
+            .paginate(params.page.unwrap_or(1))
+            .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
+            .load_and_count_pages::<(Poll, User, Group)>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Polls not found".to_string())
+            })?;
+
+        polls_dms_and_groups
+            .into_iter()
+            .map(|(poll, dm, group)| populate(&poll, dm.to_profile(), group, connection))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|poll_jsons| (poll_jsons, pages_count))
+    }
+
+    pub fn find(poll_id: i32, connection: &PgConnection) -> Result<Poll, ApiError> {
+        polls::table
+            .find(poll_id)
+            .first::<Poll>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Poll not found".to_string())
+            })
+    }
+
+    pub fn find_as_json(poll: &Poll, connection: &PgConnection) -> Result<PollJson, ApiError> {
+        let dm = User::find(poll.dm, connection)
+            .map(|user| user.to_profile())
+            .map_err(|response| response)?;
+        let group = Group::find(poll.group_id, connection).map_err(|response| response)?;
+
+        populate(poll, dm, group, connection)
+    }
+
+    pub fn find_option(
+        poll: &Poll,
+        option_id: i32,
+        connection: &PgConnection,
+    ) -> Result<PollOption, ApiError> {
+        PollOption::belonging_to(poll)
+            .filter(polls_options::columns::id.eq(option_id))
+            .first::<PollOption>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Poll option not found".to_string())
+            })
+    }
+
+    /// vote (or change a vote) on one of the poll's dates
+    pub fn vote(
+        poll: &Poll,
+        option_id: i32,
+        user_id: i32,
+        vote: &str,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        if poll.session_id.is_some() {
+            return Err(ApiError::Conflict(
+                "This poll has already been closed".to_string(),
+            ));
+        }
+
+        // only members of the poll's group can vote
+        GroupUser::check_user_in_group(poll.group_id, user_id, connection)
+            .map_err(|response| response)?;
+
+        let option = Poll::find_option(poll, option_id, connection).map_err(|response| response)?;
+
+        let new_vote = &InsertablePollVote {
+            option_id: option.id,
+            user_id,
+            vote: vote.to_string(),
+        };
+
+        diesel::insert_into(polls_votes::table)
+            .values(new_vote)
+            .on_conflict((polls_votes::option_id, polls_votes::user_id))
+            .do_update()
+            .set(new_vote)
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not vote on the poll".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    /// turn the chosen date into a session, adding everyone who voted yes or maybe for it. The
+    /// poll is locked while it is closed, so it can only ever be turned into one session.
+    pub fn close(
+        poll: &Poll,
+        option_id: i32,
+        connection: &PgConnection,
+    ) -> Result<SessionJson, ApiError> {
+        GroupUser::require_permission(
+            poll.group_id,
+            poll.dm,
+            GroupPermission::CreateSessions,
+            connection,
+        )?;
+
+        let new_session = connection.transaction::<_, ApiError, _>(|| {
+            let poll = polls::table
+                .find(poll.id)
+                .for_update()
+                .get_result::<Poll>(connection)?;
+
+            if poll.session_id.is_some() {
+                return Err(ApiError::Conflict(
+                    "This poll has already been closed".to_string(),
+                ));
+            }
+
+            let option = Poll::find_option(&poll, option_id, connection)?;
+
+            let insertable_session = InsertableSession {
+                slug: unique_slug(&poll.title, connection)?,
+                title: poll.title.clone(),
+                description: poll.description.clone(),
+                dm: poll.dm,
+                session_date: option.option_date,
+                colour: poll.colour.clone(),
+                group_id: poll.group_id,
+                recurrence: None,
+                duration_minutes: DEFAULT_SESSION_DURATION_MINUTES,
+            };
+
+            let new_session = InsertableSession::insert(&insertable_session, poll.dm, connection)?;
+
+            // voters who have since left the group are not added
+            let group_members = groups_users::table
+                .filter(groups_users::columns::group_id.eq(poll.group_id))
+                .filter(groups_users::columns::admin_accepted.eq(true))
+                .filter(groups_users::columns::user_accepted.eq(true))
+                .select(groups_users::columns::user_id);
+
+            let voters = PollVote::belonging_to(&option)
+                .filter(polls_votes::columns::vote.ne("no"))
+                .filter(polls_votes::columns::user_id.ne(poll.dm))
+                .filter(polls_votes::columns::user_id.eq_any(group_members))
+                .select(polls_votes::columns::user_id)
+                .load::<i32>(connection)?;
+
+            let new_session_users = voters
+                .into_iter()
+                .map(|user_id| InsertableSessionUser {
+                    session_id: new_session.id,
+                    user_id,
+                    dm_accepted: true,
+                    user_accepted: true,
+                    role: SessionRole::Player.as_str().to_string(),
+                })
+                .collect::<Vec<_>>();
+
+            diesel::insert_into(sessions_users::table)
+                .values(&new_session_users)
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+
+            diesel::update(polls::table.find(poll.id))
+                .set(polls::session_id.eq(new_session.id))
+                .execute(connection)?;
+
+            Ok(new_session)
+        })?;
+
+        let dm = User::find(new_session.dm, connection)?.to_profile();
+
+        session::populate(&new_session, dm, connection)
+    }
+
+    pub fn delete(poll: &Poll, connection: &PgConnection) -> Result<(), ApiError> {
+        diesel::delete(polls::table.find(poll.id))
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Poll could not be deleted".to_string())
+            })?;
+
+        Ok(())
+    }
+}
+
+impl InsertablePoll {
+    pub fn create(
+        poll: InsertablePoll,
+        option_dates: Vec<DateTime<Utc>>,
+        connection: &PgConnection,
+    ) -> Result<PollJson, ApiError> {
+        //check the dm is in the group they want to schedule a session for
+        let _is_user_in_group = GroupUser::check_user_in_group(poll.group_id, poll.dm, connection)
+            .map_err(|response| response)?;
+
+        match connection
+            .build_transaction()
+            .run::<Poll, diesel::result::Error, _>(|| {
+                let new_poll = diesel::insert_into(polls::table)
+                    .values(&poll)
+                    .get_result::<Poll>(connection)?;
+
+                let new_options = option_dates
+                    .into_iter()
+                    .map(|option_date| InsertablePollOption {
+                        poll_id: new_poll.id,
+                        option_date,
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(polls_options::table)
+                    .values(&new_options)
+                    .on_conflict_do_nothing() // ignore duplicated dates
+                    .execute(connection)?;
+
+                Ok(new_poll)
+            }) {
+            Ok(poll) => Poll::find_as_json(&poll, connection),
+            Err(error) => {
+                println!("Error: {:#?}", error);
+                Err(ApiError::Internal("Could not create the poll".to_string()))
+            }
+        }
+    }
+}
+
+pub fn populate(
+    poll: &Poll,
+    dm: Profile,
+    group: Group,
+    connection: &PgConnection,
+) -> Result<PollJson, ApiError> {
+    let options = PollOption::belonging_to(poll)
+        .order(polls_options::columns::option_date.asc())
+        .load::<PollOption>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Poll options not found".to_string())
+        })?;
+
+    let votes = PollVote::belonging_to(&options)
+        .inner_join(users::table)
+        .load::<(PollVote, User)>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Poll votes not found".to_string())
+        })?
+        .grouped_by(&options);
+
+    let option_jsons = options
+        .iter()
+        .zip(votes)
+        .map(|(option, votes)| {
+            let voters_for = |vote: &str| {
+                votes
+                    .iter()
+                    .filter(|(poll_vote, _)| poll_vote.vote == vote)
+                    .map(|(_, user)| user.to_profile())
+                    .collect::<Vec<_>>()
+            };
+
+            PollOptionJson {
+                id: option.id,
+                option_date: option.option_date.format(DATE_FORMAT).to_string(),
+                yes: voters_for("yes"),
+                maybe: voters_for("maybe"),
+                no: voters_for("no"),
+            }
+        })
+        .collect();
+
+    Ok(poll.attach(dm, group, option_jsons))
+}
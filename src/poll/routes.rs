@@ -0,0 +1,242 @@
+use crate::database::DnDAgendaDB;
+use crate::poll;
+
+use rocket_contrib::json::Json;
+use rocket_contrib::json::JsonError;
+
+use rocket::request::Form;
+
+use crate::api::ApiResponse;
+use crate::api::Auth;
+use crate::error::ApiError;
+use rocket::http::Status;
+
+use crate::api::validate_colour;
+use crate::api::validate_poll_options;
+use crate::api::validate_vote;
+use crate::api::FieldValidator;
+use validator::Validate;
+
+use chrono::{DateTime, Utc};
+
+use crate::group::GroupUser;
+
+#[get("/?<params..>")]
+pub fn get_all(
+    auth: Result<Auth, ApiError>,
+    params: Form<poll::FindPolls>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => poll::Poll::read(&params, auth.id, &connection)
+            .map(|(polls, pages_count)| ApiResponse {
+                json: json!({ "polls": polls, "pollsPagesCount": pages_count }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[get("/<poll_id>")]
+pub fn get_poll(
+    auth: Result<Auth, ApiError>,
+    poll_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            let poll_details =
+                poll::Poll::find(poll_id, &connection).map_err(|response| response)?;
+
+            // only members of the poll's group can see it
+            GroupUser::check_user_in_group(poll_details.group_id, auth.id, &connection)
+                .map_err(|response| response)?;
+
+            poll::Poll::find_as_json(&poll_details, &connection)
+                .map(|poll_json| ApiResponse {
+                    json: json!({ "poll": poll_json }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct NewPoll {
+    #[validate(length(min = 1, code = "Title must be at least 1 character long"))]
+    pub title: Option<String>,
+    #[validate(length(min = 1, code = "Description must be at least 1 character long"))]
+    pub description: Option<String>,
+    #[validate(custom = "validate_colour")]
+    pub colour: Option<String>,
+    pub group: Option<i32>,
+    /// the proposed session dates
+    #[validate(custom = "validate_poll_options")]
+    pub options: Option<Vec<String>>,
+}
+
+#[post("/", format = "application/json", data = "<poll>")]
+pub fn create(
+    auth: Result<Auth, ApiError>,
+    poll: Result<Json<NewPoll>, JsonError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match poll {
+            Ok(json_poll) => {
+                let new_poll = json_poll.into_inner();
+
+                let empty_flag = false; // i.e. should we ignore empty fields?
+                let mut extractor = FieldValidator::validate(&new_poll);
+                extractor.groups_exist(&[("group", new_poll.group)], &connection)?;
+                let title = extractor.extract("title", new_poll.title, empty_flag);
+                let description =
+                    extractor.extract("description", new_poll.description, empty_flag);
+                let colour = extractor.extract("colour", new_poll.colour, empty_flag);
+                let group_id = extractor.extract("group", new_poll.group, empty_flag);
+                let options = extractor.extract("options", new_poll.options, empty_flag);
+
+                let check = extractor.check();
+
+                match check {
+                    Ok(_) => {
+                        // no need to worry about panic here because validator above will check the dates
+                        let option_dates = options
+                            .iter()
+                            .map(|option| option.parse::<DateTime<Utc>>().unwrap())
+                            .collect();
+
+                        let insertable_poll = poll::InsertablePoll {
+                            title,
+                            description,
+                            dm: auth.id,
+                            colour,
+                            group_id,
+                        };
+                        match poll::InsertablePoll::create(
+                            insertable_poll,
+                            option_dates,
+                            &connection,
+                        ) {
+                            Ok(poll) => Ok(ApiResponse {
+                                json: json!({ "poll": poll }),
+                                status: Status::Created,
+                            }),
+                            Err(response) => Err(response),
+                        }
+                    }
+                    Err(response) => Err(response),
+                }
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
+        },
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct NewVote {
+    #[validate(custom = "validate_vote")]
+    pub vote: Option<String>,
+}
+
+#[post(
+    "/<poll_id>/options/<option_id>/vote",
+    format = "application/json",
+    data = "<vote>"
+)]
+pub fn vote(
+    auth: Result<Auth, ApiError>,
+    poll_id: i32,
+    option_id: i32,
+    vote: Result<Json<NewVote>, JsonError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match vote {
+            Ok(json_vote) => {
+                let new_vote = json_vote.into_inner();
+
+                let empty_flag = false; // i.e. should we ignore empty fields?
+                let mut extractor = FieldValidator::validate(&new_vote);
+                let vote = extractor.extract("vote", new_vote.vote, empty_flag);
+
+                extractor.check()?;
+
+                let poll_details =
+                    poll::Poll::find(poll_id, &connection).map_err(|response| response)?;
+
+                poll::Poll::vote(&poll_details, option_id, auth.id, &vote, &connection)
+                    .map_err(|response| response)?;
+
+                poll::Poll::find_as_json(&poll_details, &connection)
+                    .map(|poll_json| ApiResponse {
+                        json: json!({ "poll": poll_json }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
+        },
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// pick the winning date, creating the session and adding everyone who voted yes or maybe for it
+#[post("/<poll_id>/close/<option_id>")]
+pub fn close_poll(
+    auth: Result<Auth, ApiError>,
+    poll_id: i32,
+    option_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. poll does not exist)
+            let poll_details =
+                poll::Poll::find(poll_id, &connection).map_err(|response| response)?;
+
+            if poll_details.can_manage(auth.id, &connection)? {
+                poll::Poll::close(&poll_details, option_id, &connection)
+                    .map(|session_json| ApiResponse {
+                        json: json!({ "session": session_json }),
+                        status: Status::Created,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden("you are not the DM".to_string()))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[delete("/<poll_id>")]
+pub fn delete_poll(
+    auth: Result<Auth, ApiError>,
+    poll_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. poll does not exist)
+            let poll_details =
+                poll::Poll::find(poll_id, &connection).map_err(|response| response)?;
+
+            if poll_details.can_manage(auth.id, &connection)? {
+                poll::Poll::delete(&poll_details, &connection)
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "poll deleted successfully" }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden("you are not the DM".to_string()))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
@@ -0,0 +1,236 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::{reminders_sent, sessions_guests, sessions_users, users};
+use diesel::prelude::*;
+
+use crate::session::{Session, SessionGuest, SessionUser};
+
+use crate::config::AppConfig;
+use chrono::{DateTime, Duration, Utc};
+
+use std::thread;
+use std::time;
+
+use crate::database::worker_pool;
+use crate::error::ApiError;
+
+use crate::mail::send_reminder;
+
+/// A reminder that has been sent. Recorded in the same transaction that queues the email, so
+/// a reminder is queued exactly once, even if the scheduler restarts or queueing fails.
+#[table_name = "reminders_sent"]
+#[derive(Insertable)]
+pub struct InsertableReminderSent {
+    pub session_id: i32,
+    /// the start of the session (or occurrence) the reminder is for
+    pub session_date: DateTime<Utc>,
+    pub offset_minutes: i32,
+    /// the email address the reminder is sent to
+    pub recipient: String,
+}
+
+/// start the background thread that sends reminder emails as they become due
+pub fn start(app_config: AppConfig) {
+    thread::spawn(move || {
+        let pool = worker_pool(&app_config.database_url);
+
+        loop {
+            match pool.get() {
+                Ok(connection) => {
+                    if let Err(response) = send_due_reminders(Utc::now(), &app_config, &connection)
+                    {
+                        println!("Error sending reminders: {:#?}", response);
+                    }
+                }
+                Err(error) => println!("Error: {:#?}", error),
+            }
+
+            thread::sleep(time::Duration::from_secs(
+                app_config.reminder_interval_seconds,
+            ));
+        }
+    });
+}
+
+/// email everyone in a session starting within the largest reminder offset, unless they have
+/// already been reminded
+pub fn send_due_reminders(
+    now: DateTime<Utc>,
+    app_config: &AppConfig,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    let offsets = &app_config.reminder_offsets_minutes;
+    let largest_offset = match offsets.iter().max() {
+        Some(offset) => *offset,
+        None => return Ok(()),
+    };
+
+    let upcoming = Session::read_upcoming(
+        now,
+        now + Duration::minutes(i64::from(largest_offset)),
+        connection,
+    )?;
+
+    for (session, starts) in upcoming {
+        for start in starts {
+            let offset = match due_offset(offsets, start, now) {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            for recipient in read_recipients(&session, app_config.remind_guests, connection)? {
+                let reminder = InsertableReminderSent {
+                    session_id: session.id,
+                    session_date: start,
+                    offset_minutes: offset,
+                    recipient: recipient.email,
+                };
+
+                // if queueing the email fails the claim is rolled back, so the next run tries
+                // again
+                let queued = connection.transaction::<_, ApiError, _>(|| {
+                    if claim(&reminder, connection)? {
+                        send_reminder(
+                            &reminder.recipient,
+                            &recipient.name,
+                            recipient.guest,
+                            &session.title,
+                            &session.slug,
+                            start,
+                            app_config,
+                            connection,
+                        )?;
+                    }
+                    Ok(())
+                });
+                if let Err(error) = queued {
+                    println!("Error queueing reminder: {:#?}", error);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// the reminder due for an occurrence starting at `start`, if any. After a restart several
+/// reminders can be due at once, so only the latest one is sent.
+fn due_offset(offsets: &[i32], start: DateTime<Utc>, now: DateTime<Utc>) -> Option<i32> {
+    offsets
+        .iter()
+        .filter(|offset| start - Duration::minutes(i64::from(**offset)) <= now)
+        .min()
+        .copied()
+}
+
+/// someone to remind about a session, either a member or a guest
+struct Recipient {
+    email: String,
+    name: String,
+    guest: bool,
+}
+
+/// everyone to remind about a session, i.e. its members and (if `remind_guests` is set) its
+/// guests, unless they turned reminders off
+fn read_recipients(
+    session: &Session,
+    remind_guests: bool,
+    connection: &PgConnection,
+) -> Result<Vec<Recipient>, ApiError> {
+    let mut recipients = SessionUser::belonging_to(session)
+        .filter(sessions_users::columns::dm_accepted.eq(true))
+        .filter(sessions_users::columns::user_accepted.eq(true))
+        .inner_join(users::table)
+        .filter(users::columns::reminders_enabled.eq(true))
+        .filter(users::columns::email_verified.eq(true))
+        .select((users::columns::email, users::columns::username))
+        .load::<(String, String)>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Members not found".to_string())
+        })?
+        .into_iter()
+        .map(|(email, name)| Recipient {
+            email,
+            name,
+            guest: false,
+        })
+        .collect::<Vec<_>>();
+
+    if remind_guests {
+        let guests = SessionGuest::belonging_to(session)
+            .filter(sessions_guests::columns::guest_email.is_not_null())
+            .filter(sessions_guests::columns::upgraded_at.is_null())
+            .filter(sessions_guests::columns::reminders_enabled.eq(true))
+            .select((
+                sessions_guests::columns::guest_email,
+                sessions_guests::columns::guest_name,
+            ))
+            .load::<(Option<String>, String)>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Guests not found".to_string())
+            })?;
+
+        recipients.extend(guests.into_iter().filter_map(|(email, name)| {
+            email.map(|email| Recipient {
+                email,
+                name,
+                guest: true,
+            })
+        }));
+    }
+
+    Ok(recipients)
+}
+
+/// record the reminder as sent, returning false if it already was
+fn claim(reminder: &InsertableReminderSent, connection: &PgConnection) -> Result<bool, ApiError> {
+    diesel::insert_into(reminders_sent::table)
+        .values(reminder)
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .map(|inserted| inserted > 0)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not record the reminder".to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFSETS: &[i32] = &[24 * 60, 60];
+
+    fn start() -> DateTime<Utc> {
+        "2030-01-08T19:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn nothing_is_due_before_the_largest_offset() {
+        let now = start() - Duration::minutes(24 * 60 + 1);
+        assert_eq!(due_offset(OFFSETS, start(), now), None);
+    }
+
+    #[test]
+    fn each_offset_is_due_once_reached() {
+        let now = start() - Duration::minutes(24 * 60);
+        assert_eq!(due_offset(OFFSETS, start(), now), Some(24 * 60));
+
+        let now = start() - Duration::minutes(60);
+        assert_eq!(due_offset(OFFSETS, start(), now), Some(60));
+    }
+
+    #[test]
+    fn only_the_latest_overdue_reminder_is_sent() {
+        let now = start() - Duration::minutes(30);
+        assert_eq!(due_offset(OFFSETS, start(), now), Some(60));
+    }
+
+    #[test]
+    fn no_offsets_means_no_reminders() {
+        assert_eq!(due_offset(&[], start(), start()), None);
+    }
+}
@@ -0,0 +1,35 @@
+/// A role someone can have somewhere, e.g. in a group or a session. Each role can do everything
+/// the roles below it can, so the roles are ordered from least to most powerful.
+pub trait Role: Copy + Ord + 'static {
+    /// what the role lets someone do
+    type Permission: Copy;
+
+    /// every role, from least to most powerful
+    const ALL: &'static [Self];
+    /// what the roles are called in errors, e.g. "group role"
+    const NAME: &'static str;
+
+    /// the role as it is stored and shown in the API
+    fn as_str(self) -> &'static str;
+
+    /// the least powerful role with `permission`
+    fn required_for(permission: Self::Permission) -> Self;
+
+    fn can(self, permission: Self::Permission) -> bool {
+        self >= Self::required_for(permission)
+    }
+
+    /// whether someone with this role can remove, or change the role of, someone with `other`
+    fn outranks(self, other: Self) -> bool {
+        self > other
+    }
+
+    /// the role called `role`, see `as_str`
+    fn from_name(role: &str) -> Result<Self, String> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|known| known.as_str() == role)
+            .ok_or_else(|| format!("{} is not a {}", role, Self::NAME))
+    }
+}
@@ -1,5 +1,27 @@
 #![allow(clippy::single_component_path_imports)]
 
+table! {
+    calendar_feeds (user_id) {
+        user_id -> Int4,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    group_invites (id) {
+        id -> Int4,
+        group_id -> Int4,
+        code -> Text,
+        created_by -> Nullable<Int4>,
+        max_uses -> Nullable<Int4>,
+        uses -> Int4,
+        expires_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     groups (id) {
         id -> Int4,
@@ -8,6 +30,7 @@ table! {
         description -> Text,
         image -> Nullable<Text>,
         admin -> Int4,
+        privacy -> Text,
     }
 }
 
@@ -17,6 +40,106 @@ table! {
         user_id -> Int4,
         admin_accepted -> Bool,
         user_accepted -> Bool,
+        role -> Text,
+    }
+}
+
+table! {
+    login_sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    mail_outbox (id) {
+        id -> Int4,
+        from_address -> Text,
+        to_address -> Text,
+        subject -> Text,
+        html -> Text,
+        text -> Text,
+        status -> Text,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamptz,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Int4,
+        user_id -> Int4,
+        kind -> Text,
+        actor_id -> Nullable<Int4>,
+        session_id -> Nullable<Int4>,
+        group_id -> Nullable<Int4>,
+        read_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    polls (id) {
+        id -> Int4,
+        title -> Text,
+        description -> Text,
+        dm -> Int4,
+        colour -> Text,
+        group_id -> Int4,
+        session_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    polls_options (id) {
+        id -> Int4,
+        poll_id -> Int4,
+        option_date -> Timestamptz,
+    }
+}
+
+table! {
+    polls_votes (option_id, user_id) {
+        option_id -> Int4,
+        user_id -> Int4,
+        vote -> Text,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Int4,
+        login_session_id -> Int4,
+        token_hash -> Text,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    reminders_sent (session_id, session_date, offset_minutes, recipient) {
+        session_id -> Int4,
+        session_date -> Timestamptz,
+        offset_minutes -> Int4,
+        recipient -> Text,
+        sent_at -> Timestamptz,
     }
 }
 
@@ -31,6 +154,8 @@ table! {
         colour -> Text,
         image -> Nullable<Text>,
         group_id -> Int4,
+        recurrence -> Nullable<Text>,
+        duration_minutes -> Int4,
     }
 }
 
@@ -39,6 +164,38 @@ table! {
         session_id -> Int4,
         guest_id -> Int4,
         guest_name -> Text,
+        guest_email -> Nullable<Text>,
+        link_version -> Int4,
+        link_expires_at -> Timestamptz,
+        link_revoked_at -> Nullable<Timestamptz>,
+        link_last_used_at -> Nullable<Timestamptz>,
+        rsvp -> Text,
+        rsvp_note -> Nullable<Text>,
+        upgraded_to -> Nullable<Int4>,
+        upgraded_at -> Nullable<Timestamptz>,
+        reminders_enabled -> Bool,
+    }
+}
+
+table! {
+    sessions_occurrences (session_id, occurrence_date) {
+        session_id -> Int4,
+        occurrence_date -> Timestamptz,
+        cancelled -> Bool,
+        session_date -> Nullable<Timestamptz>,
+        title -> Nullable<Text>,
+        description -> Nullable<Text>,
+        colour -> Nullable<Text>,
+    }
+}
+
+table! {
+    sessions_rsvps (session_id, occurrence_date, user_id) {
+        session_id -> Int4,
+        user_id -> Int4,
+        occurrence_date -> Timestamptz,
+        rsvp -> Text,
+        rsvp_note -> Nullable<Text>,
     }
 }
 
@@ -48,6 +205,9 @@ table! {
         user_id -> Int4,
         dm_accepted -> Bool,
         user_accepted -> Bool,
+        rsvp -> Text,
+        rsvp_note -> Nullable<Text>,
+        role -> Text,
     }
 }
 
@@ -59,23 +219,56 @@ table! {
         bio -> Nullable<Text>,
         image -> Nullable<Text>,
         password -> Text,
+        reminders_enabled -> Bool,
+        site_admin -> Bool,
+        email_verified -> Bool,
     }
 }
 
+joinable!(calendar_feeds -> users (user_id));
+joinable!(group_invites -> groups (group_id));
+joinable!(group_invites -> users (created_by));
 joinable!(groups -> users (admin));
 joinable!(groups_users -> groups (group_id));
 joinable!(groups_users -> users (user_id));
+joinable!(login_sessions -> users (user_id));
+joinable!(notifications -> groups (group_id));
+joinable!(notifications -> sessions (session_id));
+joinable!(password_resets -> users (user_id));
+joinable!(polls -> groups (group_id));
+joinable!(polls -> sessions (session_id));
+joinable!(polls -> users (dm));
+joinable!(polls_options -> polls (poll_id));
+joinable!(polls_votes -> polls_options (option_id));
+joinable!(polls_votes -> users (user_id));
+joinable!(refresh_tokens -> login_sessions (login_session_id));
+joinable!(reminders_sent -> sessions (session_id));
 joinable!(sessions -> groups (group_id));
 joinable!(sessions -> users (dm));
 joinable!(sessions_guests -> sessions (session_id));
+joinable!(sessions_guests -> users (upgraded_to));
+joinable!(sessions_occurrences -> sessions (session_id));
 joinable!(sessions_users -> sessions (session_id));
 joinable!(sessions_users -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    calendar_feeds,
+    group_invites,
     groups,
     groups_users,
+    login_sessions,
+    mail_outbox,
+    notifications,
+    password_resets,
+    polls,
+    polls_options,
+    polls_votes,
+    refresh_tokens,
+    reminders_sent,
     sessions,
     sessions_guests,
+    sessions_occurrences,
+    sessions_rsvps,
     sessions_users,
     users,
 );
@@ -3,24 +3,34 @@
 
 use crate::schema::sessions;
 use crate::schema::sessions_guests;
+use crate::schema::sessions_occurrences;
+use crate::schema::sessions_rsvps;
 use crate::schema::sessions_users;
 use crate::schema::users;
 use diesel::prelude::*;
 
 use crate::api::GuestAuth;
+use crate::notification::{Notification, NotificationKind};
 use crate::user::Profile;
 use crate::user::User;
 
+use crate::group::role::GroupPermission;
 use crate::group::{Group, GroupUser};
 use crate::schema::{groups, groups_users};
 
-use crate::config::DATE_FORMAT;
-use chrono::{DateTime, Utc};
+use crate::config::AppConfig;
+use crate::config::{DATE_FORMAT, RECURRENCE_HORIZON_DAYS};
+use chrono::{DateTime, Duration, Utc};
 
+pub mod recurrence;
+pub mod role;
 pub mod routes;
 
-use crate::api::ApiResponse;
-use rocket::http::Status;
+use crate::role::Role;
+use recurrence::Recurrence;
+use role::{SessionPermission, SessionRole};
+
+use crate::error::ApiError;
 
 use crate::config::DEFAULT_LIMIT;
 
@@ -30,6 +40,8 @@ use crate::database::Paginate;
 
 use itertools::Itertools;
 
+use crate::visibility::{require_visible, Viewable, Visibility};
+
 #[table_name = "sessions"]
 #[belongs_to(Group)]
 #[derive(Associations, Debug, Identifiable, AsChangeset, Serialize, Deserialize, Queryable)]
@@ -43,8 +55,13 @@ pub struct Session {
     pub colour: String,
     pub image: Option<String>,
     pub group_id: i32,
+    pub recurrence: Option<String>,
+    pub duration_minutes: i32,
 }
 
+/// the start and end of every instance of a session
+pub type Schedule = Vec<(DateTime<Utc>, DateTime<Utc>)>;
+
 // TODO: remove clone when diesel will allow skipping fields
 #[derive(Deserialize, AsChangeset, Default, Clone)]
 #[table_name = "sessions_users"]
@@ -53,6 +70,15 @@ pub struct UpdateSessionUser {
     user_accepted: bool,
 }
 
+#[derive(Deserialize, AsChangeset)]
+#[table_name = "sessions_users"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct UpdateRsvp {
+    pub rsvp: String,
+    /// replaced on every RSVP, so that an old note does not linger
+    pub rsvp_note: Option<String>,
+}
+
 #[derive(FromForm, Default)]
 pub struct FindSessions {
     title: Option<String>,
@@ -62,6 +88,42 @@ pub struct FindSessions {
     order: Option<String>,
 }
 
+/// A member of a session along with their RSVP for it
+#[derive(Serialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMember {
+    #[serde(flatten)]
+    pub profile: Profile,
+    /// one of "dm", "co_dm", "player" or "spectator"
+    pub role: String,
+    /// one of "going", "maybe", "not_going" or "no_response"
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
+}
+
+/// A guest of a session along with their RSVP for it
+#[derive(Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct GuestRsvp {
+    pub guest_id: i32,
+    pub guest_name: String,
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
+}
+
+/// the RSVP counts include both members and guests
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Headcount {
+    pub going: usize,
+    pub maybe: usize,
+    pub not_going: usize,
+    pub no_response: usize,
+    pub guests: usize,
+    pub members: Vec<SessionMember>,
+    pub guest_rsvps: Vec<GuestRsvp>,
+}
+
 #[derive(Serialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionJson {
@@ -71,18 +133,57 @@ pub struct SessionJson {
     pub description: String,
     pub dm: Profile,
     pub session_date: String,
+    pub duration_minutes: i32,
+    pub end_date: String,
     pub colour: String,
     pub group: Group,
-    pub members: Vec<Profile>,
+    pub members: Vec<SessionMember>,
     pub guests: Vec<(i32, String)>,
+    pub recurrence: Option<String>,
+    /// the original date of this occurrence, if the session is an occurrence of a recurring series
+    pub occurrence_date: Option<String>,
+}
+
+/// A session along with the other sessions it overlaps
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    pub session: SessionJson,
+    pub conflicts_with: Vec<SessionJson>,
 }
 
 impl Session {
+    /// whether the user's role in the session gives them `permission`. Anyone allowed to manage
+    /// every session in the group has the permissions `given_to_group_schedulers` too.
+    pub fn has_permission(
+        &self,
+        user_id: i32,
+        permission: SessionPermission,
+        connection: &PgConnection,
+    ) -> Result<bool, ApiError> {
+        if let Some(role) = SessionUser::role(self.id, user_id, connection)? {
+            if role.can(permission) {
+                return Ok(true);
+            }
+        }
+
+        if !permission.given_to_group_schedulers() {
+            return Ok(false);
+        }
+
+        GroupUser::has_permission(
+            self.group_id,
+            user_id,
+            GroupPermission::ManageSessions,
+            connection,
+        )
+    }
+
     pub fn attach(
         &self,
         dm: Profile,
         group: Group,
-        members: Vec<Profile>,
+        members: Vec<SessionMember>,
         guests: Vec<(i32, String)>,
     ) -> SessionJson {
         SessionJson {
@@ -92,115 +193,625 @@ impl Session {
             description: self.description.clone(),
             dm,
             session_date: self.session_date.format(DATE_FORMAT).to_string(),
+            duration_minutes: self.duration_minutes,
+            end_date: self
+                .end_date(self.session_date)
+                .format(DATE_FORMAT)
+                .to_string(),
             colour: self.colour.clone(),
             group,
             members,
             guests,
+            recurrence: self.recurrence.clone(),
+            occurrence_date: None,
         }
     }
-    pub fn read(
-        params: &FindSessions,
-        user_id: i32,
-        connection: &PgConnection,
-    ) -> Result<(Vec<SessionJson>, i64), ApiResponse> {
-        let mut pages_count: i64 = 0;
-        let user = User::find(user_id, connection).map_err(|response| response)?;
 
-        // get all sessions belonging to the same groups as the user
-        GroupUser::belonging_to(&user)
-            .filter(groups_users::columns::admin_accepted.eq(true))
-            .filter(groups_users::columns::user_accepted.eq(true))
-            .inner_join(groups::table)
-            .select(groups::all_columns)
-            .load::<Group>(connection)
+    /// expand recurring sessions into one `SessionJson` per occurrence, applying any edited or
+    /// cancelled occurrences, which are loaded for all the sessions at once. Non-recurring
+    /// sessions are returned as they are.
+    pub fn expand_occurrences(
+        sessions: &[Session],
+        session_jsons: Vec<SessionJson>,
+        connection: &PgConnection,
+    ) -> Result<Vec<SessionJson>, ApiError> {
+        let overrides = SessionOccurrence::belonging_to(sessions)
+            .load::<SessionOccurrence>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Groups not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::Internal("Occurrences not found".to_string())
+            })?
+            .grouped_by(sessions);
+        let rsvps = SessionRsvp::belonging_to(sessions)
+            .load::<SessionRsvp>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("RSVPs not found".to_string())
             })?
+            .grouped_by(sessions);
+
+        sessions
             .iter()
-            .map(|group| {
-                let mut query = Session::belonging_to(group)
-                    .inner_join(users::table) // dm details
-                    .select((sessions::all_columns, users::all_columns))
-                    .into_boxed();
-
-                if let Some(ref dm) = params.dm {
-                    // query = query
-                    //     .filter(dsl::similar_to(users::username, dm))
-                    //     .order(dsl::similarity(users::username, dm).desc())
-                    query = query
-                        .filter(users::username.eq(dm))
-                        .order(sessions::session_date.asc())
-                } else if let Some(ref order) = params.order {
-                    match order.to_lowercase().as_ref() {
-                        "asc" => query = query.order(sessions::session_date.asc()),
-                        "desc" => query = query.order(sessions::session_date.desc()),
-                        _ => query = query.order(sessions::session_date.asc()),
+            .zip(session_jsons)
+            .zip(overrides.into_iter().zip(rsvps))
+            .map(|((session, session_json), (overrides, rsvps))| {
+                session.expand(session_json, &overrides, &rsvps)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|expanded| expanded.into_iter().flatten().collect())
+    }
+
+    /// `expand_occurrences` for a single session, whose edited occurrences are `overrides` and
+    /// whose members' RSVPs for single occurrences are `rsvps`
+    fn expand(
+        &self,
+        session_json: SessionJson,
+        overrides: &[SessionOccurrence],
+        rsvps: &[SessionRsvp],
+    ) -> Result<Vec<SessionJson>, ApiError> {
+        let recurrence = match self.recurrence {
+            Some(ref recurrence) => recurrence.parse::<Recurrence>().map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Session has an invalid recurrence".to_string())
+            })?,
+            None => return Ok(vec![session_json]),
+        };
+
+        let horizon = Utc::now() + Duration::days(RECURRENCE_HORIZON_DAYS);
+
+        Ok(recurrence
+            .occurrences(self.session_date, horizon)
+            .into_iter()
+            .filter_map(|occurrence_date| {
+                let mut occurrence_json = session_json.clone();
+                let mut start = occurrence_date;
+                occurrence_json.occurrence_date =
+                    Some(occurrence_date.format(DATE_FORMAT).to_string());
+                apply_rsvps(&mut occurrence_json.members, rsvps, occurrence_date);
+
+                if let Some(edited) = overrides
+                    .iter()
+                    .find(|edited| edited.occurrence_date == occurrence_date)
+                {
+                    if edited.cancelled {
+                        return None;
+                    }
+                    if let Some(session_date) = edited.session_date {
+                        start = session_date;
+                    }
+                    if let Some(ref title) = edited.title {
+                        occurrence_json.title = title.clone();
+                    }
+                    if let Some(ref description) = edited.description {
+                        occurrence_json.description = description.clone();
+                    }
+                    if let Some(ref colour) = edited.colour {
+                        occurrence_json.colour = colour.clone();
                     }
-                } else {
-                    // default to asc
-                    query = query.order(sessions::session_date.asc())
                 }
+                occurrence_json.session_date = start.format(DATE_FORMAT).to_string();
+                occurrence_json.end_date = self.end_date(start).format(DATE_FORMAT).to_string();
+                Some(occurrence_json)
+            })
+            .collect())
+    }
+
+    /// when an instance of the session starting at `start` ends
+    pub fn end_date(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        start + Duration::minutes(i64::from(self.duration_minutes))
+    }
+
+    /// the edited or cancelled occurrences of the session
+    pub fn overrides(&self, connection: &PgConnection) -> Result<Vec<SessionOccurrence>, ApiError> {
+        SessionOccurrence::belonging_to(self)
+            .load::<SessionOccurrence>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Occurrences not found".to_string())
+            })
+    }
+
+    pub fn schedule(&self, connection: &PgConnection) -> Result<Schedule, ApiError> {
+        Ok(schedule_of(
+            self.session_date,
+            self.duration_minutes,
+            self.recurrence.as_deref(),
+            &self.overrides(connection)?,
+        ))
+    }
+
+    /// every session the user is a member of, along with its schedule
+    fn read_user_schedules(
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Vec<(Session, User, Schedule)>, ApiError> {
+        let users_sessions = sessions_users::table
+            .filter(sessions_users::columns::user_id.eq(user_id))
+            .filter(sessions_users::columns::dm_accepted.eq(true))
+            .filter(sessions_users::columns::user_accepted.eq(true))
+            .select(sessions_users::columns::session_id);
+
+        let (sessions, dms): (Vec<Session>, Vec<User>) = sessions::table
+            .filter(sessions::id.eq_any(users_sessions))
+            .inner_join(users::table) // dm details
+            .select((sessions::all_columns, users::all_columns))
+            .load::<(Session, User)>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Sessions not found".to_string())
+            })?
+            .into_iter()
+            .unzip();
+
+        let overrides = SessionOccurrence::belonging_to(&sessions)
+            .load::<SessionOccurrence>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Occurrences not found".to_string())
+            })?
+            .grouped_by(&sessions);
+
+        Ok(sessions
+            .into_iter()
+            .zip(dms)
+            .zip(overrides)
+            .map(|((session, dm), overrides)| {
+                let schedule = schedule_of(
+                    session.session_date,
+                    session.duration_minutes,
+                    session.recurrence.as_deref(),
+                    &overrides,
+                );
+                (session, dm, schedule)
+            })
+            .collect())
+    }
+
+    /// every session with an instance starting after `from` and up to `until`, along with the
+    /// starts of those instances
+    pub fn read_upcoming(
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        connection: &PgConnection,
+    ) -> Result<Vec<(Session, Vec<DateTime<Utc>>)>, ApiError> {
+        let sessions = sessions::table
+            // recurring sessions can have occurrences long after they first started
+            .filter(
+                sessions::recurrence
+                    .is_not_null()
+                    .or(sessions::session_date.gt(from)),
+            )
+            .filter(sessions::session_date.le(until))
+            .load::<Session>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Sessions not found".to_string())
+            })?;
+
+        let overrides = SessionOccurrence::belonging_to(&sessions)
+            .load::<SessionOccurrence>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Occurrences not found".to_string())
+            })?
+            .grouped_by(&sessions);
+
+        Ok(sessions
+            .into_iter()
+            .zip(overrides)
+            .filter_map(|(session, overrides)| {
+                let starts = schedule_of(
+                    session.session_date,
+                    session.duration_minutes,
+                    session.recurrence.as_deref(),
+                    &overrides,
+                )
+                .into_iter()
+                .map(|(start, _)| start)
+                .filter(|start| *start > from && *start <= until)
+                .collect::<Vec<_>>();
 
-                if let Some(ref title) = params.title {
-                    query = query
-                        .filter(dsl::similar_to(sessions::title, title))
-                        .order(dsl::similarity(sessions::title, title).desc())
+                if starts.is_empty() {
+                    None
+                } else {
+                    Some((session, starts))
                 }
+            })
+            .collect())
+    }
+
+    /// the sessions (other than `exclude_session_id`) the user is a member of that overlap `schedule`
+    pub fn find_conflicts(
+        user_id: i32,
+        schedule: &[(DateTime<Utc>, DateTime<Utc>)],
+        exclude_session_id: Option<i32>,
+        connection: &PgConnection,
+    ) -> Result<Vec<SessionJson>, ApiError> {
+        let (sessions, dms): (Vec<Session>, Vec<Profile>) =
+            Session::read_user_schedules(user_id, connection)?
+                .into_iter()
+                .filter(|(session, _, _)| Some(session.id) != exclude_session_id)
+                .filter(|(_, _, other_schedule)| overlaps(schedule, other_schedule))
+                .map(|(session, dm, _)| (session, dm.to_profile()))
+                .unzip();
+
+        populate_all(&sessions, dms, connection)
+    }
+
+    /// find the conflicts of the given users with `schedule`, returning them as a warning,
+    /// or as an error if `reject_conflicts` is set
+    pub fn check_conflicts(
+        user_ids: &[i32],
+        schedule: &[(DateTime<Utc>, DateTime<Utc>)],
+        exclude_session_id: Option<i32>,
+        reject_conflicts: bool,
+        connection: &PgConnection,
+    ) -> Result<Vec<SessionJson>, ApiError> {
+        let conflicts = user_ids
+            .iter()
+            .unique()
+            .map(|user_id| {
+                Session::find_conflicts(*user_id, schedule, exclude_session_id, connection)
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .unique_by(|session_json| session_json.id)
+            .collect::<Vec<_>>();
+
+        if reject_conflicts && !conflicts.is_empty() {
+            return Err(ApiError::ScheduleConflict(json!(conflicts)));
+        }
+
+        Ok(conflicts)
+    }
+
+    /// every upcoming clash between the sessions the user is a member of
+    pub fn read_conflicts(
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Vec<Conflict>, ApiError> {
+        let now = Utc::now();
+        let mut sessions = Vec::new();
+        let mut dms = Vec::new();
+        let mut schedules = Vec::new();
+        for (session, dm, schedule) in Session::read_user_schedules(user_id, connection)? {
+            sessions.push(session);
+            dms.push(dm.to_profile());
+            schedules.push(
+                schedule
+                    .into_iter()
+                    .filter(|(_, end)| *end > now)
+                    .collect::<Schedule>(),
+            );
+        }
+
+        // in the same order as `schedules`
+        let session_jsons = populate_all(&sessions, dms, connection)?;
+
+        let mut conflicts = Vec::new();
+        for (index, (session_json, schedule)) in session_jsons.iter().zip(&schedules).enumerate() {
+            let conflicts_with = session_jsons
+                .iter()
+                .zip(&schedules)
+                .enumerate()
+                .filter(|(other_index, (_, other_schedule))| {
+                    *other_index != index && overlaps(schedule, other_schedule)
+                })
+                .map(|(_, (other, _))| other.clone())
+                .collect::<Vec<_>>();
+
+            if !conflicts_with.is_empty() {
+                conflicts.push(Conflict {
+                    session: session_json.clone(),
+                    conflicts_with,
+                });
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    /// check that `occurrence_date` is an occurrence of this recurring session
+    fn check_occurrence(&self, occurrence_date: DateTime<Utc>) -> Result<(), ApiError> {
+        let is_occurrence = self
+            .recurrence
+            .as_ref()
+            .and_then(|recurrence| recurrence.parse::<Recurrence>().ok())
+            .map(|recurrence| recurrence.includes(self.session_date, occurrence_date))
+            .unwrap_or(false);
+
+        if is_occurrence {
+            Ok(())
+        } else {
+            Err(ApiError::NotFound("Occurrence not found".to_string()))
+        }
+    }
 
-                query
-                    .paginate(params.page.unwrap_or(1))
-                    .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
-                    .load_and_count_pages::<(Session, User)>(connection)
-                    .map_err(|error| {
-                        println!("Error: {:#?}", error);
-                        ApiResponse {
-                            json: json!({"error": "Sessions not found" }),
-                            status: Status::NotFound,
-                        }
+    /// edit a single occurrence of a recurring session without touching the rest of the series.
+    /// Whether the occurrence is cancelled is left as it is.
+    pub fn update_occurrence(
+        session: &Session,
+        occurrence_date: DateTime<Utc>,
+        changes: &UpdateSessionOccurrence,
+        connection: &PgConnection,
+    ) -> Result<SessionOccurrence, ApiError> {
+        session.check_occurrence(occurrence_date)?;
+
+        connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                diesel::insert_into(sessions_occurrences::table)
+                    .values(&InsertableSessionOccurrence {
+                        session_id: session.id,
+                        occurrence_date,
+                        cancelled: false,
+                        session_date: None,
+                        title: None,
+                        description: None,
+                        colour: None,
                     })
-                    .map(|(sessions_and_dms, count)| {
-                        pages_count += count;
-                        sessions_and_dms
-                    })?
-                    .iter()
-                    .map(|(session, dm)| populate(session, dm.to_profile(), connection))
-                    .collect::<Result<Vec<_>, _>>()
+                    .on_conflict_do_nothing()
+                    .execute(connection)?;
+
+                // diesel refuses to run an update without any changes
+                if !changes.is_empty() {
+                    diesel::update(sessions_occurrences::table.find((session.id, occurrence_date)))
+                        .set(changes)
+                        .execute(connection)?;
+                }
+
+                sessions_occurrences::table
+                    .find((session.id, occurrence_date))
+                    .first::<SessionOccurrence>(connection)
             })
-            .collect::<Result<Vec<_>, _>>()
-            .map(|session_jsons| {
-                (
-                    session_jsons
-                        .into_iter()
-                        .flatten()
-                        .unique_by(|session_json| session_json.id)
-                        .collect(),
-                    pages_count,
-                )
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not update the occurrence".to_string())
+            })
+    }
+
+    /// cancel a single occurrence of a recurring session without touching the rest of the series
+    pub fn cancel_occurrence(
+        session: &Session,
+        occurrence_date: DateTime<Utc>,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        session.check_occurrence(occurrence_date)?;
+
+        let cancelled_occurrence = &InsertableSessionOccurrence {
+            session_id: session.id,
+            occurrence_date,
+            cancelled: true,
+            session_date: None,
+            title: None,
+            description: None,
+            colour: None,
+        };
+
+        // any edits are kept, in case the occurrence is brought back
+        diesel::insert_into(sessions_occurrences::table)
+            .values(cancelled_occurrence)
+            .on_conflict((
+                sessions_occurrences::session_id,
+                sessions_occurrences::occurrence_date,
+            ))
+            .do_update()
+            .set(sessions_occurrences::cancelled.eq(true))
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not cancel the occurrence".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    /// after the series' start or recurrence changed from `previous`, move each edited or
+    /// cancelled occurrence, and each RSVP for a single occurrence, to where that occurrence now
+    /// falls, or drop them if it is no longer an occurrence
+    fn move_occurrences(
+        &self,
+        previous: &Session,
+        connection: &PgConnection,
+    ) -> Result<(), diesel::result::Error> {
+        if self.session_date == previous.session_date && self.recurrence == previous.recurrence {
+            return Ok(());
+        }
+
+        let recurrence = self
+            .recurrence
+            .as_ref()
+            .and_then(|recurrence| recurrence.parse::<Recurrence>().ok());
+        let shift = self.session_date - previous.session_date;
+        let move_date = |occurrence_date: DateTime<Utc>| {
+            let occurrence_date = occurrence_date + shift;
+            recurrence
+                .as_ref()
+                .filter(|recurrence| recurrence.includes(self.session_date, occurrence_date))
+                .map(|_| occurrence_date)
+        };
+
+        let overrides = SessionOccurrence::belonging_to(self)
+            .load::<SessionOccurrence>(connection)?
+            .into_iter()
+            .filter_map(|occurrence| {
+                move_date(occurrence.occurrence_date).map(|occurrence_date| {
+                    InsertableSessionOccurrence {
+                        session_id: self.id,
+                        occurrence_date,
+                        cancelled: occurrence.cancelled,
+                        session_date: occurrence.session_date,
+                        title: occurrence.title,
+                        description: occurrence.description,
+                        colour: occurrence.colour,
+                    }
+                })
             })
+            .collect::<Vec<_>>();
+        let rsvps = SessionRsvp::belonging_to(self)
+            .load::<SessionRsvp>(connection)?
+            .into_iter()
+            .filter_map(|session_rsvp| {
+                move_date(session_rsvp.occurrence_date).map(|occurrence_date| {
+                    InsertableSessionRsvp {
+                        session_id: self.id,
+                        user_id: session_rsvp.user_id,
+                        occurrence_date,
+                        rsvp: session_rsvp.rsvp,
+                        rsvp_note: session_rsvp.rsvp_note,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // the keys are replaced all at once, as moving them one by one could clash
+        diesel::delete(SessionOccurrence::belonging_to(self)).execute(connection)?;
+        if !overrides.is_empty() {
+            diesel::insert_into(sessions_occurrences::table)
+                .values(&overrides)
+                .execute(connection)?;
+        }
+        diesel::delete(SessionRsvp::belonging_to(self)).execute(connection)?;
+        if !rsvps.is_empty() {
+            diesel::insert_into(sessions_rsvps::table)
+                .values(&rsvps)
+                .execute(connection)?;
+        }
+
+        Ok(())
+    }
+
+    /// a page of the sessions in the user's groups, along with how many pages there are. Pages
+    /// are counted in series, not occurrences: a recurring session takes up one place on its
+    /// page, however many occurrences it is expanded into.
+    pub fn read(
+        params: &FindSessions,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(Vec<SessionJson>, i64), ApiError> {
+        let user_groups = groups_users::table
+            .filter(groups_users::columns::user_id.eq(user_id))
+            .filter(groups_users::columns::admin_accepted.eq(true))
+            .filter(groups_users::columns::user_accepted.eq(true))
+            .select(groups_users::columns::group_id);
+
+        // get all sessions belonging to the same groups as the user, in one query so that the
+        // pages are counted across all of them
+        let mut query = sessions::table
+            .filter(sessions::group_id.eq_any(user_groups))
+            .inner_join(users::table) // dm details
+            .select((sessions::all_columns, users::all_columns))
+            .into_boxed();
+
+        if let Some(ref dm) = params.dm {
+            // query = query
+            //     .filter(dsl::similar_to(users::username, dm))
+            //     .order(dsl::similarity(users::username, dm).desc())
+            query = query
+                .filter(users::username.eq(dm))
+                .order(sessions::session_date.asc())
+        } else if let Some(ref order) = params.order {
+            match order.to_lowercase().as_ref() {
+                "asc" => query = query.order(sessions::session_date.asc()),
+                "desc" => query = query.order(sessions::session_date.desc()),
+                _ => query = query.order(sessions::session_date.asc()),
+            }
+        } else {
+            // default to asc
+            query = query.order(sessions::session_date.asc())
+        }
+
+        if let Some(ref title) = params.title {
+            query = query
+                .filter(dsl::similar_to(sessions::title, title))
+                .order(dsl::similarity(sessions::title, title).desc())
+        }
+
+        let (sessions_and_dms, pages_count) = query
+            .paginate(params.page.unwrap_or(1))
+            .per_page(params.limit.unwrap_or(DEFAULT_LIMIT))
+            .load_and_count_pages::<(Session, User)>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Sessions not found".to_string())
+            })?;
+
+        let (sessions, dms): (Vec<Session>, Vec<Profile>) = sessions_and_dms
+            .into_iter()
+            .map(|(session, dm)| (session, dm.to_profile()))
+            .unzip();
+        let session_jsons = populate_all(&sessions, dms, connection)?;
+
+        Session::expand_occurrences(&sessions, session_jsons, connection)
+            .map(|session_jsons| (session_jsons, pages_count))
+    }
+
+    /// every session `read` would show the user, across all pages. Recurring sessions are left
+    /// as a single series, given along with their edited or cancelled occurrences.
+    pub fn read_all(
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Vec<(Session, Vec<SessionOccurrence>)>, ApiError> {
+        let user_groups = groups_users::table
+            .filter(groups_users::columns::user_id.eq(user_id))
+            .filter(groups_users::columns::admin_accepted.eq(true))
+            .filter(groups_users::columns::user_accepted.eq(true))
+            .select(groups_users::columns::group_id);
+
+        let sessions = sessions::table
+            .filter(sessions::group_id.eq_any(user_groups))
+            .order(sessions::session_date.asc())
+            .load::<Session>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Sessions not found".to_string())
+            })?;
+        let overrides = SessionOccurrence::belonging_to(&sessions)
+            .load::<SessionOccurrence>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Occurrences not found".to_string())
+            })?
+            .grouped_by(&sessions);
+
+        Ok(sessions.into_iter().zip(overrides).collect())
     }
 
-    pub fn find(session_id: i32, connection: &PgConnection) -> Result<Session, ApiResponse> {
+    pub fn find(session_id: i32, connection: &PgConnection) -> Result<Session, ApiError> {
         sessions::table
             .find(session_id)
             .first::<Session>(connection)
             .map(|session| session)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Session not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Session not found".to_string())
             })
     }
 
+    /// the session with the given slug, if the user is allowed to see it
+    pub fn find_visible(
+        session_slug: &str,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Session, ApiError> {
+        let session = sessions::table
+            .filter(sessions::slug.eq(session_slug))
+            .first::<Session>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Session not found".to_string())
+            })?;
+
+        require_visible(&session, user_id, connection)?;
+
+        Ok(session)
+    }
+
     pub fn find_as_json(
         session_slug: &str,
+        user_id: i32,
         connection: &PgConnection,
-    ) -> Result<SessionJson, ApiResponse> {
+    ) -> Result<SessionJson, ApiError> {
         let session_and_dm = sessions::table
             .filter(sessions::slug.eq(session_slug))
             .inner_join(users::table) // dm details
@@ -208,19 +819,19 @@ impl Session {
             .first::<(Session, User)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Session not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Session not found".to_string())
             })?;
         let (session, dm) = session_and_dm;
+
+        require_visible(&session, user_id, connection)?;
+
         populate(&session, dm.to_profile(), connection).map_err(|response| response)
     }
 
     pub fn read_users(
         session_id: i32,
         connection: &PgConnection,
-    ) -> Result<Vec<Profile>, ApiResponse> {
+    ) -> Result<Vec<Profile>, ApiError> {
         let session = Session::find(session_id, connection).map_err(|response| response)?;
 
         SessionUser::belonging_to(&session)
@@ -232,20 +843,18 @@ impl Session {
             .map(|users| users.iter().map(|user| user.to_profile()).collect())
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Users not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Users not found".to_string())
             })
     }
 
     pub fn read_guests(
         session_id: i32,
         connection: &PgConnection,
-    ) -> Result<Vec<(i32, String)>, ApiResponse> {
+    ) -> Result<Vec<(i32, String)>, ApiError> {
         let session = Session::find(session_id, connection).map_err(|response| response)?;
 
         SessionGuest::belonging_to(&session)
+            .filter(sessions_guests::columns::upgraded_at.is_null())
             .select((
                 sessions_guests::columns::guest_id,
                 sessions_guests::columns::guest_name,
@@ -254,10 +863,7 @@ impl Session {
             .map(|guests| guests)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Guests not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Guests not found".to_string())
             })
     }
 
@@ -265,9 +871,10 @@ impl Session {
         session_id: i32,
         guest_id: i32,
         connection: &PgConnection,
-    ) -> Result<(i32, String), ApiResponse> {
+    ) -> Result<(i32, String), ApiError> {
         sessions_guests::table
             .find((session_id, guest_id))
+            .filter(sessions_guests::columns::upgraded_at.is_null())
             .select((
                 sessions_guests::columns::guest_id,
                 sessions_guests::columns::guest_name,
@@ -276,10 +883,7 @@ impl Session {
             .map(|guests| guests)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Guest not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Guest not found".to_string())
             })
     }
 
@@ -287,137 +891,167 @@ impl Session {
         session_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let new_session_user = &InsertableSessionUser {
-            session_id,
-            user_id,
-            dm_accepted: false,
-            user_accepted: true,
-        };
-
-        diesel::insert_into(sessions_users::table)
-            .values(new_session_user)
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not request to join the session", "details": error.to_string() }),
-                    status: Status::InternalServerError,
-                }
-            })?;
-        Ok(())
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let new_session_user = &InsertableSessionUser {
+                session_id,
+                user_id,
+                dm_accepted: false,
+                user_accepted: true,
+                role: SessionRole::Player.as_str().to_string(),
+            };
+
+            diesel::insert_into(sessions_users::table)
+                .values(new_session_user)
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not request to join the session".to_string())
+                })?;
+
+            let session = Session::find(session_id, connection)?;
+            Notification::notify_session(
+                session.dm,
+                NotificationKind::SessionRequestReceived,
+                user_id,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn accept_to_join(
         session: &Session,
         user_id: i32,
+        accepted_by: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let session_user = SessionUser::belonging_to(session)
-            .filter(sessions_users::columns::dm_accepted.eq(false))
-            .filter(sessions_users::columns::user_accepted.eq(true))
-            .filter(sessions_users::columns::user_id.eq(user_id))
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-        let updated_session_user = &UpdateSessionUser {
-            dm_accepted: true,
-            user_accepted: true,
-        };
-
-        diesel::update(sessions_users::table.find((session_user.session_id, user_id)))
-            .set(updated_session_user)
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User could not be accepted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-
-        Ok(())
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let session_user = SessionUser::belonging_to(session)
+                .filter(sessions_users::columns::dm_accepted.eq(false))
+                .filter(sessions_users::columns::user_accepted.eq(true))
+                .filter(sessions_users::columns::user_id.eq(user_id))
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+            let updated_session_user = &UpdateSessionUser {
+                dm_accepted: true,
+                user_accepted: true,
+            };
+
+            diesel::update(sessions_users::table.find((session_user.session_id, user_id)))
+                .set(updated_session_user)
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User could not be accepted".to_string())
+                })?;
+
+            Notification::notify_session(
+                user_id,
+                NotificationKind::SessionRequestAccepted,
+                accepted_by,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn invite_to_join(
         session_id: i32,
         user_id: i32,
         group_id: i32,
+        invited_by: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        if !GroupUser::check_user_in_group(group_id, user_id, connection)? {
-            return Err(ApiResponse {
-                json: json!({"error": "User not in the same group as the session" }),
-                status: Status::InternalServerError,
-            });
-        }
-        let new_session_user = &InsertableSessionUser {
-            session_id,
-            user_id,
-            dm_accepted: true,
-            user_accepted: false,
-        };
-
-        diesel::insert_into(sessions_users::table)
-            .values(new_session_user)
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not make an invite to the user to join the session" }),
-                    status: Status::InternalServerError,
-                }
-            })?;
-
-        Ok(())
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            if !GroupUser::check_user_in_group(group_id, user_id, connection)? {
+                return Err(ApiError::Forbidden(
+                    "that user is not a member of the session's group".to_string(),
+                ));
+            }
+            let new_session_user = &InsertableSessionUser {
+                session_id,
+                user_id,
+                dm_accepted: true,
+                user_accepted: false,
+                role: SessionRole::Player.as_str().to_string(),
+            };
+
+            diesel::insert_into(sessions_users::table)
+                .values(new_session_user)
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal(
+                        "Could not make an invite to the user to join the session".to_string(),
+                    )
+                })?;
+
+            let session = Session::find(session_id, connection)?;
+            Notification::notify_session(
+                user_id,
+                NotificationKind::SessionInviteReceived,
+                invited_by,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn accept_invite_to_join(
         session: &Session,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let session_user = SessionUser::belonging_to(session)
-            .filter(sessions_users::columns::dm_accepted.eq(true))
-            .filter(sessions_users::columns::user_accepted.eq(false))
-            .filter(sessions_users::columns::user_id.eq(user_id))
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-        let updated_session_user = &UpdateSessionUser {
-            dm_accepted: true,
-            user_accepted: true,
-        };
-
-        diesel::update(sessions_users::table.find((session_user.session_id, user_id)))
-            .set(updated_session_user)
-            .get_result::<SessionUser>(connection)
-            .map_err(|error| {
-                println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not accept invite", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
-
-        Ok(())
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let session_user = SessionUser::belonging_to(session)
+                .filter(sessions_users::columns::dm_accepted.eq(true))
+                .filter(sessions_users::columns::user_accepted.eq(false))
+                .filter(sessions_users::columns::user_id.eq(user_id))
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+            let updated_session_user = &UpdateSessionUser {
+                dm_accepted: true,
+                user_accepted: true,
+            };
+
+            diesel::update(sessions_users::table.find((session_user.session_id, user_id)))
+                .set(updated_session_user)
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("Could not accept invite".to_string())
+                })?;
+
+            Notification::notify_session(
+                session.dm,
+                NotificationKind::SessionInviteAccepted,
+                user_id,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
     }
 
     pub fn is_user_waiting_to_join(
         session_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         sessions_users::table
             .find((session_id, user_id))
             .select((
@@ -427,10 +1061,7 @@ impl Session {
             .get_result::<(bool, bool)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Session/User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Session/User not found".to_string())
             })
             .map(|(dm_accepted, user_accepted)| !dm_accepted && user_accepted)
     }
@@ -439,7 +1070,7 @@ impl Session {
         session_id: i32,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         sessions_users::table
             .find((session_id, user_id))
             .select((
@@ -449,81 +1080,319 @@ impl Session {
             .get_result::<(bool, bool)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Session/User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Session/User not found".to_string())
             })
             .map(|(dm_accepted, user_accepted)| dm_accepted && !user_accepted)
     }
 
-    pub fn remove_user(
+    /// refuse a user's request to join the session
+    pub fn deny_to_join(
+        session: &Session,
+        user_id: i32,
+        denied_by: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let denied = diesel::delete(
+                sessions_users::table
+                    .find((session.id, user_id))
+                    .filter(sessions_users::columns::dm_accepted.eq(false))
+                    .filter(sessions_users::columns::user_accepted.eq(true)),
+            )
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Request could not be denied".to_string())
+            })?;
+            if denied == 0 {
+                return Err(ApiError::NotFound("Request not found".to_string()));
+            }
+
+            Notification::notify_session(
+                user_id,
+                NotificationKind::SessionRequestDenied,
+                denied_by,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
+    }
+
+    /// turn down an invite to join the session
+    pub fn decline_invite_to_join(
+        session: &Session,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let declined = diesel::delete(
+                sessions_users::table
+                    .find((session.id, user_id))
+                    .filter(sessions_users::columns::dm_accepted.eq(true))
+                    .filter(sessions_users::columns::user_accepted.eq(false)),
+            )
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Invite could not be declined".to_string())
+            })?;
+            if declined == 0 {
+                return Err(ApiError::NotFound("Invite not found".to_string()));
+            }
+
+            Notification::notify_session(
+                session.dm,
+                NotificationKind::SessionInviteDeclined,
+                user_id,
+                session.id,
+                connection,
+            )?;
+
+            Ok(())
+        })
+    }
+
+    pub fn remove_user(
+        session: &Session,
+        user_id: i32,
+        removed_by: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            let session_user = SessionUser::belonging_to(session)
+                .filter(sessions_users::columns::user_id.eq(user_id))
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User not found".to_string())
+                })?;
+
+            diesel::delete(sessions_users::table.find((session_user.session_id, user_id)))
+                .execute(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::NotFound("User could not be deleted".to_string())
+                })?;
+
+            // either the user left or the DM removed them
+            if removed_by == user_id {
+                Notification::notify_session(
+                    session.dm,
+                    NotificationKind::SessionUserLeft,
+                    user_id,
+                    session.id,
+                    connection,
+                )?;
+            } else {
+                Notification::notify_session(
+                    user_id,
+                    NotificationKind::SessionUserRemoved,
+                    removed_by,
+                    session.id,
+                    connection,
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn remove_guest(
+        session: &Session,
+        guest_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let session_guest = SessionGuest::belonging_to(session)
+            .filter(sessions_guests::columns::guest_id.eq(guest_id))
+            .get_result::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Guest not found".to_string())
+            })?;
+
+        diesel::delete(sessions_guests::table.find((session_guest.session_id, guest_id)))
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Guest could not be deleted".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    /// set a member's RSVP (and note) for the session
+    pub fn rsvp(
+        session: &Session,
+        user_id: i32,
+        rsvp: &UpdateRsvp,
+        connection: &PgConnection,
+    ) -> Result<SessionMember, ApiError> {
+        if let Some(role) = SessionUser::role(session.id, user_id, connection)? {
+            if !role.can(SessionPermission::Rsvp) {
+                return Err(ApiError::Forbidden(
+                    SessionPermission::Rsvp.denied().to_string(),
+                ));
+            }
+        }
+
+        let session_user = diesel::update(
+            SessionUser::belonging_to(session)
+                .filter(sessions_users::columns::dm_accepted.eq(true))
+                .filter(sessions_users::columns::user_accepted.eq(true))
+                .filter(sessions_users::columns::user_id.eq(user_id)),
+        )
+        .set(rsvp)
+        .get_result::<SessionUser>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("You are not a member of this session".to_string())
+        })?;
+
+        let user = User::find(user_id, connection).map_err(|response| response)?;
+
+        Ok(session_user.to_member(&user))
+    }
+
+    /// set a member's RSVP (and note) for a single occurrence of a recurring session, instead of
+    /// the RSVP they gave for the whole series
+    pub fn rsvp_occurrence(
         session: &Session,
+        occurrence_date: DateTime<Utc>,
         user_id: i32,
+        rsvp: &UpdateRsvp,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
+    ) -> Result<SessionMember, ApiError> {
+        session.check_occurrence(occurrence_date)?;
+
         let session_user = SessionUser::belonging_to(session)
+            .filter(sessions_users::columns::dm_accepted.eq(true))
+            .filter(sessions_users::columns::user_accepted.eq(true))
             .filter(sessions_users::columns::user_id.eq(user_id))
-            .get_result::<SessionUser>(connection)
+            .first::<SessionUser>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("You are not a member of this session".to_string())
             })?;
 
-        diesel::delete(sessions_users::table.find((session_user.session_id, user_id)))
-            .execute(connection)
+        let can_rsvp = session_user
+            .role
+            .parse::<SessionRole>()
+            .map_or(false, |role| role.can(SessionPermission::Rsvp));
+        if !can_rsvp {
+            return Err(ApiError::Forbidden(
+                SessionPermission::Rsvp.denied().to_string(),
+            ));
+        }
+
+        let session_rsvp = diesel::insert_into(sessions_rsvps::table)
+            .values(&InsertableSessionRsvp {
+                session_id: session.id,
+                user_id,
+                occurrence_date,
+                rsvp: rsvp.rsvp.clone(),
+                rsvp_note: rsvp.rsvp_note.clone(),
+            })
+            .on_conflict((
+                sessions_rsvps::session_id,
+                sessions_rsvps::occurrence_date,
+                sessions_rsvps::user_id,
+            ))
+            .do_update()
+            .set((
+                sessions_rsvps::rsvp.eq(&rsvp.rsvp),
+                sessions_rsvps::rsvp_note.eq(&rsvp.rsvp_note),
+            ))
+            .get_result::<SessionRsvp>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::Internal("Could not RSVP to the occurrence".to_string())
             })?;
 
-        Ok(())
+        let user = User::find(user_id, connection).map_err(|response| response)?;
+        let mut members = vec![session_user.to_member(&user)];
+        apply_rsvps(&mut members, &[session_rsvp], occurrence_date);
+
+        members
+            .pop()
+            .ok_or_else(|| ApiError::NotFound("You are not a member of this session".to_string()))
     }
 
-    pub fn remove_guest(
+    /// summarise the members' RSVPs, for the DM to check before the session, or before a single
+    /// occurrence of it if it recurs. Guests only RSVP to the whole series.
+    pub fn headcount(
         session: &Session,
-        guest_id: i32,
+        occurrence_date: Option<DateTime<Utc>>,
         connection: &PgConnection,
-    ) -> Result<(), ApiResponse> {
-        let session_guest = SessionGuest::belonging_to(session)
-            .filter(sessions_guests::columns::guest_id.eq(guest_id))
-            .get_result::<SessionGuest>(connection)
+    ) -> Result<Headcount, ApiError> {
+        // the caller has already checked who can see the headcount, so the members are given
+        // as they are, without hiding anything from a viewer
+        let mut members = SessionUser::belonging_to(session)
+            .filter(sessions_users::columns::dm_accepted.eq(true))
+            .filter(sessions_users::columns::user_accepted.eq(true))
+            .inner_join(users::table)
+            .load::<(SessionUser, User)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Guest not found", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
-            })?;
+                ApiError::NotFound("Members not found".to_string())
+            })?
+            .into_iter()
+            .map(|(session_user, user)| session_user.to_member(&user))
+            .collect::<Vec<_>>();
+
+        if let Some(occurrence_date) = occurrence_date {
+            session.check_occurrence(occurrence_date)?;
+
+            let rsvps = SessionRsvp::belonging_to(session)
+                .filter(sessions_rsvps::occurrence_date.eq(occurrence_date))
+                .load::<SessionRsvp>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("RSVPs not found".to_string())
+                })?;
+            apply_rsvps(&mut members, &rsvps, occurrence_date);
+        }
 
-        diesel::delete(sessions_guests::table.find((session_guest.session_id, guest_id)))
-            .execute(connection)
+        let guest_rsvps = SessionGuest::belonging_to(session)
+            .filter(sessions_guests::columns::upgraded_at.is_null())
+            .select((
+                sessions_guests::columns::guest_id,
+                sessions_guests::columns::guest_name,
+                sessions_guests::columns::rsvp,
+                sessions_guests::columns::rsvp_note,
+            ))
+            .load::<GuestRsvp>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Guest could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Guests not found".to_string())
             })?;
 
-        Ok(())
+        let count = |rsvp: &str| {
+            members.iter().filter(|member| member.rsvp == rsvp).count()
+                + guest_rsvps
+                    .iter()
+                    .filter(|guest| guest.rsvp == rsvp)
+                    .count()
+        };
+
+        Ok(Headcount {
+            going: count("going"),
+            maybe: count("maybe"),
+            not_going: count("not_going"),
+            no_response: count("no_response"),
+            guests: guest_rsvps.len(),
+            members,
+            guest_rsvps,
+        })
     }
 
-    pub fn delete(session: &Session, connection: &PgConnection) -> Result<(), ApiResponse> {
+    pub fn delete(session: &Session, connection: &PgConnection) -> Result<(), ApiError> {
         diesel::delete(sessions::table.find(session.id))
             .execute(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Session could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Session could not be deleted".to_string())
             })?;
 
         Ok(())
@@ -532,33 +1401,234 @@ impl Session {
     pub fn create_guest_token(
         session_id: i32,
         guest_name: &str,
+        guest_email: Option<String>,
+        lifetime: Duration,
+        app_config: &AppConfig,
         connection: &PgConnection,
-    ) -> Result<String, ApiResponse> {
+    ) -> Result<String, ApiError> {
         let new_session_guest = &InsertableSessionGuest {
             session_id,
             guest_name: guest_name.to_string(),
+            guest_email,
+            link_expires_at: Utc::now() + lifetime,
         };
 
-        let guest_id = diesel::insert_into(sessions_guests::table)
+        diesel::insert_into(sessions_guests::table)
             .values(new_session_guest)
             .get_result::<SessionGuest>(connection)
+            .map(|session_guest| session_guest.token(app_config))
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Could not create a guest link with that guest name" }),
-                    status: Status::InternalServerError,
+                match error {
+                    // guest names only have to be unique within a session
+                    diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::UniqueViolation,
+                        ref info,
+                    ) if info.constraint_name()
+                        == Some("sessions_guests_session_id_guest_name_key") =>
+                    {
+                        ApiError::invalid_field(
+                            "guest_name",
+                            "This session already has a guest with that name",
+                        )
+                    }
+                    _ => ApiError::Internal("Could not create a guest link".to_string()),
                 }
-            })?
-            .guest_id;
+            })
+    }
 
-        let token = GuestAuth {
-            session_id,
-            guest_id,
-            guest_name: guest_name.to_string(),
-        }
-        .token();
+    /// replace a guest's link with a new one, the old link stops working
+    pub fn rotate_guest_link(
+        session: &Session,
+        guest_id: i32,
+        lifetime: Duration,
+        app_config: &AppConfig,
+        connection: &PgConnection,
+    ) -> Result<String, ApiError> {
+        diesel::update(
+            sessions_guests::table
+                .find((session.id, guest_id))
+                .filter(sessions_guests::columns::upgraded_at.is_null()),
+        )
+        .set((
+            sessions_guests::columns::link_version.eq(sessions_guests::columns::link_version + 1),
+            sessions_guests::columns::link_expires_at.eq(Utc::now() + lifetime),
+            sessions_guests::columns::link_revoked_at.eq(None::<DateTime<Utc>>),
+        ))
+        .get_result::<SessionGuest>(connection)
+        .map(|session_guest| session_guest.token(app_config))
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Guest not found".to_string())
+        })
+    }
+
+    /// stop a guest's link from working, without removing the guest from the session
+    pub fn revoke_guest_link(
+        session: &Session,
+        guest_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        diesel::update(sessions_guests::table.find((session.id, guest_id)))
+            .set(sessions_guests::columns::link_revoked_at.eq(Utc::now()))
+            .get_result::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Guest not found".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    /// check a guest link is still the guest's current, unexpired and unrevoked link, recording
+    /// that it was used
+    pub fn use_guest_link(
+        guest_auth: &GuestAuth,
+        connection: &PgConnection,
+    ) -> Result<SessionGuest, ApiError> {
+        diesel::update(
+            sessions_guests::table
+                .find((guest_auth.session_id, guest_auth.guest_id))
+                .filter(sessions_guests::columns::link_version.eq(guest_auth.version))
+                .filter(sessions_guests::columns::link_revoked_at.is_null())
+                .filter(sessions_guests::columns::link_expires_at.gt(Utc::now())),
+        )
+        .set(sessions_guests::columns::link_last_used_at.eq(Utc::now()))
+        .get_result::<SessionGuest>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Unauthorized("this guest link has expired or been revoked".to_string())
+        })
+    }
+
+    /// set a guest's RSVP (and note) for the session using their guest link
+    pub fn guest_rsvp(
+        guest_auth: &GuestAuth,
+        rsvp: &UpdateRsvp,
+        connection: &PgConnection,
+    ) -> Result<SessionGuest, ApiError> {
+        let session_guest = Session::use_guest_link(guest_auth, connection)?;
+
+        diesel::update(&session_guest)
+            .set((
+                sessions_guests::columns::rsvp.eq(&rsvp.rsvp),
+                sessions_guests::columns::rsvp_note.eq(&rsvp.rsvp_note),
+            ))
+            .get_result::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not RSVP to the session".to_string())
+            })
+    }
+
+    /// turn the reminders a guest is sent on or off using their guest link, as guests do not
+    /// have settings of their own
+    pub fn guest_reminders(
+        guest_auth: &GuestAuth,
+        reminders_enabled: bool,
+        connection: &PgConnection,
+    ) -> Result<SessionGuest, ApiError> {
+        let session_guest = Session::use_guest_link(guest_auth, connection)?;
+
+        diesel::update(&session_guest)
+            .set(sessions_guests::columns::reminders_enabled.eq(reminders_enabled))
+            .get_result::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not change the guest's reminders".to_string())
+            })
+    }
+
+    /// turn a guest into a member of the session with the account `user_id`, keeping their RSVP.
+    /// The guest row is kept (with its link revoked) so their history is not lost.
+    pub fn upgrade_guest(
+        guest_auth: &GuestAuth,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<SessionMember, ApiError> {
+        connection.transaction::<_, ApiError, _>(|| {
+            // claim the guest in the same statement that checks the link, so it can only be
+            // upgraded once
+            let session_guest = diesel::update(
+                sessions_guests::table
+                    .find((guest_auth.session_id, guest_auth.guest_id))
+                    .filter(sessions_guests::columns::link_version.eq(guest_auth.version))
+                    .filter(sessions_guests::columns::link_revoked_at.is_null())
+                    .filter(sessions_guests::columns::link_expires_at.gt(Utc::now()))
+                    .filter(sessions_guests::columns::upgraded_at.is_null()),
+            )
+            .set((
+                sessions_guests::columns::upgraded_to.eq(user_id),
+                sessions_guests::columns::upgraded_at.eq(Utc::now()),
+                sessions_guests::columns::link_revoked_at.eq(Utc::now()),
+                sessions_guests::columns::link_last_used_at.eq(Utc::now()),
+            ))
+            .get_result::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                match error {
+                    diesel::result::Error::NotFound => ApiError::Unauthorized(
+                        "this guest link has expired or been revoked".to_string(),
+                    ),
+                    _ => ApiError::Internal("Could not add you to the session".to_string()),
+                }
+            })?;
+
+            // the user may have already asked to join, or been invited
+            let session_user = diesel::insert_into(sessions_users::table)
+                .values((
+                    sessions_users::columns::session_id.eq(session_guest.session_id),
+                    sessions_users::columns::user_id.eq(user_id),
+                    sessions_users::columns::dm_accepted.eq(true),
+                    sessions_users::columns::user_accepted.eq(true),
+                    sessions_users::columns::rsvp.eq(&session_guest.rsvp),
+                    sessions_users::columns::rsvp_note.eq(&session_guest.rsvp_note),
+                ))
+                .on_conflict((
+                    sessions_users::columns::session_id,
+                    sessions_users::columns::user_id,
+                ))
+                .do_update()
+                .set((
+                    sessions_users::columns::dm_accepted.eq(true),
+                    sessions_users::columns::user_accepted.eq(true),
+                    sessions_users::columns::rsvp.eq(&session_guest.rsvp),
+                    sessions_users::columns::rsvp_note.eq(&session_guest.rsvp_note),
+                ))
+                .get_result::<SessionUser>(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not add you to the session".to_string())
+                })?;
+
+            // notified in the same transaction, so the DM only hears of upgrades that happened
+            let session = Session::find(session_user.session_id, connection)?;
+            Notification::notify_session(
+                session.dm,
+                NotificationKind::SessionGuestUpgraded,
+                user_id,
+                session.id,
+                connection,
+            )?;
+
+            let user = User::find(user_id, connection).map_err(|response| response)?;
+
+            Ok(session_user.to_member(&user))
+        })
+    }
 
-        Ok(token)
+    /// every guest with the state of their link, for the DM
+    pub fn read_guest_links(
+        session: &Session,
+        connection: &PgConnection,
+    ) -> Result<Vec<SessionGuest>, ApiError> {
+        SessionGuest::belonging_to(session)
+            .order(sessions_guests::columns::guest_id.asc())
+            .load::<SessionGuest>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::NotFound("Guests not found".to_string())
+            })
     }
 }
 
@@ -570,6 +1640,76 @@ pub struct SessionGuest {
     pub session_id: i32,
     pub guest_id: i32,
     pub guest_name: String,
+    /// only used to send the guest reminders
+    #[serde(skip_serializing)]
+    pub guest_email: Option<String>,
+    #[serde(skip_serializing)]
+    pub link_version: i32,
+    pub link_expires_at: DateTime<Utc>,
+    pub link_revoked_at: Option<DateTime<Utc>>,
+    /// when the guest last opened their link
+    pub link_last_used_at: Option<DateTime<Utc>>,
+    /// one of "going", "maybe", "not_going" or "no_response"
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
+    /// the account the guest registered, kept so the guest's history can still be found
+    pub upgraded_to: Option<i32>,
+    pub upgraded_at: Option<DateTime<Utc>>,
+    /// whether the guest is sent reminders, which they can turn off from their link
+    pub reminders_enabled: bool,
+}
+
+impl SessionGuest {
+    /// the token in the guest's current link
+    pub fn token(&self, app_config: &AppConfig) -> String {
+        GuestAuth {
+            exp: Some(self.link_expires_at.timestamp()),
+            session_id: self.session_id,
+            guest_id: self.guest_id,
+            guest_name: self.guest_name.clone(),
+            version: self.link_version,
+        }
+        .token(app_config)
+    }
+}
+
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
+#[belongs_to(Session)]
+#[primary_key(session_id, occurrence_date)]
+#[table_name = "sessions_occurrences"]
+#[serde(rename_all = "camelCase")]
+pub struct SessionOccurrence {
+    pub session_id: i32,
+    pub occurrence_date: DateTime<Utc>,
+    pub cancelled: bool,
+    pub session_date: Option<DateTime<Utc>>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+}
+
+/// A member's RSVP for a single occurrence of a recurring session
+#[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
+#[belongs_to(Session)]
+#[primary_key(session_id, occurrence_date, user_id)]
+#[table_name = "sessions_rsvps"]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRsvp {
+    pub session_id: i32,
+    pub user_id: i32,
+    pub occurrence_date: DateTime<Utc>,
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
+}
+
+#[table_name = "sessions_rsvps"]
+#[derive(Serialize, Deserialize, Insertable)]
+pub struct InsertableSessionRsvp {
+    pub session_id: i32,
+    pub user_id: i32,
+    pub occurrence_date: DateTime<Utc>,
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
 }
 
 #[derive(Identifiable, Queryable, Debug, Associations, Serialize, Deserialize)]
@@ -582,14 +1722,150 @@ pub struct SessionUser {
     pub user_id: i32,
     pub dm_accepted: bool,
     pub user_accepted: bool,
+    pub rsvp: String,
+    pub rsvp_note: Option<String>,
+    pub role: String,
+}
+
+impl Viewable for Session {
+    const NOT_FOUND: &'static str = "Session not found";
+    const FORBIDDEN: &'static str = "you are not a member of this session or its group";
+
+    /// a session can be seen by its own members (and anyone invited to, or asking to join, it),
+    /// the members of its group, and anyone at all if the group is public. Everyone else gets
+    /// told it exists only if they can see its group.
+    fn visibility(&self, user_id: i32, connection: &PgConnection) -> Result<Visibility, ApiError> {
+        let session_user = sessions_users::table
+            .find((self.id, user_id))
+            .select(sessions_users::columns::user_id)
+            .first::<i32>(connection)
+            .optional()
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Session/User not found".to_string())
+            })?;
+
+        if session_user.is_some() {
+            return Ok(Visibility::Visible);
+        }
+
+        let group = Group::find(self.group_id, connection)?;
+
+        if group.sessions_visible_to(user_id, connection)? {
+            return Ok(Visibility::Visible);
+        }
+
+        match group.visibility(user_id, connection)? {
+            Visibility::Visible => Ok(Visibility::Forbidden),
+            _ => Ok(Visibility::Hidden),
+        }
+    }
 }
 
 impl SessionUser {
+    /// the user's role in the session, if they are a member of it
+    pub fn role(
+        session_id: i32,
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Option<SessionRole>, ApiError> {
+        sessions_users::table
+            .find((session_id, user_id))
+            .filter(sessions_users::columns::dm_accepted.eq(true))
+            .filter(sessions_users::columns::user_accepted.eq(true))
+            .select(sessions_users::columns::role)
+            .first::<String>(connection)
+            .optional()
+            .map(|role| role.and_then(|role| role.parse::<SessionRole>().ok()))
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Session/User not found".to_string())
+            })
+    }
+
+    /// the user's role in the session, or an error if it does not give them `permission`
+    pub fn require_permission(
+        session_id: i32,
+        user_id: i32,
+        permission: SessionPermission,
+        connection: &PgConnection,
+    ) -> Result<SessionRole, ApiError> {
+        match SessionUser::role(session_id, user_id, connection)? {
+            Some(role) if role.can(permission) => Ok(role),
+            _ => Err(ApiError::Forbidden(permission.denied().to_string())),
+        }
+    }
+
+    /// give a member a new role. Nobody can make someone the DM this way (the DM is changed by
+    /// changing the session's dm), or change the role of someone who is not below them, or give
+    /// a role that is not below their own.
+    pub fn set_role(
+        session: &Session,
+        user_id: i32,
+        role: SessionRole,
+        changed_by: i32,
+        connection: &PgConnection,
+    ) -> Result<SessionMember, ApiError> {
+        let changer_role = SessionUser::require_permission(
+            session.id,
+            changed_by,
+            SessionPermission::ManageRoles,
+            connection,
+        )?;
+
+        let current_role =
+            SessionUser::role(session.id, user_id, connection)?.ok_or_else(|| {
+                ApiError::NotFound("That user is not a member of this session".to_string())
+            })?;
+
+        if role == SessionRole::Dm {
+            return Err(ApiError::invalid_field(
+                "role",
+                "Change the session's dm to hand it over",
+            ));
+        }
+
+        if !changer_role.outranks(current_role) || !changer_role.outranks(role) {
+            return Err(ApiError::Forbidden(
+                SessionPermission::ManageRoles.denied().to_string(),
+            ));
+        }
+
+        let session_user = diesel::update(
+            SessionUser::belonging_to(session)
+                .filter(sessions_users::columns::dm_accepted.eq(true))
+                .filter(sessions_users::columns::user_accepted.eq(true))
+                .filter(sessions_users::columns::user_id.eq(user_id))
+                .filter(sessions_users::columns::role.ne(SessionRole::Dm.as_str())),
+        )
+        .set(sessions_users::columns::role.eq(role.as_str()))
+        .get_result::<SessionUser>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound(
+                "That user is not a member of this session, or is its DM".to_string(),
+            )
+        })?;
+
+        let user = User::find(user_id, connection)?;
+
+        Ok(session_user.to_member(&user))
+    }
+
+    pub fn to_member(&self, user: &User) -> SessionMember {
+        SessionMember {
+            profile: user.to_profile(),
+            role: self.role.clone(),
+            rsvp: self.rsvp.clone(),
+            rsvp_note: self.rsvp_note.clone(),
+        }
+    }
+
     pub fn check_user_in_session(
         session: &Session,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         SessionUser::belonging_to(session)
             .filter(sessions_users::columns::dm_accepted.eq(true))
             .filter(sessions_users::columns::user_accepted.eq(true))
@@ -598,10 +1874,7 @@ impl SessionUser {
             .map(|_| true)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "errors": { "dm": [ "That user is not a member of this session" ] } }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("That user is not a member of this session".to_string())
             })
     }
 }
@@ -631,6 +1904,8 @@ pub struct InsertableSession {
     pub session_date: DateTime<Utc>,
     pub colour: String,
     pub group_id: i32,
+    pub recurrence: Option<String>,
+    pub duration_minutes: i32,
 }
 
 #[table_name = "sessions_users"]
@@ -640,6 +1915,7 @@ pub struct InsertableSessionUser {
     pub user_id: i32,
     pub dm_accepted: bool,
     pub user_accepted: bool,
+    pub role: String,
 }
 
 #[table_name = "sessions_guests"]
@@ -647,52 +1923,70 @@ pub struct InsertableSessionUser {
 pub struct InsertableSessionGuest {
     pub session_id: i32,
     pub guest_name: String,
+    pub guest_email: Option<String>,
+    pub link_expires_at: DateTime<Utc>,
+}
+
+#[table_name = "sessions_occurrences"]
+#[derive(Serialize, Deserialize, Insertable)]
+pub struct InsertableSessionOccurrence {
+    pub session_id: i32,
+    pub occurrence_date: DateTime<Utc>,
+    pub cancelled: bool,
+    pub session_date: Option<DateTime<Utc>>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+}
+
+/// the edits to a single occurrence: `None` leaves a field as it is, and `Some(None)` resets it
+/// to the series' value
+#[derive(AsChangeset, Default)]
+#[table_name = "sessions_occurrences"]
+pub struct UpdateSessionOccurrence {
+    pub session_date: Option<Option<DateTime<Utc>>>,
+    pub title: Option<Option<String>>,
+    pub description: Option<Option<String>>,
+    pub colour: Option<Option<String>>,
+}
+
+impl UpdateSessionOccurrence {
+    pub fn is_empty(&self) -> bool {
+        self.session_date.is_none()
+            && self.title.is_none()
+            && self.description.is_none()
+            && self.colour.is_none()
+    }
 }
 
 impl InsertableSession {
+    /// the schedule the session will have once created
+    pub fn schedule(&self) -> Schedule {
+        schedule_of(
+            self.session_date,
+            self.duration_minutes,
+            self.recurrence.as_deref(),
+            &[],
+        )
+    }
+
     pub fn create(
         session: InsertableSession,
         creator_id: i32,
         connection: &PgConnection,
-    ) -> Result<SessionJson, ApiResponse> {
-        //check the user is in the group they want to add the session to
-        let _is_user_in_group =
-            GroupUser::check_user_in_group(session.group_id, creator_id, connection)
-                .map_err(|response| response)?;
+    ) -> Result<SessionJson, ApiError> {
+        //check the user can add sessions to the group
+        GroupUser::require_permission(
+            session.group_id,
+            creator_id,
+            GroupPermission::CreateSessions,
+            connection,
+        )?;
 
         match connection
             .build_transaction()
             .run::<Session, diesel::result::Error, _>(|| {
-                let new_session = diesel::insert_into(sessions::table)
-                    .values(&session)
-                    .get_result::<Session>(connection)?;
-
-                let new_session_user = &InsertableSessionUser {
-                    session_id: new_session.id,
-                    user_id: new_session.dm,
-                    dm_accepted: true,
-                    user_accepted: true,
-                };
-
-                diesel::insert_into(sessions_users::table)
-                    .values(new_session_user)
-                    .get_result::<SessionUser>(connection)?;
-
-                // add creator of session as member of the party if they are not the dm
-                if new_session.dm != creator_id {
-                    let creator_session_user = &InsertableSessionUser {
-                        session_id: new_session.id,
-                        user_id: creator_id,
-                        dm_accepted: true,
-                        user_accepted: true,
-                    };
-
-                    diesel::insert_into(sessions_users::table)
-                        .values(creator_session_user)
-                        .get_result::<SessionUser>(connection)?;
-                }
-
-                Ok(new_session)
+                InsertableSession::insert(&session, creator_id, connection)
             }) {
             Ok(session) => {
                 let dm = User::find(session.dm, connection)
@@ -705,13 +1999,52 @@ impl InsertableSession {
             }
             Err(error) => {
                 println!("Error: {:#?}", error);
-                Err(ApiResponse {
-                    json: json!({ "errors": { "title": [ "Title must be unique" ] }, "details": error.to_string() }), // assume this as the most common cause due to slug and title not being unique
-                    status: Status::InternalServerError,
-                })
+                // assume this as the most common cause due to slug and title not being unique
+                Err(ApiError::invalid_field("title", "Title must be unique"))
             }
         }
     }
+
+    /// add the session along with its DM, and its creator if they are someone else. This does not
+    /// check the creator is allowed to, and should be run in a transaction.
+    pub fn insert(
+        session: &InsertableSession,
+        creator_id: i32,
+        connection: &PgConnection,
+    ) -> Result<Session, diesel::result::Error> {
+        let new_session = diesel::insert_into(sessions::table)
+            .values(session)
+            .get_result::<Session>(connection)?;
+
+        let new_session_user = &InsertableSessionUser {
+            session_id: new_session.id,
+            user_id: new_session.dm,
+            dm_accepted: true,
+            user_accepted: true,
+            role: SessionRole::Dm.as_str().to_string(),
+        };
+
+        diesel::insert_into(sessions_users::table)
+            .values(new_session_user)
+            .get_result::<SessionUser>(connection)?;
+
+        // add creator of session as member of the party if they are not the dm
+        if new_session.dm != creator_id {
+            let creator_session_user = &InsertableSessionUser {
+                session_id: new_session.id,
+                user_id: creator_id,
+                dm_accepted: true,
+                user_accepted: true,
+                role: SessionRole::CoDm.as_str().to_string(),
+            };
+
+            diesel::insert_into(sessions_users::table)
+                .values(creator_session_user)
+                .get_result::<SessionUser>(connection)?;
+        }
+
+        Ok(new_session)
+    }
 }
 
 // TODO: remove clone when diesel will allow skipping fields
@@ -725,73 +2058,259 @@ pub struct UpdateSession {
     #[serde(skip)]
     slug: Option<String>,
     dm: Option<i32>,
+    /// `Some(None)` stops the session from recurring
+    recurrence: Option<Option<String>>,
+    duration_minutes: Option<i32>,
 }
 
 impl UpdateSession {
+    /// a rescheduled session is checked for conflicts with the other sessions of its members,
+    /// which are returned alongside it, or rejected with a 409 if `reject_conflicts` is set
     pub fn update(
         id: i32,
         session: &UpdateSession,
+        reject_conflicts: bool,
         connection: &PgConnection,
-    ) -> Result<SessionJson, ApiResponse> {
-        let updated_session = diesel::update(sessions::table.find(id))
-            .set(session)
-            .get_result::<Session>(connection)
-            .map_err(|error| {
-                println!("Cannot update session: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "error": "cannot update session" }),
-                    status: Status::UnprocessableEntity,
-                }
-            })?;
+    ) -> Result<(SessionJson, Vec<SessionJson>), ApiError> {
+        let previous = Session::find(id, connection)?;
+        let previous_dm = previous.dm;
+
+        let (updated_session, conflicts) = connection.transaction::<_, ApiError, _>(|| {
+            let updated_session = diesel::update(sessions::table.find(id))
+                .set(session)
+                .get_result::<Session>(connection)
+                .map_err(|error| {
+                    println!("Cannot update session: {:#?}", error);
+                    ApiError::Conflict("cannot update session".to_string())
+                })?;
+
+            updated_session.move_occurrences(&previous, connection)?;
+
+            if updated_session.dm != previous_dm {
+                // the old DM stays on as a co-DM, the new one joins if they had not already
+                diesel::update(sessions_users::table.find((id, previous_dm)))
+                    .set(sessions_users::columns::role.eq(SessionRole::CoDm.as_str()))
+                    .execute(connection)?;
+
+                diesel::insert_into(sessions_users::table)
+                    .values(&InsertableSessionUser {
+                        session_id: id,
+                        user_id: updated_session.dm,
+                        dm_accepted: true,
+                        user_accepted: true,
+                        role: SessionRole::Dm.as_str().to_string(),
+                    })
+                    .on_conflict((
+                        sessions_users::columns::session_id,
+                        sessions_users::columns::user_id,
+                    ))
+                    .do_update()
+                    .set((
+                        sessions_users::columns::dm_accepted.eq(true),
+                        sessions_users::columns::user_accepted.eq(true),
+                        sessions_users::columns::role.eq(SessionRole::Dm.as_str()),
+                    ))
+                    .execute(connection)?;
+
+                Notification::notify_session(
+                    updated_session.dm,
+                    NotificationKind::SessionDMChanged,
+                    previous_dm,
+                    id,
+                    connection,
+                )?;
+            }
+
+            let conflicts = if updated_session.session_date != previous.session_date
+                || updated_session.recurrence != previous.recurrence
+                || updated_session.duration_minutes != previous.duration_minutes
+            {
+                let member_ids = sessions_users::table
+                    .filter(sessions_users::columns::session_id.eq(id))
+                    .filter(sessions_users::columns::dm_accepted.eq(true))
+                    .filter(sessions_users::columns::user_accepted.eq(true))
+                    .select(sessions_users::columns::user_id)
+                    .load::<i32>(connection)?;
+
+                Session::check_conflicts(
+                    &member_ids,
+                    &updated_session.schedule(connection)?,
+                    Some(id),
+                    reject_conflicts,
+                    connection,
+                )?
+            } else {
+                Vec::new()
+            };
+
+            Ok((updated_session, conflicts))
+        })?;
 
         let dm = User::find(updated_session.dm, connection)
             .map(|user| user.to_profile())
             .map_err(|response| response)?;
 
         populate(&updated_session, dm, connection)
-            .map(|session_json| session_json)
+            .map(|session_json| (session_json, conflicts))
             .map_err(|response| response)
     }
 }
 
+/// the start and end of every instance of a session: each (non cancelled) occurrence up to the
+/// horizon if it recurs, otherwise just the session itself
+fn schedule_of(
+    session_date: DateTime<Utc>,
+    duration_minutes: i32,
+    recurrence: Option<&str>,
+    overrides: &[SessionOccurrence],
+) -> Schedule {
+    let duration = Duration::minutes(i64::from(duration_minutes));
+    let horizon = Utc::now() + Duration::days(RECURRENCE_HORIZON_DAYS);
+
+    let starts = match recurrence.and_then(|recurrence| recurrence.parse::<Recurrence>().ok()) {
+        Some(recurrence) => recurrence
+            .occurrences(session_date, horizon)
+            .into_iter()
+            .filter_map(|occurrence_date| {
+                match overrides
+                    .iter()
+                    .find(|edited| edited.occurrence_date == occurrence_date)
+                {
+                    Some(edited) if edited.cancelled => None,
+                    Some(edited) => Some(edited.session_date.unwrap_or(occurrence_date)),
+                    None => Some(occurrence_date),
+                }
+            })
+            .collect(),
+        None => vec![session_date],
+    };
+
+    starts
+        .into_iter()
+        .map(|start| (start, start + duration))
+        .collect()
+}
+
+/// whether any instance in one schedule overlaps any instance in the other
+fn overlaps(
+    schedule: &[(DateTime<Utc>, DateTime<Utc>)],
+    other_schedule: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> bool {
+    schedule.iter().any(|(start, end)| {
+        other_schedule
+            .iter()
+            .any(|(other_start, other_end)| start < other_end && other_start < end)
+    })
+}
+
+/// replace the members' RSVPs for the whole series with the ones they gave for the occurrence
+/// at `occurrence_date`, if any
+fn apply_rsvps(
+    members: &mut [SessionMember],
+    rsvps: &[SessionRsvp],
+    occurrence_date: DateTime<Utc>,
+) {
+    for member in members.iter_mut() {
+        if let Some(session_rsvp) = rsvps.iter().find(|session_rsvp| {
+            session_rsvp.occurrence_date == occurrence_date
+                && session_rsvp.user_id == member.profile.id()
+        }) {
+            member.rsvp = session_rsvp.rsvp.clone();
+            member.rsvp_note = session_rsvp.rsvp_note.clone();
+        }
+    }
+}
+
+/// the slug of `title`, numbered if another session already has it, e.g. `one-shot-2`
+pub fn unique_slug(
+    title: &str,
+    connection: &PgConnection,
+) -> Result<String, diesel::result::Error> {
+    let slug = slug::slugify(title);
+    let taken = sessions::table
+        .filter(
+            sessions::slug
+                .eq(&slug)
+                .or(sessions::slug.like(format!("{}-%", slug))),
+        )
+        .select(sessions::slug)
+        .load::<String>(connection)?;
+
+    let mut unique_slug = slug.clone();
+    let mut number = 1;
+    while taken.contains(&unique_slug) {
+        number += 1;
+        unique_slug = format!("{}-{}", slug, number);
+    }
+
+    Ok(unique_slug)
+}
+
 pub fn populate(
     session: &Session,
     dm: Profile,
     connection: &PgConnection,
-) -> Result<SessionJson, ApiResponse> {
-    let members = SessionUser::belonging_to(session)
+) -> Result<SessionJson, ApiError> {
+    populate_all(std::slice::from_ref(session), vec![dm], connection)?
+        .pop()
+        .ok_or_else(|| ApiError::NotFound("Session not found".to_string()))
+}
+
+/// `populate` for many sessions at once, each with the profile of its DM in `dms`. Their
+/// members, guests and groups are loaded in one query each, rather than one per session.
+pub fn populate_all(
+    sessions: &[Session],
+    dms: Vec<Profile>,
+    connection: &PgConnection,
+) -> Result<Vec<SessionJson>, ApiError> {
+    let members = SessionUser::belonging_to(sessions)
         .filter(sessions_users::columns::dm_accepted.eq(true))
         .filter(sessions_users::columns::user_accepted.eq(true))
         .inner_join(users::table)
-        .select(users::all_columns)
-        .load::<User>(connection)
+        .load::<(SessionUser, User)>(connection)
         .map_err(|error| {
             println!("Error: {:#?}", error);
-            ApiResponse {
-                json: json!({"error": "Members not found" }),
-                status: Status::NotFound,
-            }
+            ApiError::NotFound("Members not found".to_string())
         })?
-        .iter()
-        .map(|user| user.to_profile())
-        .collect();
-    let guests = SessionGuest::belonging_to(session)
-        .select((sessions_guests::guest_id, sessions_guests::guest_name))
-        .load::<(i32, String)>(connection)
+        .grouped_by(sessions);
+    // guests who registered an account are listed as members instead
+    let guests = SessionGuest::belonging_to(sessions)
+        .filter(sessions_guests::upgraded_at.is_null())
+        .load::<SessionGuest>(connection)
         .map_err(|error| {
             println!("Error: {:#?}", error);
-            ApiResponse {
-                json: json!({"error": "Guests not found" }),
-                status: Status::NotFound,
-            }
+            ApiError::NotFound("Guests not found".to_string())
+        })?
+        .grouped_by(sessions);
+    let group_ids: Vec<i32> = sessions.iter().map(|session| session.group_id).collect();
+    let groups = groups::table
+        .filter(groups::id.eq_any(group_ids))
+        .load::<Group>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Group not found".to_string())
         })?;
-    let group = Group::find(session.group_id, connection).map_err(|error| {
-        println!("Error: {:#?}", error);
-        ApiResponse {
-            json: json!({"error": "Group not found" }),
-            status: Status::NotFound,
-        }
-    })?;
 
-    Ok(session.attach(dm, group, members, guests))
+    sessions
+        .iter()
+        .zip(dms)
+        .zip(members.into_iter().zip(guests))
+        .map(|((session, dm), (members, guests))| {
+            let group = groups
+                .iter()
+                .find(|group| group.id == session.group_id)
+                .cloned()
+                .ok_or_else(|| ApiError::NotFound("Group not found".to_string()))?;
+            let members = members
+                .iter()
+                .map(|(session_user, user)| session_user.to_member(user))
+                .collect();
+            let guests = guests
+                .into_iter()
+                .map(|guest| (guest.guest_id, guest.guest_name))
+                .collect();
+
+            Ok(session.attach(dm, group, members, guests))
+        })
+        .collect()
 }
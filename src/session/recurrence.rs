@@ -0,0 +1,410 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use std::fmt;
+use std::str::FromStr;
+
+/// format of DATE-TIME values in RRULE/EXDATE lines (always UTC)
+const ICAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// format of DATE values in RRULE/EXDATE lines
+const ICAL_DATE_FORMAT: &str = "%Y%m%d";
+
+/// A subset of an RFC 5545 recurrence: weekly RRULEs (INTERVAL=2 for biweekly) with
+/// BYDAY, COUNT or UNTIL, plus any EXDATEs. Occurrences are expanded in UTC from the
+/// session's `session_date`, which acts as DTSTART.
+///
+/// Parsed from (and displayed as) iCalendar content lines, e.g.
+/// `RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TH;COUNT=10\nEXDATE:20200319T190000Z`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub interval: u32,
+    pub by_day: Vec<Weekday>,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    pub exdates: Vec<DateTime<Utc>>,
+}
+
+impl Recurrence {
+    /// every occurrence starting at `start` (inclusive) up to `horizon` (inclusive),
+    /// with EXDATEs removed
+    pub fn occurrences(&self, start: DateTime<Utc>, horizon: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let mut by_day = if self.by_day.is_empty() {
+            vec![start.weekday()]
+        } else {
+            self.by_day.clone()
+        };
+        by_day.sort_by_key(|day| day.num_days_from_monday());
+        by_day.dedup();
+
+        // weeks start on a monday (WKST=MO)
+        let week_start = start - Duration::days(i64::from(start.weekday().num_days_from_monday()));
+
+        let mut occurrences = Vec::new();
+        let mut generated = 0;
+        let mut week = 0;
+        loop {
+            for day in &by_day {
+                let candidate = week_start
+                    + Duration::weeks(week)
+                    + Duration::days(i64::from(day.num_days_from_monday()));
+                if candidate < start {
+                    continue;
+                }
+                if candidate > horizon || self.until.map_or(false, |until| candidate > until) {
+                    return occurrences;
+                }
+                if self.count.map_or(false, |count| generated >= count) {
+                    return occurrences;
+                }
+                // COUNT includes instances that are later removed by EXDATE
+                generated += 1;
+                if !self.exdates.contains(&candidate) {
+                    occurrences.push(candidate);
+                }
+            }
+            week += i64::from(self.interval);
+        }
+    }
+
+    /// whether `date` is one of the (non excluded) occurrences of a series starting at `start`
+    pub fn includes(&self, start: DateTime<Utc>, date: DateTime<Utc>) -> bool {
+        self.occurrences(start, date).contains(&date)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(recurrence: &str) -> Result<Self, Self::Err> {
+        let mut rrule: Option<Recurrence> = None;
+        let mut exdates = Vec::new();
+
+        for line in recurrence
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            if line.starts_with("EXDATE") {
+                let values = line.splitn(2, ':').nth(1).unwrap_or("");
+                for value in values.split(',') {
+                    exdates.push(parse_ical_date_time(value)?);
+                }
+            } else {
+                let rule = if line.starts_with("RRULE:") {
+                    &line["RRULE:".len()..]
+                } else {
+                    line
+                };
+                if rrule.is_some() {
+                    return Err("only one RRULE is supported".to_string());
+                }
+                rrule = Some(parse_rrule(rule)?);
+            }
+        }
+
+        let mut recurrence = rrule.ok_or_else(|| "missing RRULE".to_string())?;
+        recurrence.exdates = exdates;
+        Ok(recurrence)
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RRULE:FREQ=WEEKLY")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let by_day = self
+                .by_day
+                .iter()
+                .map(|day| weekday_to_ical(*day))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(f, ";BYDAY={}", by_day)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format(ICAL_DATE_TIME_FORMAT))?;
+        }
+        if !self.exdates.is_empty() {
+            let exdates = self
+                .exdates
+                .iter()
+                .map(|exdate| exdate.format(ICAL_DATE_TIME_FORMAT).to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(f, "\nEXDATE:{}", exdates)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_rrule(rule: &str) -> Result<Recurrence, String> {
+    let mut frequency = None;
+    let mut recurrence = Recurrence {
+        interval: 1,
+        by_day: Vec::new(),
+        count: None,
+        until: None,
+        exdates: Vec::new(),
+    };
+
+    for part in rule.split(';').filter(|part| !part.is_empty()) {
+        let mut key_value = part.splitn(2, '=');
+        let key = key_value.next().unwrap_or("");
+        let value = key_value
+            .next()
+            .ok_or_else(|| format!("{} is missing a value", key))?;
+
+        match key {
+            "FREQ" => frequency = Some(value),
+            "INTERVAL" => {
+                recurrence.interval = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|interval| *interval > 0)
+                    .ok_or_else(|| "INTERVAL must be a positive number".to_string())?
+            }
+            "BYDAY" => {
+                recurrence.by_day = value
+                    .split(',')
+                    .map(weekday_from_ical)
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            "COUNT" => {
+                recurrence.count = Some(
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| "COUNT must be a positive number".to_string())?,
+                )
+            }
+            "UNTIL" => recurrence.until = Some(parse_ical_date(value)?),
+            "WKST" if value == "MO" => {}
+            _ => return Err(format!("{} is not supported", key)),
+        }
+    }
+
+    match frequency {
+        Some("WEEKLY") => {}
+        Some(_) => return Err("only FREQ=WEEKLY is supported".to_string()),
+        None => return Err("FREQ is required".to_string()),
+    }
+    if recurrence.count.is_some() && recurrence.until.is_some() {
+        return Err("COUNT and UNTIL cannot both be set".to_string());
+    }
+
+    Ok(recurrence)
+}
+
+fn parse_ical_date_time(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, ICAL_DATE_TIME_FORMAT)
+        .map(|date_time| Utc.from_utc_datetime(&date_time))
+        .map_err(|_| format!("{} is not a valid UTC date-time", value))
+}
+
+fn parse_ical_date(value: &str) -> Result<DateTime<Utc>, String> {
+    parse_ical_date_time(value).or_else(|_| {
+        NaiveDate::parse_from_str(value.trim(), ICAL_DATE_FORMAT)
+            // an UNTIL date covers the whole day
+            .map(|date| Utc.from_utc_datetime(&date.and_hms(23, 59, 59)))
+            .map_err(|_| format!("{} is not a valid UTC date", value))
+    })
+}
+
+fn weekday_from_ical(day: &str) -> Result<Weekday, String> {
+    match day.trim() {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("{} is not a valid BYDAY value", day)),
+    }
+}
+
+fn weekday_to_ical(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a thursday
+    fn start() -> DateTime<Utc> {
+        Utc.ymd(2020, 3, 5).and_hms(19, 0, 0)
+    }
+
+    fn far_horizon() -> DateTime<Utc> {
+        Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let text = "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TH;COUNT=10\nEXDATE:20200319T190000Z";
+        let recurrence = text.parse::<Recurrence>().expect("valid recurrence");
+
+        assert_eq!(recurrence.interval, 2);
+        assert_eq!(recurrence.by_day, vec![Weekday::Thu]);
+        assert_eq!(recurrence.count, Some(10));
+        assert_eq!(recurrence.until, None);
+        assert_eq!(
+            recurrence.exdates,
+            vec![Utc.ymd(2020, 3, 19).and_hms(19, 0, 0)]
+        );
+        assert_eq!(recurrence.to_string(), text);
+    }
+
+    #[test]
+    fn test_parse_without_rrule_prefix() {
+        let recurrence = "FREQ=WEEKLY;BYDAY=MO,TH"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(recurrence.interval, 1);
+        assert_eq!(recurrence.by_day, vec![Weekday::Mon, Weekday::Thu]);
+        assert_eq!(recurrence.to_string(), "RRULE:FREQ=WEEKLY;BYDAY=MO,TH");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for invalid in &[
+            "",
+            "RRULE:BYDAY=TH",
+            "RRULE:FREQ=DAILY",
+            "RRULE:FREQ=WEEKLY;INTERVAL=0",
+            "RRULE:FREQ=WEEKLY;COUNT=0",
+            "RRULE:FREQ=WEEKLY;BYDAY=XX",
+            "RRULE:FREQ=WEEKLY;BYMONTH=1",
+            "RRULE:FREQ=WEEKLY;COUNT=2;UNTIL=20200401",
+            "RRULE:FREQ=WEEKLY\nRRULE:FREQ=WEEKLY",
+            "RRULE:FREQ=WEEKLY\nEXDATE:20200319",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        let recurrence = "RRULE:FREQ=WEEKLY;COUNT=3"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(
+            recurrence.occurrences(start(), far_horizon()),
+            vec![
+                start(),
+                start() + Duration::weeks(1),
+                start() + Duration::weeks(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_includes_exdates() {
+        let recurrence = "RRULE:FREQ=WEEKLY;COUNT=3\nEXDATE:20200312T190000Z"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(
+            recurrence.occurrences(start(), far_horizon()),
+            vec![start(), start() + Duration::weeks(2)]
+        );
+        assert!(!recurrence.includes(start(), start() + Duration::weeks(1)));
+    }
+
+    #[test]
+    fn test_until_date_covers_the_whole_day() {
+        let recurrence = "RRULE:FREQ=WEEKLY;UNTIL=20200319"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(
+            recurrence.occurrences(start(), far_horizon()),
+            vec![
+                start(),
+                start() + Duration::weeks(1),
+                start() + Duration::weeks(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_until_date_time_is_inclusive() {
+        let recurrence = "RRULE:FREQ=WEEKLY;UNTIL=20200312T190000Z"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(
+            recurrence.occurrences(start(), far_horizon()),
+            vec![start(), start() + Duration::weeks(1)]
+        );
+    }
+
+    #[test]
+    fn test_horizon_ends_the_window() {
+        let recurrence = "RRULE:FREQ=WEEKLY"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        // the horizon itself is included
+        let horizon = start() + Duration::weeks(2);
+        assert_eq!(
+            recurrence.occurrences(start(), horizon),
+            vec![start(), start() + Duration::weeks(1), horizon]
+        );
+        assert!(recurrence
+            .occurrences(start(), start() - Duration::days(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_by_day_skips_days_before_the_start() {
+        let recurrence = "RRULE:FREQ=WEEKLY;BYDAY=MO,TH"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        // the monday of the first week is before the start, so the series begins on the thursday
+        assert_eq!(
+            recurrence.occurrences(start(), start() + Duration::days(7)),
+            vec![
+                start(),
+                Utc.ymd(2020, 3, 9).and_hms(19, 0, 0),
+                Utc.ymd(2020, 3, 12).and_hms(19, 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interval() {
+        let recurrence = "RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=3"
+            .parse::<Recurrence>()
+            .expect("valid recurrence");
+
+        assert_eq!(
+            recurrence.occurrences(start(), far_horizon()),
+            vec![
+                start(),
+                start() + Duration::weeks(2),
+                start() + Duration::weeks(4)
+            ]
+        );
+        assert!(recurrence.includes(start(), start() + Duration::weeks(4)));
+        assert!(!recurrence.includes(start(), start() + Duration::weeks(1)));
+        assert!(!recurrence.includes(start(), start() + Duration::weeks(6)));
+    }
+}
@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+use crate::role::Role;
+
+/// A member's part in a session, see `Role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SessionRole {
+    /// watches the session, but does not play or RSVP
+    Spectator,
+    Player,
+    /// helps the DM run the session
+    CoDm,
+    /// the session's `dm`; there is exactly one per session
+    Dm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionPermission {
+    Rsvp,
+    /// accept, deny, invite and remove members
+    ManageMembers,
+    /// create, rotate, revoke and remove guest links, and see the headcount
+    ManageGuests,
+    /// change members' roles
+    ManageRoles,
+    /// edit, cancel or delete the session
+    EditSession,
+    /// hand the session over to another DM
+    HandOver,
+}
+
+impl Role for SessionRole {
+    type Permission = SessionPermission;
+
+    const ALL: &'static [SessionRole] = &[
+        SessionRole::Spectator,
+        SessionRole::Player,
+        SessionRole::CoDm,
+        SessionRole::Dm,
+    ];
+    const NAME: &'static str = "session role";
+
+    fn as_str(self) -> &'static str {
+        match self {
+            SessionRole::Spectator => "spectator",
+            SessionRole::Player => "player",
+            SessionRole::CoDm => "co_dm",
+            SessionRole::Dm => "dm",
+        }
+    }
+
+    fn required_for(permission: SessionPermission) -> SessionRole {
+        match permission {
+            SessionPermission::Rsvp => SessionRole::Player,
+            SessionPermission::ManageMembers | SessionPermission::ManageGuests => SessionRole::CoDm,
+            SessionPermission::ManageRoles
+            | SessionPermission::EditSession
+            | SessionPermission::HandOver => SessionRole::Dm,
+        }
+    }
+}
+
+impl FromStr for SessionRole {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<SessionRole, String> {
+        SessionRole::from_name(role)
+    }
+}
+
+impl SessionPermission {
+    /// the error given to someone without this permission
+    pub fn denied(self) -> &'static str {
+        match self {
+            SessionPermission::Rsvp => "spectators do not RSVP",
+            SessionPermission::ManageMembers | SessionPermission::ManageGuests => {
+                "you are not the DM or a co-DM"
+            }
+            SessionPermission::ManageRoles
+            | SessionPermission::EditSession
+            | SessionPermission::HandOver => "you are not the DM",
+        }
+    }
+
+    /// whether members allowed to manage every session in the group have this permission in
+    /// sessions they do not have a role in. They can run those sessions, but not play in them,
+    /// change who has which role or take them over from their DM.
+    pub fn given_to_group_schedulers(self) -> bool {
+        match self {
+            SessionPermission::ManageMembers
+            | SessionPermission::ManageGuests
+            | SessionPermission::EditSession => true,
+            SessionPermission::Rsvp
+            | SessionPermission::ManageRoles
+            | SessionPermission::HandOver => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_can_do_everything_below_them() {
+        assert!(!SessionRole::Spectator.can(SessionPermission::Rsvp));
+        assert!(SessionRole::Player.can(SessionPermission::Rsvp));
+        assert!(!SessionRole::Player.can(SessionPermission::ManageGuests));
+
+        assert!(SessionRole::CoDm.can(SessionPermission::Rsvp));
+        assert!(SessionRole::CoDm.can(SessionPermission::ManageMembers));
+        assert!(!SessionRole::CoDm.can(SessionPermission::EditSession));
+
+        assert!(SessionRole::Dm.can(SessionPermission::ManageRoles));
+        assert!(SessionRole::Dm.can(SessionPermission::HandOver));
+    }
+
+    #[test]
+    fn roles_only_outrank_roles_below_them() {
+        assert!(SessionRole::Dm.outranks(SessionRole::CoDm));
+        assert!(SessionRole::CoDm.outranks(SessionRole::Spectator));
+        assert!(!SessionRole::CoDm.outranks(SessionRole::CoDm));
+        assert!(!SessionRole::Player.outranks(SessionRole::Dm));
+    }
+
+    #[test]
+    fn group_schedulers_cannot_take_sessions_over() {
+        assert!(SessionPermission::EditSession.given_to_group_schedulers());
+        assert!(SessionPermission::ManageGuests.given_to_group_schedulers());
+        assert!(!SessionPermission::HandOver.given_to_group_schedulers());
+        assert!(!SessionPermission::ManageRoles.given_to_group_schedulers());
+    }
+
+    #[test]
+    fn roles_are_stored_as_their_names() {
+        for role in SessionRole::ALL {
+            assert_eq!(role.as_str().parse::<SessionRole>(), Ok(*role));
+        }
+        assert_eq!(
+            "owner".parse::<SessionRole>(),
+            Err("owner is not a session role".to_string())
+        );
+    }
+}
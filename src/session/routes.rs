@@ -1,33 +1,44 @@
+use crate::calendar;
 use crate::database::DnDAgendaDB;
 use crate::session;
 
 use rocket_contrib::json::Json;
 use rocket_contrib::json::JsonError;
-use rocket_contrib::json::JsonValue;
 
 use rocket::request::Form;
 
 use crate::api::ApiResponse;
 use crate::api::Auth;
 use crate::api::GuestAuth;
+use crate::error::ApiError;
+use rocket::http::ContentType;
 use rocket::http::RawStr;
 use rocket::http::Status;
+use rocket::response::content::Content;
+use rocket::State;
 
 use crate::api::validate_colour;
-use crate::api::validate_group_exists;
-use crate::api::validate_user_exists;
+use crate::api::validate_occurrence_fields;
+use crate::api::validate_recurrence;
+use crate::api::validate_rsvp;
+use crate::api::validate_session_role;
 use crate::api::FieldValidator;
-use validator::Validate;
+use validator::{validate_email, Validate};
 
-use chrono::{DateTime, Utc};
-
-use std::thread;
+use chrono::{DateTime, Duration, Utc};
 
 use regex::Regex;
 
-use crate::mailgun::{send_mail, MailType};
+use crate::mail::{send_mail, MailType};
 
+use crate::config::AppConfig;
+use crate::config::DEFAULT_SESSION_DURATION_MINUTES;
+use crate::config::{DEFAULT_GUEST_LINK_LIFETIME_DAYS, MAX_GUEST_LINK_LIFETIME_DAYS};
+use crate::role::Role;
+use crate::session::recurrence::Recurrence;
+use crate::session::role::{SessionPermission, SessionRole};
 use crate::user::User;
+use crate::visibility::require_visible;
 
 lazy_static! {
     static ref SESSION_DATE_FORMAT: Regex =
@@ -36,10 +47,10 @@ lazy_static! {
 
 #[get("/?<params..>")]
 pub fn get_all(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<session::FindSessions>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => session::Session::read(&params, auth.id, &connection)
             .map(|(sessions, pages_count)| ApiResponse {
@@ -47,52 +58,71 @@ pub fn get_all(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_slug>")]
 pub fn get_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_slug: String,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => match session::Session::find_as_json(&session_slug, &connection) {
-            Ok(session_json) => ApiResponse {
+        Ok(auth) => match session::Session::find_as_json(&session_slug, auth.id, &connection) {
+            Ok(session_json) => Ok(ApiResponse {
                 json: json!({ "session": session_json }),
                 status: Status::Ok,
-            },
-            Err(response) => response,
-        },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
+            }),
+            Err(response) => Err(response),
         },
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[get("/<session_slug>/ics")]
+pub fn get_session_ics(
+    auth: Result<Auth, ApiError>,
+    session_slug: String,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<Content<String>, ApiError> {
+    match auth {
+        Ok(auth) => {
+            let session_details =
+                session::Session::find_visible(&session_slug, auth.id, &connection)?;
+            let overrides = session_details.overrides(&connection)?;
+
+            Ok(Content(
+                ContentType::new("text", "calendar"),
+                calendar::to_ics(&app_config.app_url, &[(session_details, overrides)]),
+            ))
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/users")]
 pub fn get_users(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => match session::Session::read_users(session_id, &connection) {
-            Ok(users) => ApiResponse {
-                json: json!({ "users": users }),
-                status: Status::Ok,
-            },
-            Err(response) => response,
-        },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        },
+        Ok(auth) => {
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            require_visible(&session_details, auth.id, &connection)?;
+
+            session::Session::read_users(session_id, &connection)
+                .map(|users| ApiResponse {
+                    json: json!({ "users": users }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -102,7 +132,6 @@ pub struct NewSession {
     pub title: Option<String>,
     #[validate(length(min = 1, code = "Description must be at least 1 character long"))]
     pub description: Option<String>,
-    #[validate(custom = "validate_user_exists")]
     pub dm: Option<i32>,
     #[validate(regex(
         path = "SESSION_DATE_FORMAT",
@@ -111,16 +140,30 @@ pub struct NewSession {
     pub session_date: Option<String>,
     #[validate(custom = "validate_colour")]
     pub colour: Option<String>,
-    #[validate(custom = "validate_group_exists")]
     pub group: Option<i32>,
+    #[validate(custom = "validate_recurrence")]
+    pub recurrence: Option<String>,
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        code = "Duration must be between 1 minute and 24 hours"
+    ))]
+    pub duration_minutes: Option<i32>,
 }
 
-#[post("/", format = "application/json", data = "<session>")] // data attribute tells rocket to expect Body Data - then map the body to a parameter
+/// overlaps with the sessions the creator and DM are already in are returned as `conflicts`,
+/// or rejected with a 409 if `reject_conflicts` is set
+#[post(
+    "/?<reject_conflicts>",
+    format = "application/json",
+    data = "<session>"
+)] // data attribute tells rocket to expect Body Data - then map the body to a parameter
 pub fn create(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session: Result<Json<NewSession>, JsonError>,
+    reject_conflicts: Option<bool>,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => match session {
             Ok(json_session) => {
@@ -128,6 +171,8 @@ pub fn create(
 
                 let empty_flag = false; // i.e. should we ignore empty fields?
                 let mut extractor = FieldValidator::validate(&new_session);
+                extractor.users_exist(&[("dm", new_session.dm)], &connection)?;
+                extractor.groups_exist(&[("group", new_session.group)], &connection)?;
                 let title = extractor.extract("title", new_session.title, empty_flag);
                 let description =
                     extractor.extract("description", new_session.description, empty_flag);
@@ -142,8 +187,7 @@ pub fn create(
 
                 match check {
                     Ok(_) => {
-                        // no need to worry about panic here because validator above will check the regex of the date
-                        let session_date = session_date_str.parse::<DateTime<Utc>>().unwrap();
+                        let session_date = parse_session_date(&session_date_str)?;
 
                         let insertable_session = session::InsertableSession {
                             slug: slugify(&title),
@@ -153,37 +197,44 @@ pub fn create(
                             session_date,
                             colour,
                             group_id,
+                            recurrence: new_session
+                                .recurrence
+                                .filter(|recurrence| !recurrence.is_empty())
+                                .map(normalise_recurrence),
+                            duration_minutes: new_session
+                                .duration_minutes
+                                .unwrap_or(DEFAULT_SESSION_DURATION_MINUTES),
+                        };
+
+                        let conflicts = match session::Session::check_conflicts(
+                            &[dm, auth.id],
+                            &insertable_session.schedule(),
+                            None,
+                            reject_conflicts.unwrap_or(false),
+                            &connection,
+                        ) {
+                            Ok(conflicts) => conflicts,
+                            Err(response) => return Err(response),
                         };
+
                         match session::InsertableSession::create(
                             insertable_session,
                             auth.id,
                             &connection,
                         ) {
-                            Ok(session) => ApiResponse {
-                                json: json!({ "session": session }),
+                            Ok(session) => Ok(ApiResponse {
+                                json: json!({ "session": session, "conflicts": conflicts }),
                                 status: Status::Created,
-                            },
-                            Err(response) => response,
+                            }),
+                            Err(response) => Err(response),
                         }
                     }
-                    Err(response) => response,
+                    Err(response) => Err(response),
                 }
             }
-            Err(json_error) => match json_error {
-                JsonError::Parse(_req, err) => ApiResponse {
-                    json: json!({ "error": err.to_string() }),
-                    status: Status::BadRequest,
-                },
-                JsonError::Io(_err) => ApiResponse {
-                    json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                    status: Status::InternalServerError,
-                },
-            },
-        },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
+            Err(json_error) => Err(ApiError::from(json_error)),
         },
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -200,35 +251,43 @@ pub struct UpdateSessionData {
     pub session_date: Option<String>,
     #[validate(custom = "validate_colour")]
     pub colour: Option<String>,
+    #[validate(custom = "validate_recurrence")]
+    pub recurrence: Option<String>,
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        code = "Duration must be between 1 minute and 24 hours"
+    ))]
+    pub duration_minutes: Option<i32>,
     slug: Option<String>,
 }
 
-#[patch("/<session_id>", format = "application/json", data = "<session>")]
+/// rescheduling a session returns overlaps with the other sessions its members are in as
+/// `conflicts`, or rejects them with a 409 if `reject_conflicts` is set
+#[patch(
+    "/<session_id>?<reject_conflicts>",
+    format = "application/json",
+    data = "<session>"
+)]
 pub fn patch_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session: Result<Json<UpdateSessionData>, JsonError>,
     session_id: i32,
+    reject_conflicts: Option<bool>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                let mut session_update_details = session.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::EditSession,
+                &connection,
+            )? {
+                let mut session_update_details = session.map_err(ApiError::from)?.into_inner();
 
                 if let Some(ref title) = session_update_details.title {
                     session_update_details.slug = Some(slugify(&title));
@@ -254,90 +313,90 @@ pub fn patch_session(
                 );
                 let _colour =
                     extractor.extract("colour", session_validator_details.colour, empty_flag);
+                let _recurrence = extractor.extract(
+                    "recurrence",
+                    session_validator_details.recurrence,
+                    empty_flag,
+                );
+                let _duration_minutes = extractor.extract(
+                    "duration_minutes",
+                    session_validator_details.duration_minutes,
+                    empty_flag,
+                );
 
                 extractor.check()?;
 
-                let session_date: Option<DateTime<Utc>>;
-
-                if let Some(ref _date) = session_update_details.session_date {
-                    session_date = Some(
-                        session_update_details
-                            .session_date
-                            .unwrap()
-                            .parse::<DateTime<Utc>>()
-                            .unwrap(),
-                    );
-                } else {
-                    session_date = None;
-                }
+                let session_date = match session_update_details.session_date {
+                    Some(ref date) => Some(parse_session_date(date)?),
+                    None => None,
+                };
 
                 let update_session = session::UpdateSession {
                     title: session_update_details.title,
                     description: session_update_details.description,
                     session_date,
                     colour: session_update_details.colour,
+                    // an empty recurrence stops the session from recurring
+                    recurrence: session_update_details.recurrence.map(|recurrence| {
+                        if recurrence.is_empty() {
+                            None
+                        } else {
+                            Some(normalise_recurrence(recurrence))
+                        }
+                    }),
+                    duration_minutes: session_update_details.duration_minutes,
 
                     slug: session_update_details.slug,
                     dm: None,
                 };
 
-                session::UpdateSession::update(session_id, &update_session, &connection)
-                    .map(|session| ApiResponse {
-                        json: json!({ "session": session }),
-                        status: Status::Ok,
-                    })
-                    .map_err(|response| response)
-            } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
+                session::UpdateSession::update(
+                    session_id,
+                    &update_session,
+                    reject_conflicts.unwrap_or(false),
+                    &connection,
+                )
+                .map(|(session, conflicts)| ApiResponse {
+                    json: json!({ "session": session, "conflicts": conflicts }),
+                    status: Status::Ok,
                 })
+                .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::EditSession.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[derive(Deserialize, Validate, Clone)]
 pub struct UpdateSessionDMData {
-    #[validate(custom = "validate_user_exists")]
     pub dm: Option<i32>,
 }
 
 #[patch("/<session_id>/dm", format = "application/json", data = "<session>")]
 pub fn patch_dm_of_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session: Result<Json<UpdateSessionDMData>, JsonError>,
     session_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                let session_update_details = session.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            if session_details.has_permission(auth.id, SessionPermission::HandOver, &connection)? {
+                let session_update_details = session.map_err(ApiError::from)?.into_inner();
 
                 let session_validator_details = session_update_details.clone();
 
                 let empty_flag = true; // i.e. do not emit error if empty
                 let mut extractor = FieldValidator::validate(&session_update_details);
+                extractor.users_exist(&[("dm", session_update_details.dm)], &connection)?;
                 let new_dm = extractor.extract("dm", session_validator_details.dm, empty_flag);
 
                 extractor.check()?;
@@ -355,310 +414,342 @@ pub fn patch_dm_of_session(
                     description: None,
                     session_date: None,
                     colour: None,
+                    recurrence: None,
+                    duration_minutes: None,
                     slug: None,
 
                     dm: session_update_details.dm,
                 };
 
-                session::UpdateSession::update(session_id, &update_session, &connection)
-                    .map(|session| ApiResponse {
+                session::UpdateSession::update(session_id, &update_session, false, &connection)
+                    .map(|(session, _)| ApiResponse {
                         json: json!({ "session": session }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    SessionPermission::HandOver.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
-#[get("/<session_id>/join", format = "application/json")]
+#[get("/<session_id>/join?<reject_conflicts>", format = "application/json")]
 pub fn join_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
+    reject_conflicts: Option<bool>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
+            let conflicts = session::Session::check_conflicts(
+                &[auth.id],
+                &session_details.schedule(&connection)?,
+                Some(session_details.id),
+                reject_conflicts.unwrap_or(false),
+                &connection,
+            )?;
+
             session::Session::request_to_join(session_details.id, auth.id, &connection)
                 .map(|_| ApiResponse {
-                    json: json!({ "message": "requested to join session successfully" }),
+                    json: json!({ "message": "requested to join session successfully", "conflicts": conflicts }),
                     status: Status::Ok,
                 })
                 .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/accept/<user_id>", format = "application/json")]
 pub fn accept_to_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                session::Session::accept_to_join(&session_details, user_id, &connection)
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageMembers,
+                &connection,
+            )? {
+                session::Session::accept_to_join(&session_details, user_id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "successfully accepted user to session" }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/deny/<user_id>", format = "application/json")]
 pub fn deny_to_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                session::Session::remove_user(&session_details, user_id, &connection)
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageMembers,
+                &connection,
+            )? {
+                session::Session::deny_to_join(&session_details, user_id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "successfully denied user to session" }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get(
-    "/<session_id>/invite/<user_id>",
+    "/<session_id>/invite/<user_id>?<reject_conflicts>",
     format = "application/json",
     rank = 2
 )]
 pub fn invite_to_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
+    reject_conflicts: Option<bool>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
-            if auth.id == session_details.dm {
-                session::Session::invite_to_join(
-                    session_details.id,
-                    user_id,
-                    session_details.group_id,
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageMembers,
+                &connection,
+            )? {
+                let conflicts = session::Session::check_conflicts(
+                    &[user_id],
+                    &session_details.schedule(&connection)?,
+                    Some(session_details.id),
+                    reject_conflicts.unwrap_or(false),
                     &connection,
-                )
-                .map(|_| {
-                    // since invite_to_join succeeded, the user and dm must exist
-                    let user = User::find(user_id, &connection).unwrap();
-                    let dm = User::find(session_details.dm, &connection).unwrap();
+                )?;
+
+                // the mail is queued in the same transaction, so it is only sent for an invite
+                // that was made
+                connection
+                    .transaction::<_, ApiError, _>(|| {
+                        session::Session::invite_to_join(
+                            session_details.id,
+                            user_id,
+                            session_details.group_id,
+                            auth.id,
+                            &connection,
+                        )?;
+
+                        let user = User::find(user_id, &connection)?;
+                        let inviter = User::find(auth.id, &connection)?;
 
-                    thread::spawn(|| {
                         send_mail(
                             MailType::SessionInviteReceived,
                             user,
                             session_details.title,
                             session_details.slug,
-                            dm,
-                        );
-                    });
-                    ApiResponse {
-                        json: json!({ "message": "invited user to join session successfully" }),
+                            inviter,
+                            &app_config,
+                            &connection,
+                        )
+                    })
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "invited user to join session successfully", "conflicts": conflicts }),
                         status: Status::Ok,
-                    }
-                })
-                .map_err(|response| response)
+                    })
+                    .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageMembers.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/invite/accept", format = "application/json")]
 pub fn accept_invite_to_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            session::Session::accept_invite_to_join(&session_details, auth.id, &connection)
-                .map(|_| {
-                    // since accept_invite_to_join succeeded, the user and dm must exist
-                    let user = User::find(auth.id, &connection).unwrap();
-                    let dm = User::find(session_details.dm, &connection).unwrap();
-
-                    thread::spawn(|| {
-                        send_mail(
-                            MailType::SessionInviteAccepted,
-                            user,
-                            session_details.title,
-                            session_details.slug,
-                            dm,
-                        );
-                    });
-                    ApiResponse {
-                        json: json!({ "message": "Joined session successfully" }),
-                        status: Status::Ok,
-                    }
+            connection
+                .transaction::<_, ApiError, _>(|| {
+                    session::Session::accept_invite_to_join(
+                        &session_details,
+                        auth.id,
+                        &connection,
+                    )?;
+
+                    let user = User::find(auth.id, &connection)?;
+                    let dm = User::find(session_details.dm, &connection)?;
+
+                    send_mail(
+                        MailType::SessionInviteAccepted,
+                        user,
+                        session_details.title,
+                        session_details.slug,
+                        dm,
+                        &app_config,
+                        &connection,
+                    )
+                })
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "Joined session successfully" }),
+                    status: Status::Ok,
                 })
                 .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/invite/deny", format = "application/json")]
 pub fn deny_invite_to_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            session::Session::remove_user(&session_details, auth.id, &connection)
-                .map(|_| {
-                    // since remove_user succeeded, the user and dm must exist
-                    let user = User::find(auth.id, &connection).unwrap();
-                    let dm = User::find(session_details.dm, &connection).unwrap();
-
-                    thread::spawn(|| {
-                        send_mail(
-                            MailType::SessionInviteDeclined,
-                            user,
-                            session_details.title,
-                            session_details.slug,
-                            dm,
-                        );
-                    });
-                    ApiResponse {
-                        json: json!({ "message": "Denied invite to session successfully" }),
-                        status: Status::Ok,
-                    }
+            connection
+                .transaction::<_, ApiError, _>(|| {
+                    session::Session::decline_invite_to_join(
+                        &session_details,
+                        auth.id,
+                        &connection,
+                    )?;
+
+                    let user = User::find(auth.id, &connection)?;
+                    let dm = User::find(session_details.dm, &connection)?;
+
+                    send_mail(
+                        MailType::SessionInviteDeclined,
+                        user,
+                        session_details.title,
+                        session_details.slug,
+                        dm,
+                        &app_config,
+                        &connection,
+                    )
+                })
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "Denied invite to session successfully" }),
+                    status: Status::Ok,
                 })
                 .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/waiting/<user_id>", format = "application/json")]
 pub fn is_user_waiting_to_join(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => session::Session::is_user_waiting_to_join(session_id, user_id, &connection)
-            .map(|is_waiting| ApiResponse {
-                json: json!({ "waiting": is_waiting }),
-                status: Status::Ok,
-            })
-            .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Ok(auth) => {
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            require_visible(&session_details, auth.id, &connection)?;
+
+            session::Session::is_user_waiting_to_join(session_id, user_id, &connection)
+                .map(|is_waiting| ApiResponse {
+                    json: json!({ "waiting": is_waiting }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/invited/<user_id>", format = "application/json")]
 pub fn is_user_invited_to_join(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => session::Session::is_user_invited_to_join(session_id, user_id, &connection)
-            .map(|is_invited| ApiResponse {
-                json: json!({ "invited": is_invited }),
-                status: Status::Ok,
-            })
-            .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Ok(auth) => {
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            require_visible(&session_details, auth.id, &connection)?;
+
+            session::Session::is_user_invited_to_join(session_id, user_id, &connection)
+                .map(|is_invited| ApiResponse {
+                    json: json!({ "invited": is_invited }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<session_id>/leave")]
 pub fn leave_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
@@ -666,39 +757,39 @@ pub fn leave_session(
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
             if auth.id != session_details.dm {
-                session::Session::remove_user(&session_details, auth.id, &connection)
+                session::Session::remove_user(&session_details, auth.id, auth.id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "left session successfully" }),
                         status: Status::Ok,
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are the DM, so you cannot leave" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    "you are the DM, so you cannot leave".to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<session_id>")]
 pub fn delete_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::EditSession,
+                &connection,
+            )? {
                 session::Session::delete(&session_details, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "session deleted successfully" }),
@@ -706,97 +797,163 @@ pub fn delete_session(
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
+                Err(ApiError::Forbidden(
+                    SessionPermission::EditSession.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<session_id>/remove/<user_id>")]
 pub fn remove_user_from_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     user_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                if user_id != auth.id {
-                    session::Session::remove_user(&session_details, user_id, &connection)
-                        .map(|_| ApiResponse {
-                            json: json!({ "message": "removed user from session successfully" }),
-                            status: Status::Ok,
-                        })
-                        .map_err(|response| response)
-                } else {
-                    Err(ApiResponse {
-                        json: json!({ "error": "you are the DM, so you cannot be removed" }),
-                        status: Status::Unauthorized,
-                    })
+            if !session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageMembers,
+                &connection,
+            )? {
+                return Err(ApiError::Forbidden(
+                    SessionPermission::ManageMembers.denied().to_string(),
+                ));
+            }
+
+            if user_id == session_details.dm {
+                return Err(ApiError::Forbidden("the DM cannot be removed".to_string()));
+            }
+
+            // co-DMs can only be removed by someone who outranks them, i.e. the DM
+            let user_role = session::SessionUser::role(session_details.id, user_id, &connection)?;
+            if user_role == Some(SessionRole::CoDm) {
+                let remover_role =
+                    session::SessionUser::role(session_details.id, auth.id, &connection)?;
+                if !remover_role.map_or(false, |role| role.outranks(SessionRole::CoDm)) {
+                    return Err(ApiError::Forbidden("you are not the DM".to_string()));
                 }
-            } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
-                })
             }
+
+            session::Session::remove_user(&session_details, user_id, auth.id, &connection)
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "removed user from session successfully" }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
-#[get("/<session_id>/guest_link/<guest_name>")]
+fn guest_link(app_config: &AppConfig, session: &session::Session, guest_token: &str) -> String {
+    format!(
+        "{}/#/session/{}?guest={}",
+        app_config.app_url, session.slug, guest_token
+    )
+}
+
+/// how long a new guest link should work for, `lifetime_days` defaults to
+/// `DEFAULT_GUEST_LINK_LIFETIME_DAYS`
+fn guest_link_lifetime(lifetime_days: Option<i64>) -> Result<Duration, ApiError> {
+    let lifetime_days = lifetime_days.unwrap_or(DEFAULT_GUEST_LINK_LIFETIME_DAYS);
+
+    if lifetime_days < 1 || lifetime_days > MAX_GUEST_LINK_LIFETIME_DAYS {
+        return Err(ApiError::invalid_field(
+            "lifetime_days",
+            format!(
+                "Lifetime must be between 1 and {} days",
+                MAX_GUEST_LINK_LIFETIME_DAYS
+            ),
+        ));
+    }
+
+    Ok(Duration::days(lifetime_days))
+}
+
+/// `guest_email` is optional, and only used to send the guest reminders
+#[get("/<session_id>/guest_link/<guest_name>?<guest_email>&<lifetime_days>")]
 pub fn get_guest_link(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     guest_name: String,
+    guest_email: Option<String>,
+    lifetime_days: Option<i64>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
-                session::Session::create_guest_token(session_details.id, &guest_name, &connection)
-                    .map(|guest_token| ApiResponse {
-                        json: json!({
-                            "guest_link":
-                                format!(
-                                    "https://dndearall.com/#/session/{}?guest={}",
-                                    session_details.slug, guest_token
-                                )
-                        }),
-                        status: Status::Ok,
-                    })
-                    .map_err(|response| response)
-            } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                if let Some(ref email) = guest_email {
+                    if !validate_email(email) {
+                        return Err(ApiError::invalid_field(
+                            "guest_email",
+                            "Email must be a valid email",
+                        ));
+                    }
+                }
+
+                let lifetime = guest_link_lifetime(lifetime_days)?;
+
+                session::Session::create_guest_token(
+                    session_details.id,
+                    &guest_name,
+                    guest_email,
+                    lifetime,
+                    &app_config,
+                    &connection,
+                )
+                .map(|guest_token| ApiResponse {
+                    json: json!({
+                        "guest_link": guest_link(&app_config, &session_details, &guest_token)
+                    }),
+                    status: Status::Ok,
                 })
+                .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// check a guest token is a valid guest link for the session `session_id`
+fn decode_guest_link(
+    session_id: i32,
+    guest_token: &RawStr,
+    app_config: &AppConfig,
+) -> Result<GuestAuth, ApiError> {
+    match GuestAuth::decode_guest_token(guest_token.as_str(), app_config) {
+        Some(guest_auth) => {
+            if guest_auth.session_id == session_id {
+                Ok(guest_auth)
+            } else {
+                Err(ApiError::Unauthorized(
+                    "this guest link is not valid for that session".to_string(),
+                ))
+            }
+        }
+        None => Err(ApiError::Unauthorized("not a valid guest link".to_string())),
     }
 }
 
@@ -804,48 +961,148 @@ pub fn get_guest_link(
 pub fn get_session_as_guest(
     session_id: i32,
     guest_token: &RawStr,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let guest_auth = decode_guest_link(session_id, guest_token, &app_config)?;
+
+    // get error if there is any (i.e. guest does not exist or link was rotated/revoked)
+    let _guest_details =
+        session::Session::use_guest_link(&guest_auth, &connection).map_err(|response| response)?;
+    session::Session::find(session_id, &connection)
+        .map(|session_json| ApiResponse {
+            json: json!({ "session": session_json }),
+            status: Status::Ok,
+        })
+        .map_err(|response| response)
+}
+
+#[put(
+    "/<session_id>/guest/<guest_token>/rsvp",
+    format = "application/json",
+    data = "<rsvp>"
+)]
+pub fn rsvp_as_guest(
+    session_id: i32,
+    guest_token: &RawStr,
+    rsvp: Result<Json<RsvpData>, JsonError>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let guest_auth = decode_guest_link(session_id, guest_token, &app_config)?;
+
+    match rsvp {
+        Ok(json_rsvp) => {
+            let rsvp_details = json_rsvp.into_inner();
+
+            let mut extractor = FieldValidator::validate(&rsvp_details);
+            let rsvp = extractor.extract("rsvp", rsvp_details.rsvp, false);
+
+            extractor.check()?;
+
+            let update_rsvp = session::UpdateRsvp {
+                rsvp,
+                rsvp_note: rsvp_details.note,
+            };
+
+            session::Session::guest_rsvp(&guest_auth, &update_rsvp, &connection)
+                .map(|guest| ApiResponse {
+                    json: json!({ "guest": guest }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(json_error) => Err(ApiError::from(json_error)),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct GuestRemindersData {
+    pub reminders_enabled: Option<bool>,
+}
+
+/// let a guest turn their reminder emails off (or back on) from their guest link
+#[put(
+    "/<session_id>/guest/<guest_token>/reminders",
+    format = "application/json",
+    data = "<reminders>"
+)]
+pub fn guest_reminders(
+    session_id: i32,
+    guest_token: &RawStr,
+    reminders: Result<Json<GuestRemindersData>, JsonError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
-    if let Some(guest_auth) = GuestAuth::decode_guest_token(guest_token.as_str()) {
-        if guest_auth.session_id == session_id {
-            // get error if there is any (i.e. guest does not exist)
-            let _guest_details =
-                session::Session::get_guest(session_id, guest_auth.guest_id, &connection)
-                    .map_err(|response| response)?;
-            session::Session::find(session_id, &connection)
-                .map(|session_json| ApiResponse {
-                    json: json!({ "session": session_json }),
+) -> Result<ApiResponse, ApiError> {
+    let guest_auth = decode_guest_link(session_id, guest_token, &app_config)?;
+
+    match reminders {
+        Ok(json_reminders) => {
+            let reminders_details = json_reminders.into_inner();
+
+            let mut extractor = FieldValidator::validate(&reminders_details);
+            let reminders_enabled = extractor.extract(
+                "reminders_enabled",
+                reminders_details.reminders_enabled,
+                false,
+            );
+
+            extractor.check()?;
+
+            session::Session::guest_reminders(&guest_auth, reminders_enabled, &connection)
+                .map(|guest| ApiResponse {
+                    json: json!({ "guest": guest }),
                     status: Status::Ok,
                 })
                 .map_err(|response| response)
-        } else {
-            Err(ApiResponse {
-                json: json!({ "error": "this guest link is not valid for that session" }),
-                status: Status::Unauthorized,
-            })
         }
-    } else {
-        Err(ApiResponse {
-            json: json!({ "error": "not a valid guest link" }),
-            status: Status::Unauthorized,
-        })
+        Err(json_error) => Err(ApiError::from(json_error)),
+    }
+}
+
+/// turn the guest into a member of the session, as the logged in user. Used right after
+/// registering (or logging in) from the guest page; the guest link stops working afterwards.
+#[post("/<session_id>/guest/<guest_token>/upgrade")]
+pub fn upgrade_guest(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    guest_token: &RawStr,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            let guest_auth = decode_guest_link(session_id, guest_token, &app_config)?;
+
+            session::Session::upgrade_guest(&guest_auth, auth.id, &connection)
+                .map(|member| ApiResponse {
+                    json: json!({ "member": member }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/<session_id>/guest/<guest_id>")]
 pub fn remove_guest_from_session(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     guest_id: i32,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
             // get error if there is any (i.e. session does not exist)
             let session_details =
                 session::Session::find(session_id, &connection).map_err(|response| response)?;
 
-            if auth.id == session_details.dm {
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
                 session::Session::remove_guest(&session_details, guest_id, &connection)
                     .map(|_| ApiResponse {
                         json: json!({ "message": "removed guest from session successfully" }),
@@ -853,40 +1110,564 @@ pub fn remove_guest_from_session(
                     })
                     .map_err(|response| response)
             } else {
-                Err(ApiResponse {
-                    json: json!({ "error": "you are not the DM" }),
-                    status: Status::Unauthorized,
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// give a guest a new link, the old one stops working
+#[post("/<session_id>/guests/<guest_id>/link?<lifetime_days>")]
+pub fn rotate_guest_link(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    guest_id: i32,
+    lifetime_days: Option<i64>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                let lifetime = guest_link_lifetime(lifetime_days)?;
+
+                session::Session::rotate_guest_link(
+                    &session_details,
+                    guest_id,
+                    lifetime,
+                    &app_config,
+                    &connection,
+                )
+                .map(|guest_token| ApiResponse {
+                    json: json!({
+                        "guest_link": guest_link(&app_config, &session_details, &guest_token)
+                    }),
+                    status: Status::Ok,
                 })
+                .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// stop a guest's link from working, they stay in the session until given a new link
+#[delete("/<session_id>/guests/<guest_id>/link")]
+pub fn revoke_guest_link(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    guest_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                session::Session::revoke_guest_link(&session_details, guest_id, &connection)
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "revoked guest link successfully" }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// when each guest's link expires, whether it was revoked and when it was last used
+#[get("/<session_id>/guests/links")]
+pub fn get_guest_links(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                session::Session::read_guest_links(&session_details, &connection)
+                    .map(|guests| ApiResponse {
+                        json: json!({ "guests": guests }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<session_id>/guests")]
 pub fn get_guests(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     session_id: i32,
     connection: DnDAgendaDB,
-) -> ApiResponse {
+) -> Result<ApiResponse, ApiError> {
     match auth {
-        Ok(_auth) => match session::Session::read_guests(session_id, &connection) {
-            Ok(guests) => ApiResponse {
-                json: json!({ "guests": guests }),
-                status: Status::Ok,
-            },
-            Err(response) => response,
+        Ok(auth) => {
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            require_visible(&session_details, auth.id, &connection)?;
+
+            session::Session::read_guests(session_id, &connection)
+                .map(|guests| ApiResponse {
+                    json: json!({ "guests": guests }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate, Clone)]
+pub struct UpdateOccurrenceData {
+    #[validate(length(min = 1, code = "Title must be at least 1 character long"))]
+    pub title: Option<String>,
+    #[validate(length(min = 1, code = "Description must be at least 1 character long"))]
+    pub description: Option<String>,
+    #[validate(regex(
+        path = "SESSION_DATE_FORMAT",
+        code = "must be valid output of JS toISOString()"
+    ))]
+    pub session_date: Option<String>,
+    #[validate(custom = "validate_colour")]
+    pub colour: Option<String>,
+    /// fields to set back to the series' value, unless they are also given
+    #[validate(custom = "validate_occurrence_fields")]
+    pub reset: Option<Vec<String>>,
+}
+
+impl UpdateOccurrenceData {
+    /// `Some(None)` if the field should be reset to the series' value
+    fn change<T>(&self, field: &str, value: Option<T>) -> Option<Option<T>> {
+        match value {
+            Some(value) => Some(Some(value)),
+            None if self
+                .reset
+                .as_ref()
+                .map_or(false, |reset| reset.iter().any(|reset| reset == field)) =>
+            {
+                Some(None)
+            }
+            None => None,
+        }
+    }
+}
+
+#[patch(
+    "/<session_id>/occurrences/<occurrence_date>",
+    format = "application/json",
+    data = "<occurrence>"
+)]
+pub fn patch_occurrence(
+    auth: Result<Auth, ApiError>,
+    occurrence: Result<Json<UpdateOccurrenceData>, JsonError>,
+    session_id: i32,
+    occurrence_date: String,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::EditSession,
+                &connection,
+            )? {
+                let occurrence_details = occurrence.map_err(ApiError::from)?.into_inner();
+
+                let occurrence_date = parse_occurrence_date(&occurrence_date)?;
+
+                let occurrence_validator_details = occurrence_details.clone();
+
+                let empty_flag = true; // i.e. do not emit error if empty
+                let mut extractor = FieldValidator::validate(&occurrence_details);
+                let _title =
+                    extractor.extract("title", occurrence_validator_details.title, empty_flag);
+                let _description = extractor.extract(
+                    "description",
+                    occurrence_validator_details.description,
+                    empty_flag,
+                );
+                let _sess_date = extractor.extract(
+                    "session_date",
+                    occurrence_validator_details.session_date,
+                    empty_flag,
+                );
+                let _colour =
+                    extractor.extract("colour", occurrence_validator_details.colour, empty_flag);
+
+                extractor.check()?;
+
+                let session_date = match occurrence_details.session_date {
+                    Some(ref date) => Some(parse_session_date(date)?),
+                    None => None,
+                };
+
+                let update_occurrence = session::UpdateSessionOccurrence {
+                    session_date: occurrence_details.change("session_date", session_date),
+                    title: occurrence_details.change("title", occurrence_details.title.clone()),
+                    description: occurrence_details
+                        .change("description", occurrence_details.description.clone()),
+                    colour: occurrence_details.change("colour", occurrence_details.colour.clone()),
+                };
+
+                session::Session::update_occurrence(
+                    &session_details,
+                    occurrence_date,
+                    &update_occurrence,
+                    &connection,
+                )
+                .map(|occurrence| ApiResponse {
+                    json: json!({ "occurrence": occurrence }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::EditSession.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[delete("/<session_id>/occurrences/<occurrence_date>")]
+pub fn cancel_occurrence(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    occurrence_date: String,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::EditSession,
+                &connection,
+            )? {
+                let occurrence_date = parse_occurrence_date(&occurrence_date)?;
+
+                session::Session::cancel_occurrence(&session_details, occurrence_date, &connection)
+                    .map(|_| ApiResponse {
+                        json: json!({ "message": "occurrence cancelled successfully" }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::EditSession.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RsvpData {
+    #[validate(custom = "validate_rsvp")]
+    pub rsvp: Option<String>,
+    #[validate(length(min = 1, code = "Note must be at least 1 character long"))]
+    pub note: Option<String>,
+}
+
+#[put("/<session_id>/rsvp", format = "application/json", data = "<rsvp>")]
+pub fn rsvp_to_session(
+    auth: Result<Auth, ApiError>,
+    rsvp: Result<Json<RsvpData>, JsonError>,
+    session_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match rsvp {
+            Ok(json_rsvp) => {
+                let rsvp_details = json_rsvp.into_inner();
+
+                let mut extractor = FieldValidator::validate(&rsvp_details);
+                let rsvp = extractor.extract("rsvp", rsvp_details.rsvp, false);
+
+                extractor.check()?;
+
+                // get error if there is any (i.e. session does not exist)
+                let session_details =
+                    session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+                let update_rsvp = session::UpdateRsvp {
+                    rsvp,
+                    rsvp_note: rsvp_details.note,
+                };
+
+                session::Session::rsvp(&session_details, auth.id, &update_rsvp, &connection)
+                    .map(|member| ApiResponse {
+                        json: json!({ "member": member }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
         },
-        Err(auth_error) => ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// RSVP to a single occurrence of a recurring session, instead of the whole series
+#[put(
+    "/<session_id>/occurrences/<occurrence_date>/rsvp",
+    format = "application/json",
+    data = "<rsvp>"
+)]
+pub fn rsvp_to_occurrence(
+    auth: Result<Auth, ApiError>,
+    rsvp: Result<Json<RsvpData>, JsonError>,
+    session_id: i32,
+    occurrence_date: String,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match rsvp {
+            Ok(json_rsvp) => {
+                let rsvp_details = json_rsvp.into_inner();
+
+                let mut extractor = FieldValidator::validate(&rsvp_details);
+                let rsvp = extractor.extract("rsvp", rsvp_details.rsvp, false);
+
+                extractor.check()?;
+
+                let occurrence_date = parse_occurrence_date(&occurrence_date)?;
+
+                // get error if there is any (i.e. session does not exist)
+                let session_details =
+                    session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+                let update_rsvp = session::UpdateRsvp {
+                    rsvp,
+                    rsvp_note: rsvp_details.note,
+                };
+
+                session::Session::rsvp_occurrence(
+                    &session_details,
+                    occurrence_date,
+                    auth.id,
+                    &update_rsvp,
+                    &connection,
+                )
+                .map(|member| ApiResponse {
+                    json: json!({ "member": member }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
         },
+        Err(auth_error) => Err(auth_error),
     }
 }
 
+#[get("/<session_id>/headcount")]
+pub fn get_headcount(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                session::Session::headcount(&session_details, None, &connection)
+                    .map(|headcount| ApiResponse {
+                        json: json!({ "headcount": headcount }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// the headcount for a single occurrence of a recurring session, using the RSVPs members gave
+/// for it
+#[get("/<session_id>/occurrences/<occurrence_date>/headcount")]
+pub fn get_occurrence_headcount(
+    auth: Result<Auth, ApiError>,
+    session_id: i32,
+    occurrence_date: String,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            // get error if there is any (i.e. session does not exist)
+            let session_details =
+                session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+            if session_details.has_permission(
+                auth.id,
+                SessionPermission::ManageGuests,
+                &connection,
+            )? {
+                let occurrence_date = parse_occurrence_date(&occurrence_date)?;
+
+                session::Session::headcount(&session_details, Some(occurrence_date), &connection)
+                    .map(|headcount| ApiResponse {
+                        json: json!({ "headcount": headcount }),
+                        status: Status::Ok,
+                    })
+                    .map_err(|response| response)
+            } else {
+                Err(ApiError::Forbidden(
+                    SessionPermission::ManageGuests.denied().to_string(),
+                ))
+            }
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// occurrences are identified by their original (unedited) date, as given in `occurrenceDate`
+fn parse_occurrence_date(occurrence_date: &str) -> Result<DateTime<Utc>, ApiError> {
+    occurrence_date.parse::<DateTime<Utc>>().map_err(|error| {
+        println!("Error: {:#?}", error);
+        ApiError::invalid_field(
+            "occurrence_date",
+            "must be valid output of JS toISOString()",
+        )
+    })
+}
+
+/// the validators only check dates look like the output of JS toISOString(), which does not
+/// mean they are real dates, e.g. the 31st of February
+fn parse_session_date(session_date: &str) -> Result<DateTime<Utc>, ApiError> {
+    session_date.parse::<DateTime<Utc>>().map_err(|error| {
+        println!("Error: {:#?}", error);
+        ApiError::invalid_field("session_date", "must be a valid date")
+    })
+}
+
+/// store recurrences in a consistent form, the validator has already checked they parse
+fn normalise_recurrence(recurrence: String) -> String {
+    recurrence
+        .parse::<Recurrence>()
+        .map(|recurrence| recurrence.to_string())
+        .unwrap_or(recurrence)
+}
+
 fn slugify(title: &str) -> String {
     slug::slugify(title)
 }
+
+#[derive(Deserialize, Validate)]
+pub struct SessionRoleData {
+    #[validate(custom = "validate_session_role")]
+    pub role: Option<String>,
+}
+
+/// make a member a co-DM, player or spectator
+#[put(
+    "/<session_id>/role/<user_id>",
+    format = "application/json",
+    data = "<role>"
+)]
+pub fn set_role_in_session(
+    auth: Result<Auth, ApiError>,
+    role: Result<Json<SessionRoleData>, JsonError>,
+    session_id: i32,
+    user_id: i32,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => match role {
+            Ok(json_role) => {
+                let role_details = json_role.into_inner();
+
+                let mut extractor = FieldValidator::validate(&role_details);
+                let role = extractor.extract("role", role_details.role, false);
+
+                extractor.check()?;
+
+                // get error if there is any (i.e. session does not exist)
+                let session_details =
+                    session::Session::find(session_id, &connection).map_err(|response| response)?;
+
+                // already checked by validate_session_role
+                let role = role
+                    .parse::<SessionRole>()
+                    .map_err(|error| ApiError::invalid_field("role", error))?;
+
+                session::SessionUser::set_role(
+                    &session_details,
+                    user_id,
+                    role,
+                    auth.id,
+                    &connection,
+                )
+                .map(|member| ApiResponse {
+                    json: json!({ "member": member }),
+                    status: Status::Ok,
+                })
+                .map_err(|response| response)
+            }
+            Err(json_error) => Err(ApiError::from(json_error)),
+        },
+        Err(auth_error) => Err(auth_error),
+    }
+}
@@ -0,0 +1,221 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::{login_sessions, refresh_tokens};
+use diesel::prelude::*;
+
+use super::User;
+
+use crate::config::REFRESH_TOKEN_EXPIRY_DAYS;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::api::{generate_token, hash_token};
+use crate::error::ApiError;
+
+/// length of a random refresh token
+const REFRESH_TOKEN_LENGTH: usize = 48;
+
+/// Everything issued from one login. Access tokens carry its id, so revoking it logs that
+/// device out.
+#[derive(Identifiable, Queryable, Debug, Associations)]
+#[belongs_to(User)]
+#[table_name = "login_sessions"]
+pub struct LoginSession {
+    pub id: i32,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[table_name = "login_sessions"]
+#[derive(Insertable)]
+pub struct InsertableLoginSession {
+    pub user_id: i32,
+}
+
+/// A single-use token for getting a new access token. Using one replaces it with a new one;
+/// only the hash of each token is stored.
+#[derive(Identifiable, Queryable, Debug, Associations)]
+#[belongs_to(LoginSession)]
+#[table_name = "refresh_tokens"]
+pub struct RefreshToken {
+    pub id: i32,
+    pub login_session_id: i32,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    /// when the token was swapped for a new one
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[table_name = "refresh_tokens"]
+#[derive(Insertable)]
+pub struct InsertableRefreshToken {
+    pub login_session_id: i32,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl LoginSession {
+    /// log a user in, returning the new login session and its first refresh token
+    pub fn create(
+        user_id: i32,
+        connection: &PgConnection,
+    ) -> Result<(LoginSession, String), ApiError> {
+        let login_session = diesel::insert_into(login_sessions::table)
+            .values(&InsertableLoginSession { user_id })
+            .get_result::<LoginSession>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not log in".to_string())
+            })?;
+
+        let refresh_token = LoginSession::issue_refresh_token(login_session.id, connection)?;
+
+        Ok((login_session, refresh_token))
+    }
+
+    fn issue_refresh_token(
+        login_session_id: i32,
+        connection: &PgConnection,
+    ) -> Result<String, ApiError> {
+        let token = generate_token(REFRESH_TOKEN_LENGTH);
+
+        let new_refresh_token = &InsertableRefreshToken {
+            login_session_id,
+            token_hash: hash_token(&token),
+            expires_at: Utc::now() + Duration::days(REFRESH_TOKEN_EXPIRY_DAYS),
+        };
+
+        diesel::insert_into(refresh_tokens::table)
+            .values(new_refresh_token)
+            .execute(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("Could not create a refresh token".to_string())
+            })?;
+
+        Ok(token)
+    }
+
+    /// swap a refresh token for a new one. A token that was already swapped means it has
+    /// been stolen (or the client is misbehaving), so the whole login session is revoked.
+    pub fn refresh(
+        token: &str,
+        connection: &PgConnection,
+    ) -> Result<(LoginSession, String), ApiError> {
+        let invalid_token =
+            ApiError::Unauthorized("refresh token is invalid or has expired".to_string());
+        let token_hash = hash_token(token);
+
+        // use up the token in the same statement that checks it, so it only ever works once
+        let refresh_token = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(&token_hash))
+                .filter(refresh_tokens::used_at.is_null())
+                .filter(refresh_tokens::expires_at.gt(Utc::now())),
+        )
+        .set(refresh_tokens::used_at.eq(Utc::now()))
+        .get_result::<RefreshToken>(connection)
+        .optional()
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not refresh the token".to_string())
+        })?;
+
+        let refresh_token = match refresh_token {
+            Some(refresh_token) => refresh_token,
+            None => {
+                let reused_token = refresh_tokens::table
+                    .filter(refresh_tokens::token_hash.eq(&token_hash))
+                    .filter(refresh_tokens::used_at.is_not_null())
+                    .first::<RefreshToken>(connection)
+                    .optional()
+                    .map_err(|error| {
+                        println!("Error: {:#?}", error);
+                        ApiError::Internal("Could not refresh the token".to_string())
+                    })?;
+
+                if let Some(reused_token) = reused_token {
+                    LoginSession::revoke(reused_token.login_session_id, connection)?;
+
+                    return Err(ApiError::Unauthorized(
+                        "refresh token has already been used, please log in again".to_string(),
+                    ));
+                }
+
+                return Err(invalid_token);
+            }
+        };
+
+        let login_session = login_sessions::table
+            .find(refresh_token.login_session_id)
+            .filter(login_sessions::revoked_at.is_null())
+            .first::<LoginSession>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                invalid_token
+            })?;
+
+        let new_refresh_token = LoginSession::issue_refresh_token(login_session.id, connection)?;
+
+        Ok((login_session, new_refresh_token))
+    }
+
+    /// whether access tokens from this login session should still be accepted
+    pub fn is_active(login_session_id: i32, connection: &PgConnection) -> Result<bool, ApiError> {
+        diesel::select(diesel::dsl::exists(
+            login_sessions::table
+                .find(login_session_id)
+                .filter(login_sessions::revoked_at.is_null()),
+        ))
+        .get_result::<bool>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::NotFound("Login session not found".to_string())
+        })
+    }
+
+    /// log one device out
+    pub fn revoke(login_session_id: i32, connection: &PgConnection) -> Result<(), ApiError> {
+        diesel::update(
+            login_sessions::table
+                .find(login_session_id)
+                .filter(login_sessions::revoked_at.is_null()),
+        )
+        .set(login_sessions::revoked_at.eq(Utc::now()))
+        .execute(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not log out".to_string())
+        })?;
+
+        Ok(())
+    }
+
+    /// log the user out everywhere, except for the login session `keep` if given
+    pub fn revoke_all(
+        user_id: i32,
+        keep: Option<i32>,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let mut query = diesel::update(
+            login_sessions::table
+                .filter(login_sessions::user_id.eq(user_id))
+                .filter(login_sessions::revoked_at.is_null()),
+        )
+        .set(login_sessions::revoked_at.eq(Utc::now()))
+        .into_boxed();
+
+        if let Some(keep) = keep {
+            query = query.filter(login_sessions::id.ne(keep))
+        }
+
+        query.execute(connection).map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not log out".to_string())
+        })?;
+
+        Ok(())
+    }
+}
@@ -13,17 +13,23 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 use crate::group::{self, Group, GroupUser};
 use crate::schema::{groups, groups_users};
 
-use crate::api::ApiResponse;
-use rocket::http::Status;
+use crate::error::ApiError;
 
-use crate::api::Auth;
+use crate::api::{Auth, EmailVerification};
 use chrono::{Duration, Utc};
 
 use crate::session;
 
+use crate::mail::send_verification;
+
+pub mod login_session;
+pub mod password_reset;
 pub mod routes;
 
-use crate::config::DEFAULT_LIMIT;
+use password_reset::PasswordReset;
+
+use crate::config::AppConfig;
+use crate::config::{ACCESS_TOKEN_EXPIRY_MINUTES, DEFAULT_LIMIT, EMAIL_VERIFICATION_EXPIRY_HOURS};
 
 use crate::database::dsl;
 
@@ -41,6 +47,13 @@ pub struct User {
     pub image: Option<String>,
     #[serde(skip_serializing)]
     pub password: String,
+    /// whether the user wants reminder emails before their sessions
+    pub reminders_enabled: bool,
+    /// site admins can manage the mail outbox
+    #[serde(skip_serializing)]
+    pub site_admin: bool,
+    /// whether the user has proven they own their email, no emails are sent to them until then
+    pub email_verified: bool,
 }
 
 #[derive(FromForm, Default)]
@@ -59,7 +72,12 @@ pub struct UserAuth<'a> {
     email: &'a str,
     bio: Option<&'a str>,
     image: Option<&'a str>,
+    reminders_enabled: bool,
+    email_verified: bool,
     token: String,
+    /// only given out when logging in or refreshing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 #[derive(Serialize, Clone, PartialEq, Eq, Hash)]
@@ -70,14 +88,27 @@ pub struct Profile {
     image: Option<String>,
 }
 
+impl Profile {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
+
 impl User {
-    pub fn to_user_auth(&self) -> UserAuth {
-        let exp = Utc::now() + Duration::hours(1);
+    /// the user along with a new access token for the login session `sid`
+    pub fn to_user_auth(
+        &self,
+        sid: i32,
+        refresh_token: Option<String>,
+        app_config: &AppConfig,
+    ) -> UserAuth {
+        let exp = Utc::now() + Duration::minutes(ACCESS_TOKEN_EXPIRY_MINUTES);
         let token = Auth {
             id: self.id,
             exp: exp.timestamp(),
+            sid,
         }
-        .token();
+        .token(app_config);
 
         UserAuth {
             id: self.id,
@@ -85,10 +116,67 @@ impl User {
             email: &self.email,
             bio: self.bio.as_deref(),
             image: self.image.as_deref(),
+            reminders_enabled: self.reminders_enabled,
+            email_verified: self.email_verified,
             token,
+            refresh_token,
         }
     }
 
+    /// email the user a link that proves they own their current email address
+    pub fn send_verification(
+        &self,
+        app_config: &AppConfig,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let exp = Utc::now() + Duration::hours(EMAIL_VERIFICATION_EXPIRY_HOURS);
+        let token = EmailVerification {
+            user_id: self.id,
+            email: self.email.clone(),
+            exp: exp.timestamp(),
+        }
+        .token(app_config);
+
+        send_verification(&self.email, &self.username, &token, app_config, connection)
+    }
+
+    /// like `send_verification`, but a mail that cannot be queued is only logged, for changes
+    /// that should go ahead anyway. The user can always ask for the mail again.
+    pub fn try_send_verification(&self, app_config: &AppConfig, connection: &PgConnection) {
+        // in a savepoint, so that a failure does not abort the transaction this is called in
+        if let Err(error) = connection
+            .transaction::<_, ApiError, _>(|| self.send_verification(app_config, connection))
+        {
+            println!("Error queueing verification mail: {:#?}", error);
+        }
+    }
+
+    pub fn verify_email(
+        token: &str,
+        app_config: &AppConfig,
+        connection: &PgConnection,
+    ) -> Result<User, ApiError> {
+        let invalid_token = ApiError::invalid_field("token", "is invalid or has expired");
+
+        let verification = match EmailVerification::decode_token(token, app_config) {
+            Some(verification) => verification,
+            None => return Err(invalid_token),
+        };
+
+        // the email must still match, a link sent to an old address must not verify a new one
+        diesel::update(
+            users::table
+                .find(verification.user_id)
+                .filter(users::email.eq(&verification.email)),
+        )
+        .set(users::email_verified.eq(true))
+        .get_result::<User>(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            invalid_token
+        })
+    }
+
     pub fn to_profile(&self) -> Profile {
         Profile {
             id: self.id,
@@ -102,57 +190,40 @@ impl User {
         old_password: String,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<bool, ApiResponse> {
+    ) -> Result<bool, ApiError> {
         let user = users::table
             .find(user_id)
             .first::<User>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("User not found".to_string())
             })?;
 
         verify(old_password, &user.password).map_err(|error| {
             println!("Error: {}", error);
-            ApiResponse {
-                json: json!({"error": "verifying failed", "details": error.to_string() }),
-                status: Status::InternalServerError,
-            }
+            ApiError::Internal("verifying failed".to_string())
         })
     }
 
-    pub fn login(
-        email: &str,
-        password: &str,
-        connection: &PgConnection,
-    ) -> Result<User, ApiResponse> {
+    pub fn login(email: &str, password: &str, connection: &PgConnection) -> Result<User, ApiError> {
         let user = users::table
             .filter(users::email.eq(email))
             .get_result::<User>(connection)
             .map_err(|error| {
                 println!("Error: {}", error);
-                ApiResponse {
-                    json: json!({"error": "incorrect email/password" }),
-                    status: Status::Unauthorized,
-                }
+                ApiError::Unauthorized("incorrect email/password".to_string())
             })?;
 
         let password_matches = verify(password, &user.password).map_err(|error| {
             println!("Error: {}", error);
-            ApiResponse {
-                json: json!({"error": "verifying failed", "details": error.to_string() }),
-                status: Status::InternalServerError,
-            }
+            ApiError::Internal("verifying failed".to_string())
         })?;
         if password_matches {
             Ok(user)
         } else {
-            Err(ApiResponse {
-                json: json!({ "error": "incorrect email/password" }),
-                status: Status::Unauthorized,
-            })
+            Err(ApiError::Unauthorized(
+                "incorrect email/password".to_string(),
+            ))
         }
     }
 
@@ -160,7 +231,7 @@ impl User {
         params: &FindUsers,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<Profile>, i64), ApiResponse> {
+    ) -> Result<(Vec<Profile>, i64), ApiError> {
         if params.global_search.unwrap_or(false) {
             //get all users regardless of what groups the current user is in
 
@@ -193,10 +264,7 @@ impl User {
                 })
                 .map_err(|error| {
                     println!("Error: {:#?}", error);
-                    ApiResponse {
-                        json: json!({"error": "Users not found" }),
-                        status: Status::NotFound,
-                    }
+                    ApiError::NotFound("Users not found".to_string())
                 })
         } else {
             let mut pages_count: i64 = 0;
@@ -213,10 +281,7 @@ impl User {
                 .load::<Group>(connection)
                 .map_err(|error| {
                     println!("Error: {:#?}", error);
-                    ApiResponse {
-                        json: json!({"error": "Groups not found" }),
-                        status: Status::NotFound,
-                    }
+                    ApiError::NotFound("Groups not found".to_string())
                 })?
                 .iter()
                 .map(|group| {
@@ -248,10 +313,7 @@ impl User {
                         .load_and_count_pages::<User>(connection)
                         .map_err(|error| {
                             println!("Error: {:#?}", error);
-                            ApiResponse {
-                                json: json!({"error": "Users not found" }),
-                                status: Status::NotFound,
-                            }
+                            ApiError::NotFound("Users not found".to_string())
                         })
                         .map(|(users, count)| {
                             pages_count += count;
@@ -275,31 +337,25 @@ impl User {
         }
     }
 
-    pub fn find(user_id: i32, connection: &PgConnection) -> Result<User, ApiResponse> {
+    pub fn find(user_id: i32, connection: &PgConnection) -> Result<User, ApiError> {
         users::table
             .find(user_id)
             .first::<User>(connection)
             .map(|user| user)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("User not found".to_string())
             })
     }
 
-    pub fn find_profile(username: &str, connection: &PgConnection) -> Result<Profile, ApiResponse> {
+    pub fn find_profile(username: &str, connection: &PgConnection) -> Result<Profile, ApiError> {
         users::table
             .filter(users::username.eq(username))
             .first::<User>(connection)
             .map(|user| user.to_profile())
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("User not found".to_string())
             })
     }
 
@@ -308,7 +364,7 @@ impl User {
         params: &session::FindSessions,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<JsonValue>, i64), ApiResponse> {
+    ) -> Result<(Vec<JsonValue>, i64), ApiError> {
         sessions_users::table
             .filter(sessions_users::columns::dm_accepted.eq(false)
                 .and(sessions_users::columns::user_accepted.eq(true))
@@ -321,20 +377,17 @@ impl User {
             .load_and_count_pages::<(i32, String, String, i32)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Sessions not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Sessions not found".to_string())
             })
-            .map(|(session_id_slug_title_user_id, count)| {
-                (session_id_slug_title_user_id
+            .and_then(|(session_id_slug_title_user_id, count)| {
+                session_id_slug_title_user_id
                     .into_iter()
                     .map(|(session_id, slug, title, user_id)| {
-                        let user = User::find(user_id, connection).map_err(|response| response).unwrap();
-                        json!({ "id": session_id, "slug": slug, "title": title, "profile": user.to_profile() })
+                        let user = User::find(user_id, connection)?;
+                        Ok(json!({ "id": session_id, "slug": slug, "title": title, "profile": user.to_profile() }))
                     })
-                    .collect()
-                , count)
+                    .collect::<Result<Vec<_>, ApiError>>()
+                    .map(|results| (results, count))
             })
     }
 
@@ -343,7 +396,7 @@ impl User {
         params: &session::FindSessions,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<JsonValue>, i64), ApiResponse> {
+    ) -> Result<(Vec<JsonValue>, i64), ApiError> {
         sessions_users::table
             .filter(sessions_users::columns::dm_accepted.eq(true)
                 .and(sessions_users::columns::user_accepted.eq(false))
@@ -356,20 +409,17 @@ impl User {
             .load_and_count_pages::<(i32, String, String, i32)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Sessions not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Sessions not found".to_string())
             })
-            .map(|(session_id_slug_title_user_id, count)| {
-                (session_id_slug_title_user_id
+            .and_then(|(session_id_slug_title_user_id, count)| {
+                session_id_slug_title_user_id
                     .into_iter()
                     .map(|(session_id, slug, title, user_id)| {
-                        let user = User::find(user_id, connection).map_err(|response| response).unwrap();
-                        json!({ "id": session_id, "slug": slug, "title": title, "profile": user.to_profile() })
+                        let user = User::find(user_id, connection)?;
+                        Ok(json!({ "id": session_id, "slug": slug, "title": title, "profile": user.to_profile() }))
                     })
-                    .collect()
-                , count)
+                    .collect::<Result<Vec<_>, ApiError>>()
+                    .map(|results| (results, count))
             })
     }
 
@@ -378,7 +428,7 @@ impl User {
         params: &group::FindGroups,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<JsonValue>, i64), ApiResponse> {
+    ) -> Result<(Vec<JsonValue>, i64), ApiError> {
         groups_users::table
             .filter(groups_users::columns::admin_accepted.eq(false)
                 .and(groups_users::columns::user_accepted.eq(true))
@@ -391,20 +441,17 @@ impl User {
             .load_and_count_pages::<(i32, String, String, i32)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Groups not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Groups not found".to_string())
             })
-            .map(|(group_id_slug_name_user_id, count)| {
-                (group_id_slug_name_user_id
+            .and_then(|(group_id_slug_name_user_id, count)| {
+                group_id_slug_name_user_id
                     .into_iter()
                     .map(|(group_id, slug, name, user_id)| {
-                        let user = User::find(user_id, connection).map_err(|response| response).unwrap();
-                        json!({ "id": group_id, "slug": slug, "name": name, "profile": user.to_profile() })
+                        let user = User::find(user_id, connection)?;
+                        Ok(json!({ "id": group_id, "slug": slug, "name": name, "profile": user.to_profile() }))
                     })
-                    .collect()
-                , count)
+                    .collect::<Result<Vec<_>, ApiError>>()
+                    .map(|results| (results, count))
             })
     }
 
@@ -413,7 +460,7 @@ impl User {
         params: &group::FindGroups,
         user_id: i32,
         connection: &PgConnection,
-    ) -> Result<(Vec<JsonValue>, i64), ApiResponse> {
+    ) -> Result<(Vec<JsonValue>, i64), ApiError> {
         groups_users::table
             .filter(groups_users::columns::admin_accepted.eq(true)
                 .and(groups_users::columns::user_accepted.eq(false))
@@ -426,32 +473,26 @@ impl User {
             .load_and_count_pages::<(i32, String, String, i32)>(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "Sessions not found" }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("Sessions not found".to_string())
             })
-            .map(|(group_id_slug_name_user_id, count)| {
-                (group_id_slug_name_user_id
+            .and_then(|(group_id_slug_name_user_id, count)| {
+                group_id_slug_name_user_id
                     .into_iter()
                     .map(|(group_id, slug, name, user_id)| {
-                        let user = User::find(user_id, connection).map_err(|response| response).unwrap();
-                        json!({ "id": group_id, "slug": slug, "name": name, "profile": user.to_profile() })
+                        let user = User::find(user_id, connection)?;
+                        Ok(json!({ "id": group_id, "slug": slug, "name": name, "profile": user.to_profile() }))
                     })
-                    .collect()
-                , count)
+                    .collect::<Result<Vec<_>, ApiError>>()
+                    .map(|results| (results, count))
             })
     }
 
-    pub fn delete(user_id: i32, connection: &PgConnection) -> Result<(), ApiResponse> {
+    pub fn delete(user_id: i32, connection: &PgConnection) -> Result<(), ApiError> {
         diesel::delete(users::table.find(user_id))
             .execute(connection)
             .map_err(|error| {
                 println!("Error: {:#?}", error);
-                ApiResponse {
-                    json: json!({"error": "User could not be deleted", "details": error.to_string() }),
-                    status: Status::NotFound,
-                }
+                ApiError::NotFound("User could not be deleted".to_string())
             })?;
 
         Ok(())
@@ -470,6 +511,8 @@ pub struct InsertableUser {
 pub enum UserCreationError {
     DuplicatedEmail,
     DuplicatedUsername,
+    /// anything other than a taken username or email
+    Other(diesel::result::Error),
 }
 
 impl From<diesel::result::Error> for UserCreationError {
@@ -485,21 +528,15 @@ impl From<diesel::result::Error> for UserCreationError {
                 _ => {}
             }
         }
-        panic!("Error creating user: {:?}", err)
+        UserCreationError::Other(err)
     }
 }
 
 impl InsertableUser {
-    pub fn create(
-        mut user: InsertableUser,
-        connection: &PgConnection,
-    ) -> Result<User, ApiResponse> {
+    pub fn create(mut user: InsertableUser, connection: &PgConnection) -> Result<User, ApiError> {
         user.password = hash(user.password, DEFAULT_COST).map_err(|error| {
             println!("Cannot hash password: {:#?}", error);
-            ApiResponse {
-                json: json!({"error": "error hashing" }),
-                status: Status::InternalServerError,
-            }
+            ApiError::Internal("error hashing".to_string())
         })?;
 
         diesel::insert_into(users::table)
@@ -511,12 +548,10 @@ impl InsertableUser {
                 let field = match error {
                     UserCreationError::DuplicatedEmail => "email",
                     UserCreationError::DuplicatedUsername => "username",
+                    UserCreationError::Other(error) => return ApiError::from(error),
                 };
                 println!("Cannot create user: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "errors": { field: [ "has already been taken" ] } }),
-                    status: Status::UnprocessableEntity,
-                }
+                ApiError::invalid_field(field, "has already been taken")
             })
     }
 }
@@ -529,27 +564,30 @@ pub struct UpdateUser {
     email: Option<String>,
     bio: Option<String>,
     image: Option<String>,
+    reminders_enabled: Option<bool>,
+    #[serde(skip)]
+    email_verified: Option<bool>,
 
     // hack to skip the field
     password: Option<String>,
 }
 
 impl UpdateUser {
-    pub fn update(
-        id: i32,
-        user: &UpdateUser,
-        connection: &PgConnection,
-    ) -> Result<User, ApiResponse> {
-        diesel::update(users::table.find(id))
+    pub fn update(id: i32, user: &UpdateUser, connection: &PgConnection) -> Result<User, ApiError> {
+        let updated_user = diesel::update(users::table.find(id))
             .set(user)
             .get_result::<User>(connection)
             .map(|user| user)
             .map_err(|error| {
                 println!("Cannot update user: {:#?}", error);
-                ApiResponse {
-                    json: json!({ "error": "cannot update user" }),
-                    status: Status::UnprocessableEntity,
-                }
-            })
+                ApiError::Conflict("cannot update user".to_string())
+            })?;
+
+        // reset links sent before the password changed must not work afterwards
+        if user.password.is_some() {
+            PasswordReset::invalidate_all(id, connection)?;
+        }
+
+        Ok(updated_user)
     }
 }
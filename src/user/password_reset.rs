@@ -0,0 +1,147 @@
+// TODO: remove once clippy allows disabling single_component_path_import within #[derive(...)]
+#![allow(clippy::single_component_path_imports)]
+
+use crate::schema::{password_resets, users};
+use diesel::prelude::*;
+
+use super::login_session::LoginSession;
+use super::{UpdateUser, User};
+
+use bcrypt::{hash, DEFAULT_COST};
+
+use crate::config::AppConfig;
+use crate::config::PASSWORD_RESET_EXPIRY_MINUTES;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::api::{generate_token, hash_token};
+use crate::error::ApiError;
+
+use crate::mail::send_password_reset;
+
+/// length of the random token in a password reset link
+const RESET_TOKEN_LENGTH: usize = 48;
+
+/// A single-use password reset link that was emailed to a user. Only the hash of its token is
+/// stored.
+#[derive(Identifiable, Queryable, Debug, Associations)]
+#[belongs_to(User)]
+#[table_name = "password_resets"]
+pub struct PasswordReset {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[table_name = "password_resets"]
+#[derive(Insertable)]
+pub struct InsertablePasswordReset {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PasswordReset {
+    /// email a reset link to whoever owns `email`. Succeeds even if nobody does, so that this
+    /// cannot be used to find out which emails have an account.
+    pub fn request(
+        email: &str,
+        app_config: &AppConfig,
+        connection: &PgConnection,
+    ) -> Result<(), ApiError> {
+        let user = match users::table
+            .filter(users::email.eq(email))
+            .first::<User>(connection)
+            .optional()
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::Internal("User not found".to_string())
+            })? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+
+        let token = generate_token(RESET_TOKEN_LENGTH);
+
+        let new_password_reset = &InsertablePasswordReset {
+            user_id: user.id,
+            token_hash: hash_token(&token),
+            expires_at: Utc::now() + Duration::minutes(PASSWORD_RESET_EXPIRY_MINUTES),
+        };
+
+        connection.transaction::<_, ApiError, _>(|| {
+            diesel::insert_into(password_resets::table)
+                .values(new_password_reset)
+                .execute(connection)
+                .map_err(|error| {
+                    println!("Error: {:#?}", error);
+                    ApiError::Internal("Could not create a password reset".to_string())
+                })?;
+
+            send_password_reset(&user.email, &user.username, &token, app_config, connection)
+        })
+    }
+
+    /// set a new password using an emailed reset token, which can then never be used again.
+    /// Using up the token, changing the password and logging the user out all happen together,
+    /// or not at all.
+    pub fn confirm(
+        token: &str,
+        password: &str,
+        connection: &PgConnection,
+    ) -> Result<User, ApiError> {
+        let new_password = hash(password, DEFAULT_COST).map_err(|error| {
+            println!("Cannot hash password: {:#?}", error);
+            ApiError::Internal("error hashing".to_string())
+        })?;
+
+        connection.transaction::<_, ApiError, _>(|| {
+            // use up the token in the same statement that checks it, so it only ever works once
+            let password_reset = diesel::update(
+                password_resets::table
+                    .filter(password_resets::token_hash.eq(hash_token(token)))
+                    .filter(password_resets::used_at.is_null())
+                    .filter(password_resets::expires_at.gt(Utc::now())),
+            )
+            .set(password_resets::used_at.eq(Utc::now()))
+            .get_result::<PasswordReset>(connection)
+            .map_err(|error| {
+                println!("Error: {:#?}", error);
+                ApiError::invalid_field("token", "is invalid or has expired")
+            })?;
+
+            let update_user = UpdateUser {
+                password: Some(new_password),
+                ..UpdateUser::default()
+            };
+
+            // also invalidates any other reset tokens the user has not used
+            let user = UpdateUser::update(password_reset.user_id, &update_user, connection)?;
+
+            // whoever knew the old password must not stay logged in
+            LoginSession::revoke_all(user.id, None, connection)?;
+
+            Ok(user)
+        })
+    }
+
+    /// stop any reset links the user has not used yet from working, e.g. once their password
+    /// has been changed
+    pub fn invalidate_all(user_id: i32, connection: &PgConnection) -> Result<(), ApiError> {
+        diesel::update(
+            password_resets::table
+                .filter(password_resets::user_id.eq(user_id))
+                .filter(password_resets::used_at.is_null()),
+        )
+        .set(password_resets::used_at.eq(Utc::now()))
+        .execute(connection)
+        .map_err(|error| {
+            println!("Error: {:#?}", error);
+            ApiError::Internal("Could not invalidate password resets".to_string())
+        })?;
+
+        Ok(())
+    }
+}
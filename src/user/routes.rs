@@ -3,27 +3,31 @@ use crate::user;
 
 use rocket_contrib::json::Json;
 use rocket_contrib::json::JsonError;
-use rocket_contrib::json::JsonValue;
 
 use rocket::request::Form;
 
 use crate::api::ApiResponse;
 use crate::api::Auth;
+use crate::error::ApiError;
 use rocket::http::Status;
+use rocket::State;
 
 use crate::api::FieldValidator;
+use crate::config::AppConfig;
 use bcrypt::{hash, DEFAULT_COST};
 use validator::Validate;
 
 use crate::group::FindGroups;
-use crate::session::FindSessions;
+use crate::session::{FindSessions, Session};
+use crate::user::login_session::LoginSession;
+use crate::user::password_reset::PasswordReset;
 
 #[get("/?<params..>")]
 pub fn get_all(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<user::FindUsers>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::read(&params, auth.id, &connection)
             .map(|(users, pages_count)| ApiResponse {
@@ -31,38 +35,33 @@ pub fn get_all(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/self")]
 pub fn get_self(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::find(auth.id, &connection)
             .map(|user| ApiResponse {
-                json: json!({ "user": user.to_user_auth() }),
+                json: json!({ "user": user.to_user_auth(auth.sid, None, &app_config) }),
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/self/sessions/requests?<params..>")]
 pub fn get_sessions_requests(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<FindSessions>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::read_sessions_requests(&params, auth.id, &connection)
             .map(|(session_requests, pages_count)| ApiResponse {
@@ -70,19 +69,33 @@ pub fn get_sessions_requests(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// upcoming sessions the user is in that overlap each other
+#[get("/self/conflicts")]
+pub fn get_conflicts(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => Session::read_conflicts(auth.id, &connection)
+            .map(|conflicts| ApiResponse {
+                json: json!({ "conflicts": conflicts }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/self/sessions/invites?<params..>")]
 pub fn get_sessions_invites(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<FindSessions>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::read_sessions_invites(&params, auth.id, &connection)
             .map(|(session_invites, pages_count)| ApiResponse {
@@ -90,19 +103,16 @@ pub fn get_sessions_invites(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/self/groups/requests?<params..>")]
 pub fn get_groups_requests(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<FindGroups>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::read_groups_requests(&params, auth.id, &connection)
             .map(|(group_requests, pages_count)| ApiResponse {
@@ -110,19 +120,16 @@ pub fn get_groups_requests(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/self/groups/invites?<params..>")]
 pub fn get_groups_invites(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     params: Form<FindGroups>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::read_groups_invites(&params, auth.id, &connection)
             .map(|(group_invites, pages_count)| ApiResponse {
@@ -130,10 +137,7 @@ pub fn get_groups_invites(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -150,20 +154,10 @@ pub struct NewUserData {
 #[post("/", format = "application/json", data = "<user>")] // data attribute tells rocket to expect Body Data - then map the body to a parameter
 pub fn create(
     user: Result<Json<NewUserData>, JsonError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
-    let new_user = user.map_err(|json_error| {
-        match json_error {
-            JsonError::Parse(_req, err) => ApiResponse {
-                json: json!({ "error": err.to_string() }),
-                status: Status::BadRequest,
-            },
-            JsonError::Io(_err) => ApiResponse {
-                json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                status: Status::InternalServerError,
-            },
-        }
-    })?.into_inner();
+) -> Result<ApiResponse, ApiError> {
+    let new_user = user.map_err(ApiError::from)?.into_inner();
 
     let empty_flag = false; //i.e. emit error if empty
     let mut extractor = FieldValidator::validate(&new_user);
@@ -179,12 +173,21 @@ pub fn create(
         password,
     };
 
-    user::InsertableUser::create(insertable_user, &connection)
-        .map(|user| ApiResponse {
-            json: json!({ "user": user.to_user_auth() }),
-            status: Status::Created,
-        })
-        .map_err(|response| response)
+    // the verification mail is queued with the new user, but failing to queue it does not stop
+    // them from signing up
+    let (user, login_session, refresh_token) = connection.transaction::<_, ApiError, _>(|| {
+        let user = user::InsertableUser::create(insertable_user, &connection)?;
+
+        user.try_send_verification(&app_config, &connection);
+
+        let (login_session, refresh_token) = LoginSession::create(user.id, &connection)?;
+        Ok((user, login_session, refresh_token))
+    })?;
+
+    Ok(ApiResponse {
+        json: json!({ "user": user.to_user_auth(login_session.id, Some(refresh_token), &app_config) }),
+        status: Status::Created,
+    })
 }
 
 #[derive(Deserialize)]
@@ -196,20 +199,10 @@ pub struct LoginUserData {
 #[post("/login", format = "application/json", data = "<user>")]
 pub fn login(
     user: Result<Json<LoginUserData>, JsonError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
-    let login_user = user.map_err(|json_error| {
-        match json_error {
-            JsonError::Parse(_req, err) => ApiResponse {
-                json: json!({ "error": err.to_string() }),
-                status: Status::BadRequest,
-            },
-            JsonError::Io(_err) => ApiResponse {
-                json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                status: Status::InternalServerError,
-            },
-        }
-    })?.into_inner();
+) -> Result<ApiResponse, ApiError> {
+    let login_user = user.map_err(ApiError::from)?.into_inner();
 
     let empty_flag = false; // i.e. should we ignore empty fields?
     let mut extractor = FieldValidator::default();
@@ -217,14 +210,191 @@ pub fn login(
     let password = extractor.extract("password", login_user.password, empty_flag);
     extractor.check()?;
 
-    user::User::login(&email, &password, &connection)
-        .map(|user| ApiResponse {
-            json: json!({ "user": user.to_user_auth() }),
+    let user = user::User::login(&email, &password, &connection)?;
+
+    let (login_session, refresh_token) = LoginSession::create(user.id, &connection)?;
+
+    Ok(ApiResponse {
+        json: json!({ "user": user.to_user_auth(login_session.id, Some(refresh_token), &app_config) }),
+        status: Status::Accepted,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenData {
+    refresh_token: Option<String>,
+}
+
+/// swap a refresh token for a new access token (and a new refresh token)
+#[post("/token/refresh", format = "application/json", data = "<token>")]
+pub fn refresh_token(
+    token: Result<Json<RefreshTokenData>, JsonError>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let refresh_data = token.map_err(ApiError::from)?.into_inner();
+
+    let empty_flag = false; // i.e. should we ignore empty fields?
+    let mut extractor = FieldValidator::default();
+    let refresh_token = extractor.extract("refresh_token", refresh_data.refresh_token, empty_flag);
+    extractor.check()?;
+
+    let (login_session, refresh_token) = LoginSession::refresh(&refresh_token, &connection)?;
+
+    let user = user::User::find(login_session.user_id, &connection)?;
+
+    Ok(ApiResponse {
+        json: json!({ "user": user.to_user_auth(login_session.id, Some(refresh_token), &app_config) }),
+        status: Status::Ok,
+    })
+}
+
+/// log out of this device, its access and refresh tokens stop working
+#[post("/logout")]
+pub fn logout(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => LoginSession::revoke(auth.sid, &connection)
+            .map(|_| ApiResponse {
+                json: json!({ "message": "logged out successfully" }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+/// log out of every device the user is logged in on, including this one
+#[post("/logout/everywhere")]
+pub fn logout_everywhere(
+    auth: Result<Auth, ApiError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => LoginSession::revoke_all(auth.id, None, &connection)
+            .map(|_| ApiResponse {
+                json: json!({ "message": "logged out everywhere successfully" }),
+                status: Status::Ok,
+            })
+            .map_err(|response| response),
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct PasswordResetData {
+    #[validate(email(code = "Email is not a valid email"))]
+    email: Option<String>,
+}
+
+/// email a single-use link for setting a new password, for users who forgot theirs
+#[post("/password-reset", format = "application/json", data = "<user>")]
+pub fn request_password_reset(
+    user: Result<Json<PasswordResetData>, JsonError>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let reset_user = user.map_err(ApiError::from)?.into_inner();
+
+    let empty_flag = false; // i.e. should we ignore empty fields?
+    let mut extractor = FieldValidator::validate(&reset_user);
+    let email = extractor.extract("email", reset_user.email, empty_flag);
+    extractor.check()?;
+
+    PasswordReset::request(&email, &app_config, &connection)
+        .map(|_| ApiResponse {
+            json: json!({ "message": "if an account uses that email, a password reset link has been sent to it" }),
             status: Status::Accepted,
         })
         .map_err(|response| response)
 }
 
+#[derive(Deserialize, Validate)]
+pub struct PasswordResetConfirmData {
+    token: Option<String>,
+    #[validate(length(min = 8, code = "Password must be at least 8 characters long"))]
+    password: Option<String>,
+}
+
+#[post(
+    "/password-reset/confirm",
+    format = "application/json",
+    data = "<user>"
+)]
+pub fn confirm_password_reset(
+    user: Result<Json<PasswordResetConfirmData>, JsonError>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let reset_user = user.map_err(ApiError::from)?.into_inner();
+
+    let empty_flag = false; // i.e. should we ignore empty fields?
+    let mut extractor = FieldValidator::validate(&reset_user);
+    let token = extractor.extract("token", reset_user.token, empty_flag);
+    let password = extractor.extract("password", reset_user.password, empty_flag);
+    extractor.check()?;
+
+    PasswordReset::confirm(&token, &password, &connection)
+        .map(|_| ApiResponse {
+            json: json!({ "message": "password reset successfully" }),
+            status: Status::Ok,
+        })
+        .map_err(|response| response)
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailData {
+    token: Option<String>,
+}
+
+/// confirm the user owns their email, using the token from a verification mail
+#[post("/verify-email", format = "application/json", data = "<user>")]
+pub fn verify_email(
+    user: Result<Json<VerifyEmailData>, JsonError>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    let verify_user = user.map_err(ApiError::from)?.into_inner();
+
+    let empty_flag = false; // i.e. should we ignore empty fields?
+    let mut extractor = FieldValidator::default();
+    let token = extractor.extract("token", verify_user.token, empty_flag);
+    extractor.check()?;
+
+    user::User::verify_email(&token, &app_config, &connection)
+        .map(|_| ApiResponse {
+            json: json!({ "message": "email verified successfully" }),
+            status: Status::Ok,
+        })
+        .map_err(|response| response)
+}
+
+#[post("/self/verify-email/resend")]
+pub fn resend_verification(
+    auth: Result<Auth, ApiError>,
+    app_config: State<AppConfig>,
+    connection: DnDAgendaDB,
+) -> Result<ApiResponse, ApiError> {
+    match auth {
+        Ok(auth) => {
+            let user = user::User::find(auth.id, &connection)?;
+
+            if user.email_verified {
+                return Err(ApiError::Conflict("email is already verified".to_string()));
+            }
+
+            user.send_verification(&app_config, &connection)
+                .map(|_| ApiResponse {
+                    json: json!({ "message": "verification email sent successfully" }),
+                    status: Status::Accepted,
+                })
+                .map_err(|response| response)
+        }
+        Err(auth_error) => Err(auth_error),
+    }
+}
+
 #[derive(Deserialize, Validate, Clone)]
 pub struct UpdateUserData {
     #[validate(length(min = 1, code = "Username must be at least 1 character long"))]
@@ -234,28 +404,19 @@ pub struct UpdateUserData {
     bio: Option<String>,
     #[validate(url(code = "Image must be a valid url"))]
     image: Option<String>,
+    reminders_enabled: Option<bool>,
 }
 
 #[patch("/self", format = "application/json", data = "<user>")]
 pub fn patch_self(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     user: Result<Json<UpdateUserData>, JsonError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
-            let user_details = user.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            let user_details = user.map_err(ApiError::from)?.into_inner();
 
             let user_validator_details = user_details.clone();
 
@@ -268,27 +429,40 @@ pub fn patch_self(
 
             extractor.check()?;
 
+            // a new email has to be verified again before anything is sent to it
+            let current_email = user::User::find(auth.id, &connection)?.email;
+            let email_changed = user_details
+                .email
+                .as_ref()
+                .map_or(false, |email| *email != current_email);
+
             //don't use values above because we want to pass on the Option<>, if extractor fails this won't execute anyway
             let update_user = user::UpdateUser {
                 username: user_details.username,
                 email: user_details.email,
                 bio: user_details.bio,
                 image: user_details.image,
+                reminders_enabled: user_details.reminders_enabled,
+                email_verified: if email_changed { Some(false) } else { None },
 
                 password: None,
             };
 
-            user::UpdateUser::update(auth.id, &update_user, &connection)
-                .map(|user| ApiResponse {
-                    json: json!({ "user": user.to_user_auth() }),
-                    status: Status::Ok,
-                })
-                .map_err(|response| response)
+            let user = connection.transaction::<_, ApiError, _>(|| {
+                let user = user::UpdateUser::update(auth.id, &update_user, &connection)?;
+
+                if email_changed {
+                    user.try_send_verification(&app_config, &connection);
+                }
+                Ok(user)
+            })?;
+
+            Ok(ApiResponse {
+                json: json!({ "user": user.to_user_auth(auth.sid, None, &app_config) }),
+                status: Status::Ok,
+            })
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
@@ -301,24 +475,14 @@ pub struct UpdateUserPasswordData {
 
 #[patch("/self/pwd", format = "application/json", data = "<user>")]
 pub fn patch_pwd_of_self(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     user: Result<Json<UpdateUserPasswordData>, JsonError>,
+    app_config: State<AppConfig>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => {
-            let user_details = user.map_err(|json_error| {
-                match json_error {
-                    JsonError::Parse(_req, err) => ApiResponse {
-                        json: json!({ "error": err.to_string() }),
-                        status: Status::BadRequest,
-                    },
-                    JsonError::Io(_err) => ApiResponse {
-                        json: json!({ "error": "I/O error occured while reading the incoming request data" }),
-                        status: Status::InternalServerError,
-                    },
-                }
-            })?.into_inner();
+            let user_details = user.map_err(ApiError::from)?.into_inner();
 
             let user_validator_details = user_details.clone();
 
@@ -341,10 +505,7 @@ pub fn patch_pwd_of_self(
                 let new_password = hash(password, DEFAULT_COST)
                     .map_err(|error| {
                         println!("Cannot hash password: {:#?}", error);
-                        ApiResponse {
-                            json: json!({"error": "error hashing" }),
-                            status: Status::InternalServerError,
-                        }
+                        ApiError::Internal("error hashing".to_string())
                     })
                     .ok();
                 let update_user = user::UpdateUser {
@@ -352,36 +513,38 @@ pub fn patch_pwd_of_self(
                     email: None,
                     bio: None,
                     image: None,
+                    reminders_enabled: None,
+                    email_verified: None,
 
                     password: new_password,
                 };
 
-                user::UpdateUser::update(auth.id, &update_user, &connection)
-                    .map(|user| ApiResponse {
-                        json: json!({ "user": user.to_user_auth() }),
-                        status: Status::Ok,
-                    })
-                    .map_err(|response| response)
-            } else {
-                Err(ApiResponse {
-                    json: json!({ "errors": { "old_password": [ "does not match current password" ] } }),
-                    status: Status::UnprocessableEntity,
+                let user = user::UpdateUser::update(auth.id, &update_user, &connection)?;
+
+                // whoever knew the old password must not stay logged in elsewhere
+                LoginSession::revoke_all(auth.id, Some(auth.sid), &connection)?;
+
+                Ok(ApiResponse {
+                    json: json!({ "user": user.to_user_auth(auth.sid, None, &app_config) }),
+                    status: Status::Ok,
                 })
+            } else {
+                Err(ApiError::invalid_field(
+                    "old_password",
+                    "does not match current password",
+                ))
             }
         }
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[get("/<username>/profile", format = "application/json")]
 pub fn get_profile(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     username: String,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(_auth) => user::User::find_profile(&username, &connection)
             .map(|profile| ApiResponse {
@@ -389,18 +552,15 @@ pub fn get_profile(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
 
 #[delete("/self")]
 pub fn delete_self(
-    auth: Result<Auth, JsonValue>,
+    auth: Result<Auth, ApiError>,
     connection: DnDAgendaDB,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse, ApiError> {
     match auth {
         Ok(auth) => user::User::delete(auth.id, &connection)
             .map(|_| ApiResponse {
@@ -408,9 +568,6 @@ pub fn delete_self(
                 status: Status::Ok,
             })
             .map_err(|response| response),
-        Err(auth_error) => Err(ApiResponse {
-            json: auth_error,
-            status: Status::Unauthorized,
-        }),
+        Err(auth_error) => Err(auth_error),
     }
 }
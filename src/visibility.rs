@@ -0,0 +1,37 @@
+use diesel::PgConnection;
+
+use crate::error::ApiError;
+
+/// Whether a user can see a group or session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Visible,
+    /// the user knows it exists, but has to join to see it
+    Forbidden,
+    /// the user should not be able to tell it exists at all
+    Hidden,
+}
+
+/// Something only some users are allowed to see, e.g. a private group or the sessions in it
+pub trait Viewable {
+    /// the error given when it is hidden, e.g. "Session not found"
+    const NOT_FOUND: &'static str;
+    /// the error given when it is forbidden
+    const FORBIDDEN: &'static str;
+
+    fn visibility(&self, user_id: i32, connection: &PgConnection) -> Result<Visibility, ApiError>;
+}
+
+/// the guard read endpoints use, giving a 404 if the user cannot know `item` exists and a 403
+/// if they know it exists but cannot see it
+pub fn require_visible<T: Viewable>(
+    item: &T,
+    user_id: i32,
+    connection: &PgConnection,
+) -> Result<(), ApiError> {
+    match item.visibility(user_id, connection)? {
+        Visibility::Visible => Ok(()),
+        Visibility::Forbidden => Err(ApiError::Forbidden(T::FORBIDDEN.to_string())),
+        Visibility::Hidden => Err(ApiError::NotFound(T::NOT_FOUND.to_string())),
+    }
+}
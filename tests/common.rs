@@ -2,15 +2,27 @@
 
 //! This file contains utility functions used by all tests.
 
+use diesel::{Connection, PgConnection};
+use dnd_agenda::mail::outbox::MemoryOutbox;
+use dnd_agenda::mail::queue::OutboxMail;
+use dnd_agenda::mail::Mail;
 use once_cell::sync::OnceCell;
 use rocket::http::{ContentType, Header, Status};
 use rocket::local::{Client, LocalResponse};
+use rocket_contrib::databases::database_config;
 use serde_json::Value;
+use std::env;
+use std::thread;
+use std::time::Duration;
 
 pub const USERNAME: &str = "tester123";
 pub const EMAIL: &str = "tester123@test.com";
 pub const PASSWORD: &str = "blahblahbl";
 
+/// A second user, for checking what one user can see of another's groups and sessions.
+pub const OTHER_USERNAME: &str = "completely_different_username";
+pub const OTHER_EMAIL: &str = "completely_different@email.com";
+
 /// Utility macro for turning `json!` into string.
 #[macro_export]
 macro_rules! json_string {
@@ -19,11 +31,19 @@ macro_rules! json_string {
     };
 }
 
+/// Where the app the API's links point to runs during the tests.
+pub const APP_URL: &str = "http://app.test";
+
 pub type Token = String;
 
 pub fn test_client() -> &'static Client {
     static INSTANCE: OnceCell<Client> = OnceCell::new();
     INSTANCE.get_or_init(|| {
+        // keep mails in memory instead of sending them, so tests can check what would be sent
+        if env::var("MAIL_TRANSPORT").is_err() {
+            env::set_var("MAIL_TRANSPORT", "memory");
+        }
+        env::set_var("APP_URL", APP_URL);
         let rocket = dnd_agenda::rocket();
         Client::new(rocket).expect("valid rocket instance")
     })
@@ -31,12 +51,100 @@ pub fn test_client() -> &'static Client {
 
 /// Retrieve a token registering a user if required.
 pub fn login(client: &Client) -> Token {
-    try_login(client).unwrap_or_else(|| {
-        register(client, USERNAME, EMAIL, PASSWORD);
-        try_login(client).expect("Cannot login")
+    login_as(client, USERNAME, EMAIL)
+}
+
+/// Retrieve a token for the given user, registering them if required.
+pub fn login_as(client: &Client, username: &str, email: &str) -> Token {
+    try_login(client, email).unwrap_or_else(|| {
+        register(client, username, email, PASSWORD);
+        try_login(client, email).expect("Cannot login")
     })
 }
 
+/// Get the id of the user a token belongs to.
+pub fn user_id(client: &Client, token: Token) -> i64 {
+    let response = &mut client
+        .get("/api/v1/users/self")
+        .header(token_header(token))
+        .dispatch();
+
+    response_json_value(response)
+        .get("user")
+        .and_then(|user| user.get("id"))
+        .and_then(|id| id.as_i64())
+        .expect("Cannot extract user id")
+}
+
+/// Create a group with the given privacy, owned by the user the token belongs to. Its name is
+/// made unique so the tests can be run again against the same database.
+pub fn create_group(client: &Client, token: Token, privacy: &str) -> Value {
+    let id = user_id(client, token.clone());
+
+    let response = &mut client
+        .post("/api/v1/groups")
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({
+            "name": format!("{}_group_{}", privacy, chrono::Utc::now().timestamp_nanos()),
+            "description": "a group for testing",
+            "admin": id,
+            "privacy": privacy,
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    response_json_value(response)["group"].clone()
+}
+
+/// Connect to the database the client uses, e.g. to do what a background worker would.
+pub fn connection(client: &Client) -> PgConnection {
+    let database_url = env::var("DATABASE_URL")
+        .ok()
+        .or_else(|| {
+            database_config("dnd_agenda", client.rocket().config())
+                .ok()
+                .map(|database| database.url.to_string())
+        })
+        .expect("no database configured");
+
+    PgConnection::establish(&database_url).expect("cannot connect to the database")
+}
+
+/// The latest mail sent to `to` with the given subject, delivering the queued mails to find it.
+pub fn sent_mail(client: &Client, to: &str, subject: &str) -> Mail {
+    // another test may be delivering the same mails, so give it a moment to finish
+    for _ in 0..10 {
+        OutboxMail::deliver_due(chrono::Utc::now(), &MemoryOutbox, &connection(client))
+            .expect("the queued mails are delivered");
+
+        if let Some(mail) = MemoryOutbox::sent()
+            .into_iter()
+            .rev()
+            .find(|mail| mail.to == to && mail.subject == subject)
+        {
+            return mail;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    panic!("no mail was sent to {} about {}", to, subject)
+}
+
+/// The token in the link in a mail, e.g. the `abc` in `...?token=abc`.
+pub fn mail_token(mail: &Mail) -> String {
+    let start = mail
+        .text
+        .find("token=")
+        .expect("the mail has a link with a token")
+        + "token=".len();
+    mail.text[start..]
+        .chars()
+        .take_while(|character| !character.is_whitespace())
+        .collect()
+}
+
 /// Make an authorization header.
 pub fn token_header(token: Token) -> Header<'static> {
     Header::new("authorization", format!("Token {}", token))
@@ -50,12 +158,12 @@ pub fn response_json_value(response: &mut LocalResponse) -> Value {
 
 // Internal stuff
 
-/// Login as default user returning None if login is not found
-fn try_login(client: &Client) -> Option<Token> {
+/// Login returning None if login is not found
+fn try_login(client: &Client, email: &str) -> Option<Token> {
     let response = &mut client
         .post("/api/v1/users/login")
         .header(ContentType::JSON)
-        .body(json_string!({ "email": EMAIL, "password": PASSWORD }))
+        .body(json_string!({ "email": email, "password": PASSWORD }))
         .dispatch();
 
     if response.status() == Status::Unauthorized {
@@ -0,0 +1,27 @@
+//! Test the shape of error responses
+
+mod common;
+
+use common::*;
+use rocket::http::{ContentType, Status};
+
+#[test]
+/// Errors Rocket raises itself must use the same JSON envelope as the API's own errors.
+fn test_unknown_route_is_json() {
+    let client = test_client();
+    let response = &mut client.get("/api/v1/does_not_exist").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(response_json_value(response)["code"], "not_found");
+}
+
+#[test]
+/// A request without a token must be refused with the `unauthorized` code.
+fn test_missing_token_is_unauthorized() {
+    let client = test_client();
+    let response = &mut client.get("/api/v1/users/self").dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response_json_value(response)["code"], "unauthorized");
+}
@@ -0,0 +1,168 @@
+//! Test joining and leaving groups
+
+mod common;
+
+use common::*;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use serde_json::Value;
+use std::thread;
+
+#[test]
+/// Refusing a request to join is notified as a denied request, not as a removal.
+fn test_denied_request_is_notified() {
+    let client = test_client();
+    let token = login(client);
+    let other_token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+    let other_id = user_id(client, other_token.clone());
+    let group = create_group(client, token.clone(), "listed");
+
+    let response = client
+        .get(format!("/api/v1/groups/{}/join", group["id"]))
+        .header(token_header(other_token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/api/v1/groups/{}/deny/{}", group["id"], other_id))
+        .header(token_header(token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // it is no longer a request, so it cannot be denied again
+    let response = client
+        .get(format!("/api/v1/groups/{}/deny/{}", group["id"], other_id))
+        .header(token_header(token))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let notification = latest_notification(client, other_token);
+    assert_eq!(notification["kind"], "group_request_denied");
+    assert_eq!(notification["groupId"], group["id"]);
+}
+
+#[test]
+/// An invite code is never used more than `max_uses` times, even when redeemed all at once.
+fn test_invite_max_uses_with_concurrent_redeems() {
+    let client = test_client();
+    let token = login(client);
+    let group = create_group(client, token.clone(), "private");
+    let invitees = (0..6)
+        .map(|invitee| {
+            let nanos = chrono::Utc::now().timestamp_nanos();
+            let name = format!("invitee_{}_{}", invitee, nanos);
+            login_as(client, &name, &format!("{}@test.com", name))
+        })
+        .collect::<Vec<_>>();
+
+    let response = &mut client
+        .post(format!("/api/v1/groups/{}/invites", group["id"]))
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({ "max_uses": 2 }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let code = response_json_value(response)["invite"]["code"]
+        .as_str()
+        .expect("the invite has a code")
+        .to_string();
+
+    let redeems = invitees
+        .into_iter()
+        .map(|invitee| {
+            let code = code.clone();
+            thread::spawn(move || {
+                client
+                    .post(format!("/api/v1/groups/invites/{}/join", code))
+                    .header(token_header(invitee))
+                    .dispatch()
+                    .status()
+            })
+        })
+        .collect::<Vec<_>>();
+    let statuses = redeems
+        .into_iter()
+        .map(|redeem| redeem.join().expect("the redeem finishes"))
+        .collect::<Vec<_>>();
+
+    let joined = statuses.iter().filter(|status| **status == Status::Ok);
+    assert_eq!(joined.count(), 2);
+    let refused = statuses.iter().filter(|status| **status == Status::Gone);
+    assert_eq!(refused.count(), 4);
+}
+
+#[test]
+/// Joining a group you are already in, or asking to again, changes nothing.
+fn test_join_group_twice() {
+    let client = test_client();
+    let token = login(client);
+    let other_token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+
+    for privacy in &["public", "listed", "private"] {
+        let group = create_group(client, token.clone(), privacy);
+
+        let response = &mut join_group(client, &group, token.clone());
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response_json_value(response)["message"],
+            "already a member of this group"
+        );
+    }
+
+    for privacy in &["public", "listed"] {
+        let group = create_group(client, token.clone(), privacy);
+
+        let response = join_group(client, &group, other_token.clone());
+        assert_eq!(response.status(), Status::Ok);
+        let response = join_group(client, &group, other_token.clone());
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
+
+#[test]
+/// A group's admin has to be an existing user.
+fn test_create_group_with_missing_admin() {
+    let client = test_client();
+
+    let response = &mut client
+        .post("/api/v1/groups")
+        .header(ContentType::JSON)
+        .header(token_header(login(client)))
+        .body(json_string!({
+            "name": format!("group_{}", chrono::Utc::now().timestamp_nanos()),
+            "description": "a group nobody runs",
+            "admin": -1,
+            "privacy": "listed",
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(
+        response_json_value(response)["errors"]["admin"][0],
+        "can only be a valid (existing) user"
+    );
+}
+
+// Utility functions
+
+fn join_group<'c>(
+    client: &'c Client,
+    group: &Value,
+    token: Token,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .get(format!("/api/v1/groups/{}/join", group["id"]))
+        .header(token_header(token))
+        .dispatch()
+}
+
+/// The most recent notification of the user the token belongs to.
+fn latest_notification(client: &Client, token: Token) -> Value {
+    let response = &mut client
+        .get("/api/v1/users/self/notifications")
+        .header(token_header(token))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    response_json_value(response)["notifications"][0].clone()
+}
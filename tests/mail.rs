@@ -0,0 +1,20 @@
+//! Test delivering queued mails
+
+mod common;
+
+use chrono::Utc;
+use common::*;
+
+#[test]
+/// Mails are queued, then delivered through the configured transport by the mail worker.
+fn test_verification_mail_is_delivered() {
+    let client = test_client();
+    let nanos = Utc::now().timestamp_nanos();
+    let email = format!("mail_{}@test.com", nanos);
+    register(client, &format!("mail_{}", nanos), &email, PASSWORD);
+
+    let mail = sent_mail(client, &email, "Verify your DnDearAll email");
+    assert!(mail
+        .text
+        .contains(&format!("{}/#/verify-email?token=", APP_URL)));
+}
@@ -0,0 +1,144 @@
+//! Test closing availability polls
+
+mod common;
+
+use common::*;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use serde_json::Value;
+
+#[test]
+/// A poll can only be turned into a session once.
+fn test_close_poll_twice() {
+    let client = test_client();
+    let token = login(client);
+    let title = format!("poll-{}", chrono::Utc::now().timestamp_nanos());
+    let poll = create_poll(client, &title);
+
+    let response = &mut close_poll(client, &poll, option_id(&poll));
+    assert_eq!(response.status(), Status::Created);
+    let session = response_json_value(response)["session"].clone();
+    assert_eq!(session["title"], title.as_str());
+
+    let response = &mut close_poll(client, &poll, option_id(&poll));
+    assert_eq!(response.status(), Status::Conflict);
+    assert_eq!(response_json_value(response)["code"], "conflict");
+
+    let response = &mut client
+        .get(format!("/api/v1/polls/{}", poll["id"]))
+        .header(token_header(token))
+        .dispatch();
+    assert_eq!(
+        response_json_value(response)["poll"]["sessionId"],
+        session["id"]
+    );
+}
+
+#[test]
+/// A close that fails must leave the poll open, without creating a session.
+fn test_failed_close_leaves_poll_open() {
+    let client = test_client();
+    let token = login(client);
+    let title = format!("poll-{}", chrono::Utc::now().timestamp_nanos());
+    let poll = create_poll(client, &title);
+
+    // not an option of this poll
+    let response = &mut close_poll(client, &poll, -1);
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = &mut client
+        .get(format!("/api/v1/polls/{}", poll["id"]))
+        .header(token_header(token))
+        .dispatch();
+    assert_eq!(
+        response_json_value(response)["poll"]["sessionId"],
+        Value::Null
+    );
+
+    let response = close_poll(client, &poll, option_id(&poll));
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[test]
+/// Polls with the same title become sessions with different slugs.
+fn test_closed_polls_get_unique_slugs() {
+    let client = test_client();
+    let title = format!("poll-{}", chrono::Utc::now().timestamp_nanos());
+    let first = create_poll(client, &title);
+    let second = create_poll(client, &title);
+
+    let response = &mut close_poll(client, &first, option_id(&first));
+    assert_eq!(response.status(), Status::Created);
+    let first_slug = response_json_value(response)["session"]["slug"].clone();
+
+    let response = &mut close_poll(client, &second, option_id(&second));
+    assert_eq!(response.status(), Status::Created);
+    let second_slug = response_json_value(response)["session"]["slug"].clone();
+
+    assert_eq!(first_slug, title.as_str());
+    assert_eq!(second_slug, format!("{}-2", title).as_str());
+}
+
+#[test]
+/// A poll has to be for an existing group.
+fn test_create_poll_with_missing_group() {
+    let client = test_client();
+
+    let response = &mut client
+        .post("/api/v1/polls")
+        .header(ContentType::JSON)
+        .header(token_header(login(client)))
+        .body(json_string!({
+            "title": format!("poll-{}", chrono::Utc::now().timestamp_nanos()),
+            "description": "a poll for no group",
+            "colour": "red",
+            "group": -1,
+            "options": ["2030-01-01T19:00:00.000Z"],
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(
+        response_json_value(response)["errors"]["group"][0],
+        "Group can only be an existing group"
+    );
+}
+
+// Utility functions
+
+/// Create a poll with the given title, in a new group owned by the default user.
+fn create_poll(client: &Client, title: &str) -> Value {
+    let token = login(client);
+    let group = create_group(client, token.clone(), "listed");
+
+    let response = &mut client
+        .post("/api/v1/polls")
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({
+            "title": title,
+            "description": "a poll for testing closing it",
+            "colour": "red",
+            "group": group["id"],
+            "options": ["2030-01-01T19:00:00.000Z", "2030-01-08T19:00:00.000Z"],
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    response_json_value(response)["poll"].clone()
+}
+
+fn option_id(poll: &Value) -> i64 {
+    poll["options"][0]["id"].as_i64().expect("poll has options")
+}
+
+fn close_poll<'c>(
+    client: &'c Client,
+    poll: &Value,
+    option_id: i64,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .post(format!("/api/v1/polls/{}/close/{}", poll["id"], option_id))
+        .header(token_header(login(client)))
+        .dispatch()
+}
@@ -0,0 +1,506 @@
+//! Test sessions and their occurrences
+
+mod common;
+
+use common::*;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use serde_json::Value;
+
+#[test]
+/// An RSVP to one occurrence only counts towards that occurrence's headcount.
+fn test_rsvp_to_single_occurrence() {
+    let client = test_client();
+    let token = login(client);
+    let session = create_session(
+        client,
+        "2030-01-01T19:00:00.000+00:00",
+        Some("RRULE:FREQ=WEEKLY;COUNT=3"),
+    );
+
+    let response = rsvp_to_occurrence(client, &session, "2030-01-08T19:00:00Z", "going");
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut client
+        .get(format!("/api/v1/sessions/{}/headcount", session["id"]))
+        .header(token_header(token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response_json_value(response)["headcount"]["going"], 0);
+
+    let response = &mut occurrence_headcount(client, &session, "2030-01-08T19:00:00Z");
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response_json_value(response)["headcount"]["going"], 1);
+
+    let response = &mut occurrence_headcount(client, &session, "2030-01-15T19:00:00Z");
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response_json_value(response)["headcount"]["going"], 0);
+}
+
+#[test]
+/// Only occurrences of a recurring session can be RSVP'd to.
+fn test_rsvp_to_missing_occurrence() {
+    let client = test_client();
+    let recurring = create_session(
+        client,
+        "2030-01-01T19:00:00.000+00:00",
+        Some("RRULE:FREQ=WEEKLY;COUNT=3"),
+    );
+    let single = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+
+    let response = rsvp_to_occurrence(client, &recurring, "2030-01-09T19:00:00Z", "going");
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = rsvp_to_occurrence(client, &single, "2030-01-01T19:00:00Z", "going");
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+/// Moving the series moves the RSVPs given for its occurrences with it.
+fn test_rsvp_moves_with_series() {
+    let client = test_client();
+    let token = login(client);
+    let session = create_session(
+        client,
+        "2030-01-01T19:00:00.000+00:00",
+        Some("RRULE:FREQ=WEEKLY;COUNT=3"),
+    );
+
+    let response = rsvp_to_occurrence(client, &session, "2030-01-08T19:00:00Z", "going");
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .patch(format!("/api/v1/sessions/{}", session["id"]))
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({ "session_date": "2030-01-02T19:00:00.000+00:00" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut occurrence_headcount(client, &session, "2030-01-09T19:00:00Z");
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response_json_value(response)["headcount"]["going"], 1);
+}
+
+#[test]
+/// Rescheduling a session onto another of its members' sessions is reported as a conflict.
+fn test_reschedule_conflicts() {
+    let client = test_client();
+    let token = login(client);
+    let existing = create_session(client, "2031-03-01T10:00:00.000+00:00", None);
+    let session = create_session(client, "2031-04-01T10:00:00.000+00:00", None);
+
+    let response = &mut reschedule(client, &session, "2031-03-01T10:30:00.000+00:00", true);
+    assert_eq!(response.status(), Status::Conflict);
+    assert_eq!(response_json_value(response)["code"], "schedule_conflict");
+
+    // the rejected change is not saved
+    let response = &mut client
+        .get(format!(
+            "/api/v1/sessions/{}",
+            session["slug"].as_str().unwrap()
+        ))
+        .header(token_header(token))
+        .dispatch();
+    assert_eq!(
+        response_json_value(response)["session"]["sessionDate"],
+        session["sessionDate"]
+    );
+
+    let response = &mut reschedule(client, &session, "2031-03-01T10:30:00.000+00:00", false);
+    assert_eq!(response.status(), Status::Ok);
+    let conflicts = response_json_value(response)["conflicts"].clone();
+    assert!(conflicts
+        .as_array()
+        .expect("conflicts are returned")
+        .iter()
+        .any(|conflict| conflict["id"] == existing["id"]));
+}
+
+#[test]
+/// The headcount counts the session's members and its guests together.
+fn test_headcount_counts_members_and_guests() {
+    let client = test_client();
+    let token = login(client);
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    guest_token(client, &session);
+
+    let response = &mut client
+        .get(format!("/api/v1/sessions/{}/headcount", session["id"]))
+        .header(token_header(token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let headcount = response_json_value(response)["headcount"].clone();
+    assert_eq!(headcount["members"].as_array().map(Vec::len), Some(1));
+    assert_eq!(headcount["guests"], 1);
+    assert_eq!(headcount["noResponse"], 2);
+}
+
+#[test]
+/// Guests can turn their reminders off from their guest link.
+fn test_guest_turns_off_reminders() {
+    let client = test_client();
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    let guest_token = guest_token(client, &session);
+
+    let response = &mut client
+        .put(format!(
+            "/api/v1/sessions/{}/guest/{}/reminders",
+            session["id"], guest_token
+        ))
+        .header(ContentType::JSON)
+        .body(json_string!({ "reminders_enabled": false }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response_json_value(response)["guest"]["reminders_enabled"],
+        false
+    );
+
+    let response = client
+        .put(format!(
+            "/api/v1/sessions/{}/guest/not-a-token/reminders",
+            session["id"]
+        ))
+        .header(ContentType::JSON)
+        .body(json_string!({ "reminders_enabled": false }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+/// Rotating a guest's link stops the old link working and gives them a new one.
+fn test_rotate_guest_link() {
+    let client = test_client();
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    let old_token = guest_token(client, &session);
+
+    let response = &mut client
+        .post(format!(
+            "/api/v1/sessions/{}/guests/{}/link",
+            session["id"],
+            guest_id(client, &session)
+        ))
+        .header(token_header(login(client)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let new_token = link_token(&response_json_value(response)["guest_link"]);
+
+    assert_eq!(
+        view_as_guest(client, &session, &old_token).status(),
+        Status::Unauthorized
+    );
+    assert_eq!(
+        view_as_guest(client, &session, &new_token).status(),
+        Status::Ok
+    );
+}
+
+#[test]
+/// A revoked guest link stops working straight away.
+fn test_revoke_guest_link() {
+    let client = test_client();
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    let guest_token = guest_token(client, &session);
+    assert_eq!(
+        view_as_guest(client, &session, &guest_token).status(),
+        Status::Ok
+    );
+
+    let response = client
+        .delete(format!(
+            "/api/v1/sessions/{}/guests/{}/link",
+            session["id"],
+            guest_id(client, &session)
+        ))
+        .header(token_header(login(client)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        view_as_guest(client, &session, &guest_token).status(),
+        Status::Unauthorized
+    );
+}
+
+#[test]
+/// A guest link stops working once it has expired.
+fn test_expired_guest_link() {
+    let client = test_client();
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    let guest_token = guest_token(client, &session);
+
+    diesel::sql_query(
+        "UPDATE sessions_guests SET link_expires_at = NOW() - INTERVAL '1 day' \
+         WHERE session_id = $1",
+    )
+    .bind::<Integer, _>(session["id"].as_i64().expect("session has an id") as i32)
+    .execute(&connection(client))
+    .expect("the guest link is expired");
+
+    assert_eq!(
+        view_as_guest(client, &session, &guest_token).status(),
+        Status::Unauthorized
+    );
+}
+
+#[test]
+/// Group schedulers can run any session in the group, but not take it over from its DM.
+fn test_group_scheduler_cannot_hand_session_over() {
+    let client = test_client();
+    let token = login(client);
+    let other_token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+    let other_id = user_id(client, other_token.clone());
+    let session = create_session(client, "2030-01-01T19:00:00.000+00:00", None);
+    let group_id = &session["group"]["id"];
+
+    let response = client
+        .get(format!("/api/v1/groups/{}/join", group_id))
+        .header(token_header(other_token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/api/v1/groups/{}/accept/{}", group_id, other_id))
+        .header(token_header(token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .put(format!("/api/v1/groups/{}/role/{}", group_id, other_id))
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({ "role": "scheduler" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .patch(format!("/api/v1/sessions/{}", session["id"]))
+        .header(ContentType::JSON)
+        .header(token_header(other_token.clone()))
+        .body(json_string!({ "description": "rescheduled by the group's scheduler" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .patch(format!("/api/v1/sessions/{}/dm", session["id"]))
+        .header(ContentType::JSON)
+        .header(token_header(other_token))
+        .body(json_string!({ "dm": other_id }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+/// A session's DM and group have to exist, both are reported at once if they do not.
+fn test_create_session_with_missing_ids() {
+    let client = test_client();
+
+    let response = &mut client
+        .post("/api/v1/sessions")
+        .header(ContentType::JSON)
+        .header(token_header(login(client)))
+        .body(json_string!({
+            "title": format!("session-{}", chrono::Utc::now().timestamp_nanos()),
+            "description": "a session nobody can run",
+            "dm": -1,
+            "session_date": "2030-01-01T19:00:00.000+00:00",
+            "colour": "red",
+            "group": -1,
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let errors = response_json_value(response)["errors"].clone();
+    assert_eq!(errors["dm"][0], "can only be a valid (existing) user");
+    assert_eq!(errors["group"][0], "Group can only be an existing group");
+}
+
+#[test]
+/// Pages are counted in series: a recurring session fills one place on its page with all of its
+/// occurrences.
+fn test_session_page_totals() {
+    let client = test_client();
+    let name = format!("pager_{}", chrono::Utc::now().timestamp_nanos());
+    let token = login_as(client, &name, &format!("{}@test.com", name));
+    let recurring = create_session_as(
+        client,
+        token.clone(),
+        "2030-01-01T19:00:00.000+00:00",
+        Some("RRULE:FREQ=WEEKLY;COUNT=3"),
+    );
+    create_session_as(client, token.clone(), "2030-02-01T19:00:00.000+00:00", None);
+
+    let response = &mut sessions_page(client, token.clone(), 1);
+    assert_eq!(response.status(), Status::Ok);
+    let page = response_json_value(response);
+    assert_eq!(page["sessions"].as_array().map(Vec::len), Some(3));
+    assert_eq!(page["sessionsPagesCount"], 2);
+
+    let response = &mut sessions_page(client, token.clone(), 2);
+    assert_eq!(response.status(), Status::Ok);
+    let page = response_json_value(response);
+    assert_eq!(page["sessions"].as_array().map(Vec::len), Some(1));
+    assert_eq!(page["sessionsPagesCount"], 2);
+
+    // cancelling an occurrence shortens its page, without changing the pages
+    let response = client
+        .delete(format!(
+            "/api/v1/sessions/{}/occurrences/2030-01-08T19:00:00Z",
+            recurring["id"]
+        ))
+        .header(token_header(token.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut sessions_page(client, token, 1);
+    assert_eq!(response.status(), Status::Ok);
+    let page = response_json_value(response);
+    assert_eq!(page["sessions"].as_array().map(Vec::len), Some(2));
+    assert_eq!(page["sessionsPagesCount"], 2);
+}
+
+// Utility functions
+
+/// Create a session at the given date, in a new group owned by the default user.
+fn create_session(client: &Client, session_date: &str, recurrence: Option<&str>) -> Value {
+    create_session_as(client, login(client), session_date, recurrence)
+}
+
+/// Create a session at the given date, in a new group owned by the user the token belongs to.
+fn create_session_as(
+    client: &Client,
+    token: Token,
+    session_date: &str,
+    recurrence: Option<&str>,
+) -> Value {
+    let id = user_id(client, token.clone());
+    let group = create_group(client, token.clone(), "listed");
+
+    let response = &mut client
+        .post("/api/v1/sessions")
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({
+            "title": format!("session-{}", chrono::Utc::now().timestamp_nanos()),
+            "description": "a session for testing its occurrences",
+            "dm": id,
+            "session_date": session_date,
+            "colour": "red",
+            "group": group["id"],
+            "recurrence": recurrence,
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    response_json_value(response)["session"].clone()
+}
+
+fn rsvp_to_occurrence<'c>(
+    client: &'c Client,
+    session: &Value,
+    occurrence_date: &str,
+    rsvp: &str,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .put(format!(
+            "/api/v1/sessions/{}/occurrences/{}/rsvp",
+            session["id"], occurrence_date
+        ))
+        .header(ContentType::JSON)
+        .header(token_header(login(client)))
+        .body(json_string!({ "rsvp": rsvp }))
+        .dispatch()
+}
+
+fn occurrence_headcount<'c>(
+    client: &'c Client,
+    session: &Value,
+    occurrence_date: &str,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .get(format!(
+            "/api/v1/sessions/{}/occurrences/{}/headcount",
+            session["id"], occurrence_date
+        ))
+        .header(token_header(login(client)))
+        .dispatch()
+}
+
+fn reschedule<'c>(
+    client: &'c Client,
+    session: &Value,
+    session_date: &str,
+    reject_conflicts: bool,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .patch(format!(
+            "/api/v1/sessions/{}?reject_conflicts={}",
+            session["id"], reject_conflicts
+        ))
+        .header(ContentType::JSON)
+        .header(token_header(login(client)))
+        .body(json_string!({ "session_date": session_date }))
+        .dispatch()
+}
+
+/// Invite a guest to the session, returning the token from their guest link.
+fn guest_token(client: &Client, session: &Value) -> String {
+    let response = &mut client
+        .get(format!(
+            "/api/v1/sessions/{}/guest_link/guest?guest_email=guest@example.com",
+            session["id"]
+        ))
+        .header(token_header(login(client)))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    link_token(&response_json_value(response)["guest_link"])
+}
+
+/// The token in a guest link, e.g. the `abc` in `...?guest=abc`.
+fn link_token(guest_link: &Value) -> String {
+    let guest_link = guest_link.as_str().expect("a guest link is returned");
+    guest_link[guest_link.find("guest=").expect("the link has a token") + "guest=".len()..]
+        .to_string()
+}
+
+/// The id of the session's first guest.
+fn guest_id(client: &Client, session: &Value) -> i64 {
+    let response = &mut client
+        .get(format!("/api/v1/sessions/{}/guests/links", session["id"]))
+        .header(token_header(login(client)))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    response_json_value(response)["guests"][0]["guest_id"]
+        .as_i64()
+        .expect("the session has a guest")
+}
+
+fn view_as_guest<'c>(
+    client: &'c Client,
+    session: &Value,
+    guest_token: &str,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .get(format!(
+            "/api/v1/sessions/{}/guest/{}",
+            session["id"], guest_token
+        ))
+        .dispatch()
+}
+
+/// A page of the user's sessions, one series per page.
+fn sessions_page<'c>(
+    client: &'c Client,
+    token: Token,
+    page: i64,
+) -> rocket::local::LocalResponse<'c> {
+    client
+        .get(format!("/api/v1/sessions?limit=1&page={}", page))
+        .header(token_header(token))
+        .dispatch()
+}
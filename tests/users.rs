@@ -3,8 +3,10 @@
 mod common;
 
 use common::*;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
 use rocket::http::{ContentType, Status};
-use rocket::local::LocalResponse;
+use rocket::local::{Client, LocalResponse};
 
 #[test]
 /// Register new user, handling repeated registration as well.
@@ -267,8 +269,171 @@ fn test_get_self_no_token() {
     check_unauthorised_error(response);
 }
 
+#[test]
+/// Refresh tokens can be swapped once, reusing one logs that login session out.
+fn test_refresh_token_rotation() {
+    let client = test_client();
+    login(client); // make sure the user exists
+    let (token, refresh_token) = login_with_refresh_token(client);
+
+    let response = &mut client
+        .post("/api/v1/users/token/refresh")
+        .header(ContentType::JSON)
+        .body(json_string!({ "refresh_token": refresh_token }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let value = response_json_value(response);
+    let user = value.get("user").expect("must have a 'user' field");
+    assert!(user.get("token").is_some());
+    assert_ne!(
+        user.get("refresh_token").and_then(|token| token.as_str()),
+        Some(refresh_token.as_str())
+    );
+
+    let response = &mut client
+        .post("/api/v1/users/token/refresh")
+        .header(ContentType::JSON)
+        .body(json_string!({ "refresh_token": refresh_token }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = &mut client
+        .get("/api/v1/users/self")
+        .header(token_header(token))
+        .dispatch();
+
+    check_unauthorised_error(response);
+}
+
+#[test]
+/// Access tokens stop working after logging out.
+fn test_logout() {
+    let client = test_client();
+    login(client); // make sure the user exists
+    let (token, _) = login_with_refresh_token(client);
+
+    let response = client
+        .post("/api/v1/users/logout")
+        .header(token_header(token.clone()))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut client
+        .get("/api/v1/users/self")
+        .header(token_header(token))
+        .dispatch();
+
+    check_unauthorised_error(response);
+}
+
+#[test]
+/// Resetting the password logs the user out everywhere, and the new password works.
+fn test_password_reset_revokes_sessions() {
+    let client = test_client();
+    let email = register_unique(client);
+    let token = login_as_with(client, &email, PASSWORD).expect("can log in");
+
+    let response = confirm_password_reset(client, &password_reset_token(client, &email));
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut client
+        .get("/api/v1/users/self")
+        .header(token_header(token))
+        .dispatch();
+    check_unauthorised_error(response);
+
+    assert!(login_as_with(client, &email, PASSWORD).is_none());
+    assert!(login_as_with(client, &email, NEW_PASSWORD).is_some());
+}
+
+#[test]
+/// A password reset link only works once.
+fn test_password_reset_token_is_single_use() {
+    let client = test_client();
+    let email = register_unique(client);
+    let reset_token = password_reset_token(client, &email);
+
+    let response = confirm_password_reset(client, &reset_token);
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = &mut confirm_password_reset(client, &reset_token);
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert!(response_json_value(response)["errors"]["token"].is_array());
+}
+
+#[test]
+/// A password reset link stops working once it expires, leaving the password as it was.
+fn test_expired_password_reset() {
+    let client = test_client();
+    let email = register_unique(client);
+    let token = login_as_with(client, &email, PASSWORD).expect("can log in");
+    let reset_token = password_reset_token(client, &email);
+
+    diesel::sql_query(
+        "UPDATE password_resets SET expires_at = NOW() - INTERVAL '1 minute' WHERE user_id = $1",
+    )
+    .bind::<Integer, _>(user_id(client, token) as i32)
+    .execute(&connection(client))
+    .expect("the reset is expired");
+
+    let response = confirm_password_reset(client, &reset_token);
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    assert!(login_as_with(client, &email, PASSWORD).is_some());
+}
+
 // Utility functions
 
+const NEW_PASSWORD: &str = "a new password";
+
+/// Register a user no other test uses, returning their email.
+fn register_unique(client: &Client) -> String {
+    let nanos = chrono::Utc::now().timestamp_nanos();
+    let email = format!("reset_{}@test.com", nanos);
+    register(client, &format!("reset_{}", nanos), &email, PASSWORD);
+    email
+}
+
+/// Log in with the given password, returning None if it is wrong.
+fn login_as_with(client: &Client, email: &str, password: &str) -> Option<Token> {
+    let response = &mut client
+        .post("/api/v1/users/login")
+        .header(ContentType::JSON)
+        .body(json_string!({ "email": email, "password": password }))
+        .dispatch();
+
+    if response.status() != Status::Ok {
+        return None;
+    }
+
+    response_json_value(response)["user"]["token"]
+        .as_str()
+        .map(String::from)
+}
+
+/// Ask for a password reset link for the email, returning the token from it.
+fn password_reset_token(client: &Client, email: &str) -> String {
+    let response = client
+        .post("/api/v1/users/password-reset")
+        .header(ContentType::JSON)
+        .body(json_string!({ "email": email }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+
+    mail_token(&sent_mail(client, email, "Reset your DnDearAll password"))
+}
+
+fn confirm_password_reset<'c>(client: &'c Client, reset_token: &str) -> LocalResponse<'c> {
+    client
+        .post("/api/v1/users/password-reset/confirm")
+        .header(ContentType::JSON)
+        .body(json_string!({ "token": reset_token, "password": NEW_PASSWORD }))
+        .dispatch()
+}
+
 /// Assert that body contains "user" response with expected fields.
 fn check_user_response(response: &mut LocalResponse) {
     let value = response_json_value(response);
@@ -305,3 +470,25 @@ fn check_unauthorised_error(response: &mut LocalResponse) {
 
     assert_eq!(token_error, Some("unauthorised"));
 }
+
+/// Log in again, returning the new login session's access and refresh tokens.
+fn login_with_refresh_token(client: &Client) -> (Token, String) {
+    let response = &mut client
+        .post("/api/v1/users/login")
+        .header(ContentType::JSON)
+        .body(json_string!({"email": EMAIL, "password": PASSWORD}))
+        .dispatch();
+
+    let value = response_json_value(response);
+    let user = value.get("user").expect("must have a 'user' field");
+    let token = user
+        .get("token")
+        .and_then(|token| token.as_str())
+        .expect("user has token");
+    let refresh_token = user
+        .get("refresh_token")
+        .and_then(|token| token.as_str())
+        .expect("user has refresh token");
+
+    (token.to_string(), refresh_token.to_string())
+}
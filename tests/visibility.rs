@@ -0,0 +1,189 @@
+//! Test who can read groups and sessions
+
+mod common;
+
+use common::*;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use serde_json::Value;
+
+#[test]
+/// A private group, and the sessions in it, must look like they do not exist to non-members.
+fn test_private_group_is_hidden_from_non_members() {
+    let client = test_client();
+    let (group, session) = group_with_session(client, "private");
+    let token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+
+    let group_slug = group["slug"].as_str().expect("group has slug");
+    let session_slug = session["slug"].as_str().expect("session has slug");
+    let session_id = session["id"].as_i64().expect("session has id");
+
+    for url in &[
+        format!("/api/v1/groups/{}", group_slug),
+        format!("/api/v1/sessions/{}", session_slug),
+        format!("/api/v1/sessions/{}/users", session_id),
+        format!("/api/v1/sessions/{}/guests", session_id),
+    ] {
+        let response = &mut client
+            .get(url)
+            .header(token_header(token.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound, "GET {}", url);
+        assert_eq!(
+            response_json_value(response)["code"],
+            "not_found",
+            "GET {}",
+            url
+        );
+    }
+
+    let response = &mut client
+        .get(format!(
+            "/api/v1/groups?global_search=true&name={}",
+            group["name"].as_str().expect("group has name")
+        ))
+        .header(token_header(token))
+        .dispatch();
+
+    let value = response_json_value(response);
+    assert!(value
+        .get("groups")
+        .and_then(|groups| groups.as_array())
+        .expect("must have a 'groups' array")
+        .iter()
+        .all(|found| found["id"] != group["id"]));
+}
+
+#[test]
+/// Anyone can see a listed group, but only its members can see its sessions.
+fn test_listed_group_sessions_are_forbidden_to_non_members() {
+    let client = test_client();
+    let (group, session) = group_with_session(client, "listed");
+    let token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+
+    let response = &mut client
+        .get(format!(
+            "/api/v1/groups/{}",
+            group["slug"].as_str().expect("group has slug")
+        ))
+        .header(token_header(token.clone()))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let value = response_json_value(response);
+    assert_eq!(value["group"]["sessions"].as_array().map(Vec::len), Some(0));
+
+    let session_slug = session["slug"].as_str().expect("session has slug");
+    let session_id = session["id"].as_i64().expect("session has id");
+
+    for url in &[
+        format!("/api/v1/sessions/{}", session_slug),
+        format!("/api/v1/sessions/{}/ics", session_slug),
+        format!("/api/v1/sessions/{}/users", session_id),
+        format!("/api/v1/sessions/{}/guests", session_id),
+    ] {
+        let response = &mut client
+            .get(url)
+            .header(token_header(token.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden, "GET {}", url);
+        assert_eq!(
+            response_json_value(response)["code"],
+            "forbidden",
+            "GET {}",
+            url
+        );
+    }
+}
+
+#[test]
+/// The sessions in a public group can be seen by anyone.
+fn test_public_group_sessions_are_visible_to_non_members() {
+    let client = test_client();
+    let (_group, session) = group_with_session(client, "public");
+    let token = login_as(client, OTHER_USERNAME, OTHER_EMAIL);
+
+    let response = client
+        .get(format!(
+            "/api/v1/sessions/{}",
+            session["slug"].as_str().expect("session has slug")
+        ))
+        .header(token_header(token))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+/// Members can still see everything in a private group.
+fn test_private_group_is_visible_to_members() {
+    let client = test_client();
+    let (group, session) = group_with_session(client, "private");
+    let token = login(client);
+
+    let response = &mut client
+        .get(format!(
+            "/api/v1/groups/{}",
+            group["slug"].as_str().expect("group has slug")
+        ))
+        .header(token_header(token.clone()))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let value = response_json_value(response);
+    assert_eq!(value["group"]["sessions"].as_array().map(Vec::len), Some(1));
+
+    let response = client
+        .get(format!(
+            "/api/v1/sessions/{}/users",
+            session["id"].as_i64().expect("session has id")
+        ))
+        .header(token_header(token))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+// Utility functions
+
+/// Create a group with the given privacy, owned by the default user, and a session in it.
+/// Names are made unique so the tests can be run again against the same database.
+fn group_with_session(client: &Client, privacy: &str) -> (Value, Value) {
+    let token = login(client);
+    let id = user_id(client, token.clone());
+    let name = format!("{}_group_{}", privacy, chrono::Utc::now().timestamp_nanos());
+
+    let response = &mut client
+        .post("/api/v1/groups")
+        .header(ContentType::JSON)
+        .header(token_header(token.clone()))
+        .body(json_string!({
+            "name": name,
+            "description": "a group for testing who can see it",
+            "admin": id,
+            "privacy": privacy,
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    let group = response_json_value(response)["group"].clone();
+
+    let response = &mut client
+        .post("/api/v1/sessions")
+        .header(ContentType::JSON)
+        .header(token_header(token))
+        .body(json_string!({
+            "title": format!("session in {}", name),
+            "description": "a session for testing who can see it",
+            "dm": id,
+            "session_date": "2030-01-01T19:00:00.000+00:00",
+            "colour": "red",
+            "group": group["id"],
+        }))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    let session = response_json_value(response)["session"].clone();
+
+    (group, session)
+}